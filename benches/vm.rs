@@ -0,0 +1,61 @@
+//! Compares the tree-walking evaluator with the bytecode VM, run from an 'EvalContext'
+//! and as a 'CompiledExpr', on the same formulas.
+//!
+//!     $ cargo bench --bench vm
+
+extern crate simple_lexer;
+
+use simple_lexer::*;
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const FORMULAS: &[(&str, &str, usize)] = &[
+    ("formula", "(x * x + 2 * x * PI - 3) / (x + 1) ^ 2", 200_000),
+    (
+        "loop",
+        "total = 0\nfor i in 0..1000 { total += i * x / (i + 1) }\ntotal",
+        200,
+    ),
+];
+
+fn time(runs: usize, mut run: impl FnMut(f64)) -> Duration {
+    let start = Instant::now();
+    for run_index in 0..runs {
+        run(run_index as f64);
+    }
+    start.elapsed()
+}
+
+fn main() {
+    for (name, formula, runs) in FORMULAS {
+        let tokens = Lexer::get_tokens(formula).unwrap();
+        let root = Parser::new(&tokens).parse().unwrap();
+        let mut ctx = EvalContext::populated();
+        let program = ctx.compile(&root).unwrap();
+
+        let walker = time(*runs, |x| {
+            ctx.bind("x", x);
+            black_box(ctx.eval(&root).unwrap());
+        });
+        let machine = time(*runs, |x| {
+            ctx.bind("x", x);
+            black_box(ctx.run(&program).unwrap());
+        });
+        let expr = CompiledExpr::compile(formula).unwrap();
+        let mut bindings = HashMap::new();
+        let compiled = time(*runs, |x| {
+            bindings.insert("x", x);
+            black_box(expr.eval(&bindings).unwrap());
+        });
+
+        println!(
+            "{:8} tree-walker {:>10.2?}  vm {:>10.2?}  compiled expr {:>10.2?}  speedup {:.1}x",
+            name,
+            walker,
+            machine,
+            compiled,
+            walker.as_secs_f64() / compiled.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use std::f64::consts::PI;
+
+/// The functions provided by the language.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Builtin {
+    Len,
+    Sum,
+    Avg,
+    Min,
+    Max,
+    Map,
+    Approx,
+    Sqrt,
+    Exp,
+    Ln,
+    Sin,
+    Cos,
+    Tan,
+    Abs,
+    Arg,
+    Conj,
+}
+
+/// The constants provided by the language. They can't be reassigned.
+pub const CONSTANTS: &[(&str, f64)] = &[
+    ("PI", std::f64::consts::PI),
+    ("E", std::f64::consts::E),
+    ("TAU", std::f64::consts::TAU),
+    ("INF", f64::INFINITY),
+    ("NAN", f64::NAN),
+];
+
+const BUILTINS: &[(&str, Builtin)] = &[
+    ("len", Builtin::Len),
+    ("sum", Builtin::Sum),
+    ("avg", Builtin::Avg),
+    ("min", Builtin::Min),
+    ("max", Builtin::Max),
+    ("map", Builtin::Map),
+    ("approx", Builtin::Approx),
+    ("sqrt", Builtin::Sqrt),
+    ("exp", Builtin::Exp),
+    ("ln", Builtin::Ln),
+    ("sin", Builtin::Sin),
+    ("cos", Builtin::Cos),
+    ("tan", Builtin::Tan),
+    ("abs", Builtin::Abs),
+    ("arg", Builtin::Arg),
+    ("conj", Builtin::Conj),
+];
+
+impl Builtin {
+    pub fn from_name(name: &str) -> Option<Self> {
+        BUILTINS
+            .iter()
+            .find(|(builtin_name, _)| *builtin_name == name)
+            .map(|(_, builtin)| *builtin)
+    }
+
+    pub fn name(self) -> &'static str {
+        BUILTINS
+            .iter()
+            .find(|(_, builtin)| *builtin == self)
+            .map(|(name, _)| *name)
+            .unwrap()
+    }
+
+    /// The function of a built-in taking a number and returning a number.
+    pub(crate) fn math(self) -> Option<fn(f64) -> f64> {
+        Some(match self {
+            Builtin::Sqrt => f64::sqrt,
+            Builtin::Exp => f64::exp,
+            Builtin::Ln => f64::ln,
+            Builtin::Sin => f64::sin,
+            Builtin::Cos => f64::cos,
+            Builtin::Tan => f64::tan,
+            Builtin::Abs => f64::abs,
+            // The argument of a real number, 0 or PI by its sign.
+            Builtin::Arg => |x| match x {
+                x if x < 0.0 => PI,
+                x if x >= 0.0 => 0.0,
+                _ => f64::NAN,
+            },
+            Builtin::Conj => |x| x,
+            _ => return None,
+        })
+    }
+
+    /// The derivative of the function of a math built-in.
+    pub(crate) fn math_derivative(self) -> Option<fn(f64) -> f64> {
+        Some(match self {
+            Builtin::Sqrt => |x| 0.5 / x.sqrt(),
+            Builtin::Exp => f64::exp,
+            Builtin::Ln => |x| 1.0 / x,
+            Builtin::Sin => f64::cos,
+            Builtin::Cos => |x| -x.sin(),
+            Builtin::Tan => |x| 1.0 / (x.cos() * x.cos()),
+            Builtin::Abs => |x| x / x.abs(),
+            Builtin::Arg => |_| 0.0,
+            Builtin::Conj => |_| 1.0,
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        for (name, builtin) in BUILTINS {
+            assert_eq!(Some(*builtin), Builtin::from_name(name));
+            assert_eq!(*name, builtin.name());
+        }
+        assert_eq!(None, Builtin::from_name("length"));
+    }
+}
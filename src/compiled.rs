@@ -0,0 +1,241 @@
+use crate::compiler::Program;
+use crate::equality::EqualityMode;
+use crate::eval::{EvalContext, EvalError, NumericPolicy};
+use crate::lexer::{Lexer, LexingError};
+use crate::parser::{Parser, ParsingError};
+use crate::vm::{self, Machine};
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+#[derive(Debug, PartialEq, Fail)]
+pub enum CompileError {
+    #[fail(display = "{}", _0)]
+    Lexing(LexingError),
+    #[fail(display = "{}", _0)]
+    Parsing(ParsingError),
+    /// The expression uses values or statements the compiled programs don't support.
+    #[fail(display = "{}", _0)]
+    Compilation(EvalError),
+}
+
+impl From<LexingError> for CompileError {
+    fn from(error: LexingError) -> Self {
+        CompileError::Lexing(error)
+    }
+}
+
+impl From<ParsingError> for CompileError {
+    fn from(error: ParsingError) -> Self {
+        CompileError::Parsing(error)
+    }
+}
+
+impl From<EvalError> for CompileError {
+    fn from(error: EvalError) -> Self {
+        CompileError::Compilation(error)
+    }
+}
+
+/// A numeric expression compiled once and evaluated many times with different
+/// values of its variables. It holds no shared state, so it can be used from
+/// several threads at once.
+#[derive(Clone, Debug)]
+pub struct CompiledExpr {
+    program: Program,
+    machine: Machine,
+}
+
+impl CompiledExpr {
+    /// Compiles 'src' with the built-in constants. The variables it reads without
+    /// setting them first are its free variables, which 'eval' requires.
+    pub fn compile(src: &str) -> Result<Self, CompileError> {
+        let tokens = Lexer::get_tokens(src)?;
+        let root = Parser::new(&tokens).parse()?;
+        let ctx = EvalContext::populated();
+        Ok(CompiledExpr {
+            program: ctx.compile(&root)?,
+            machine: ctx.machine(),
+        })
+    }
+
+    pub fn with_numeric_policy(mut self, policy: NumericPolicy) -> Self {
+        self.machine.numeric_policy = policy;
+        self
+    }
+
+    pub fn with_equality(mut self, mode: EqualityMode) -> Self {
+        self.machine.equality = mode;
+        self
+    }
+
+    /// Limits the iterations of the loops run by every call to 'eval'.
+    pub fn with_iteration_budget(mut self, budget: usize) -> Self {
+        self.machine.iteration_budget = budget;
+        self
+    }
+
+    /// The free variables of the expression, in the order of their first use.
+    pub fn variables(&self) -> impl Iterator<Item = &str> {
+        self.program
+            .globals
+            .iter()
+            .filter(|global| global.free)
+            .map(|global| global.name.as_str())
+    }
+
+    /// Evaluates the expression with the values of its variables in 'bindings'.
+    /// Fails with 'EvalError::SymbolNotFound' before running anything if one of
+    /// its free variables isn't bound. The variables it sets aren't kept.
+    pub fn eval<K>(&self, bindings: &HashMap<K, f64>) -> Result<f64, EvalError>
+    where
+        K: Borrow<str> + Hash + Eq,
+    {
+        let mut slots = vec![0.0; self.program.slots];
+        let mut defined = vec![false; self.program.slots];
+        for global in &self.program.globals {
+            match bindings.get(global.name.as_str()) {
+                Some(&num) => {
+                    slots[global.slot] = num;
+                    defined[global.slot] = true;
+                }
+                None if global.free => {
+                    return Err(EvalError::SymbolNotFound(
+                        global.name.clone(),
+                        global.location,
+                    ))
+                }
+                None => {}
+            }
+        }
+
+        vm::execute(self.machine, &self.program, &mut slots, &mut defined)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Location;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn row(values: &[(&'static str, f64)]) -> HashMap<&'static str, f64> {
+        values.iter().cloned().collect()
+    }
+
+    #[test]
+    fn test_eval_many() {
+        let expr = CompiledExpr::compile("price * qty * (1 - discount)").unwrap();
+        assert_eq!(
+            vec!["price", "qty", "discount"],
+            expr.variables().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Ok(15.0),
+            expr.eval(&row(&[("price", 2.5), ("qty", 6.0), ("discount", 0.0)]))
+        );
+        assert_eq!(
+            Ok(7.5),
+            expr.eval(&row(&[("price", 2.5), ("qty", 6.0), ("discount", 0.5)]))
+        );
+
+        let mut owned = HashMap::new();
+        owned.insert("price".to_string(), 1.0);
+        owned.insert("qty".to_string(), 2.0);
+        owned.insert("discount".to_string(), 0.25);
+        owned.insert("unused".to_string(), 7.0);
+        assert_eq!(Ok(1.5), expr.eval(&owned));
+    }
+
+    #[test]
+    fn test_missing_variable() {
+        let expr =
+            CompiledExpr::compile("total = 0\nfor i in 0..n { total += rate }\ntotal").unwrap();
+        assert_eq!(vec!["n", "rate"], expr.variables().collect::<Vec<_>>());
+        assert_eq!(
+            Err(EvalError::SymbolNotFound(
+                "rate".to_string(),
+                Location(1, 25)
+            )),
+            expr.eval(&row(&[("n", 0.0)]))
+        );
+        assert_eq!(Ok(1.5), expr.eval(&row(&[("n", 3.0), ("rate", 0.5)])));
+
+        // Read before being set, 'y' is a free variable.
+        let expr = CompiledExpr::compile("y += 1\ny").unwrap();
+        assert_eq!(vec!["y"], expr.variables().collect::<Vec<_>>());
+        assert_eq!(
+            Err(EvalError::SymbolNotFound("y".to_string(), Location(0, 0))),
+            expr.eval(&row(&[]))
+        );
+        assert_eq!(Ok(3.0), expr.eval(&row(&[("y", 2.0)])));
+    }
+
+    #[test]
+    fn test_settings() {
+        let expr = CompiledExpr::compile("a / b").unwrap();
+        assert_eq!(
+            Ok(f64::INFINITY),
+            expr.eval(&row(&[("a", 1.0), ("b", 0.0)]))
+        );
+        let expr = expr.with_numeric_policy(NumericPolicy::RejectDivisionByZero);
+        assert_eq!(
+            Err(EvalError::DivisionByZero(Location(0, 2))),
+            expr.eval(&row(&[("a", 1.0), ("b", 0.0)]))
+        );
+        let expr = CompiledExpr::compile("a * INF")
+            .unwrap()
+            .with_numeric_policy(NumericPolicy::RejectNonFinite);
+        assert_eq!(
+            Err(EvalError::NonFiniteResult(f64::INFINITY, Location(0, 4))),
+            expr.eval(&row(&[("a", 1.0)]))
+        );
+        assert!(matches!(
+            expr.eval(&row(&[("a", f64::NAN)])),
+            Err(EvalError::NonFiniteResult(num, Location(0, 0))) if num.is_nan()
+        ));
+
+        let expr = CompiledExpr::compile("a == b").unwrap();
+        assert_eq!(Ok(1.0), expr.eval(&row(&[("a", 0.3), ("b", 0.1 + 0.2)])));
+        let expr = expr.with_equality(EqualityMode::Exact);
+        assert_eq!(Ok(0.0), expr.eval(&row(&[("a", 0.3), ("b", 0.1 + 0.2)])));
+
+        let expr = CompiledExpr::compile("while 1 { 1 }")
+            .unwrap()
+            .with_iteration_budget(10);
+        assert_eq!(
+            Err(EvalError::IterationBudgetExceeded(10, Location(0, 0))),
+            expr.eval(&row(&[]))
+        );
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        assert!(matches!(
+            CompiledExpr::compile("1 @ 2"),
+            Err(CompileError::Lexing(_))
+        ));
+        assert!(matches!(
+            CompiledExpr::compile("1 +"),
+            Err(CompileError::Parsing(_))
+        ));
+        assert!(matches!(
+            CompiledExpr::compile("len([1, 2])"),
+            Err(CompileError::Compilation(EvalError::Unimplemented(_)))
+        ));
+    }
+
+    #[test]
+    fn test_shared_across_threads() {
+        let expr = Arc::new(CompiledExpr::compile("x ^ 2 + 1").unwrap());
+        let handles: Vec<_> = (0..4)
+            .map(|index| {
+                let expr = Arc::clone(&expr);
+                thread::spawn(move || expr.eval(&row(&[("x", f64::from(index))])))
+            })
+            .collect();
+        let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(vec![Ok(1.0), Ok(2.0), Ok(5.0), Ok(10.0)], results);
+    }
+}
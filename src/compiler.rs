@@ -0,0 +1,594 @@
+use crate::eval::{EvalContext, EvalError};
+use crate::lexer::Keyword;
+use crate::parser::{Location, NodeType, ParseNode};
+use crate::value::Value;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+/// An instruction of the stack machine run by 'EvalContext::run'.
+#[derive(Copy, Clone, Debug)]
+pub enum Instruction {
+    Constant(f64),
+    /// Pushes the value of a slot set by the program.
+    Load(usize),
+    /// Pushes the value of a global slot, which may be unset.
+    LoadGlobal(usize),
+    /// Sets a slot to the value on top of the stack, leaving it there.
+    Store(usize),
+    Pop,
+    /// Pops that many values, leaving a loop with 'break' or 'continue'.
+    Discard(usize),
+    Add,
+    Substract,
+    Multiply,
+    Divide,
+    Power,
+    Negate,
+    GreaterThan,
+    GreaterThanOrEqual,
+    LessThan,
+    LessThanOrEqual,
+    Equal,
+    NotEqual,
+    Jump(usize),
+    /// Pops the condition and jumps if it is 0.
+    JumpIfZero(usize),
+    /// Counts an iteration of a loop against the budget.
+    Iterate,
+    /// Adds 1 to a slot, the counter of a 'for' loop.
+    Increment(usize),
+    /// Fails with 'EvalError::InvalidRange' unless the slots hold valid bounds of a range.
+    CheckRange(usize, usize),
+    /// Fails with the error of that index in the errors of the 'Program'.
+    Fail(usize),
+    /// Sets the first slot to the index of the second one, a global slot, if it is set,
+    /// and to the index of the third one otherwise. Decides when the program runs whether
+    /// an assignment in a block updates a global or binds a variable of the block.
+    Choose(usize, usize, usize),
+    /// Pushes the value of the slot whose index is in a slot set by 'Choose'.
+    LoadReference(usize),
+    /// Like 'Store', in the slot whose index is in a slot set by 'Choose'.
+    StoreReference(usize),
+}
+
+/// A 'ParseNode' compiled to bytecode, where variables are numbered slots.
+#[derive(Clone, Debug)]
+pub struct Program {
+    /// - instructions. The code, run from the first instruction to the end.
+    pub(crate) instructions: Vec<Instruction>,
+
+    /// - locations.    The location of the node of each instruction,
+    ///                 reported by the errors it raises.
+    pub(crate) locations: Vec<Location>,
+
+    /// - slots.        The number of slots used by the program.
+    pub(crate) slots: usize,
+
+    /// - globals.      The global variables, loaded from the 'EvalContext' before the run.
+    pub(crate) globals: Vec<Global>,
+
+    /// - errors.       The errors raised by the 'Fail' instructions.
+    pub(crate) errors: Vec<EvalError>,
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for global in &self.globals {
+            writeln!(f, "global {} = #{}", global.name, global.slot)?;
+        }
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            match instruction {
+                Instruction::Fail(error) => {
+                    writeln!(f, "{:4} Fail({})", index, self.errors[*error])?
+                }
+                _ => writeln!(f, "{:4} {:?}", index, instruction)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Where the compiler put a variable bound by the program.
+#[derive(Copy, Clone, Debug)]
+enum Variable {
+    Slot(usize),
+    /// A slot set by 'Instruction::Choose'.
+    Reference(usize),
+}
+
+/// A global variable of a 'Program'.
+#[derive(Clone, Debug)]
+pub(crate) struct Global {
+    pub(crate) name: String,
+    pub(crate) slot: usize,
+
+    /// - location. The first use of the variable.
+    pub(crate) location: Location,
+
+    /// - assigned. Whether the program sets the variable,
+    ///             which is then stored back in the 'EvalContext' after the run.
+    pub(crate) assigned: bool,
+
+    /// - free.     Whether the program reads the variable before setting it,
+    ///             so it needs a value to run.
+    pub(crate) free: bool,
+}
+
+/// Jumps to patch once the end of a loop is known.
+struct Loop {
+    /// - depth.     The height of the stack when an iteration starts.
+    depth: usize,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+struct Compiler<'c> {
+    ctx: &'c EvalContext,
+    program: Program,
+
+    /// - scopes. The variables bound by the enclosing blocks, innermost last.
+    ///           The first one holds the globals bound by the program itself.
+    scopes: Vec<HashMap<String, Variable>>,
+
+    /// - globals. The index in the globals of the program of every global it refers to.
+    globals: HashMap<String, usize>,
+
+    /// - depth.   The height of the stack after the instructions emitted so far.
+    depth: usize,
+
+    loops: Vec<Loop>,
+}
+
+pub(crate) fn compile(ctx: &EvalContext, node: &ParseNode) -> Result<Program, EvalError> {
+    let mut compiler = Compiler {
+        ctx,
+        program: Program {
+            instructions: vec![],
+            locations: vec![],
+            slots: 0,
+            globals: vec![],
+            errors: vec![],
+        },
+        scopes: vec![HashMap::new()],
+        globals: HashMap::new(),
+        depth: 0,
+        loops: vec![],
+    };
+    compiler.compile_node(node)?;
+    Ok(compiler.program)
+}
+
+impl Compiler<'_> {
+    fn compile_node(&mut self, node: &ParseNode) -> Result<(), EvalError> {
+        use NodeType::*;
+        let location = node.location;
+        match &node.ntype {
+            Root(nodes) if nodes.is_empty() => {
+                return Err(EvalError::Unimplemented(String::from("empty program")))
+            }
+            Root(nodes) => self.compile_sequence(nodes, location)?,
+            Number(num) => self.emit(Instruction::Constant(*num), location),
+            Error => self.fail(EvalError::InvalidExpression(location), location),
+            Sum(left, right) => self.compile_binary(left, right, Instruction::Add, location)?,
+            Substraction(left, right) => {
+                self.compile_binary(left, right, Instruction::Substract, location)?
+            }
+            Multiplication(left, right) => {
+                self.compile_binary(left, right, Instruction::Multiply, location)?
+            }
+            Division(left, right) => {
+                self.compile_binary(left, right, Instruction::Divide, location)?
+            }
+            Power(left, right) => self.compile_binary(left, right, Instruction::Power, location)?,
+            Negation(child) => {
+                self.compile_node(child)?;
+                self.emit(Instruction::Negate, location);
+            }
+            GreaterThan(left, right) => {
+                self.compile_binary(left, right, Instruction::GreaterThan, location)?
+            }
+            GreaterThanOrEqual(left, right) => {
+                self.compile_binary(left, right, Instruction::GreaterThanOrEqual, location)?
+            }
+            LessThan(left, right) => {
+                self.compile_binary(left, right, Instruction::LessThan, location)?
+            }
+            LessThanOrEqual(left, right) => {
+                self.compile_binary(left, right, Instruction::LessThanOrEqual, location)?
+            }
+            Equal(left, right) => self.compile_binary(left, right, Instruction::Equal, location)?,
+            NotEqual(left, right) => {
+                self.compile_binary(left, right, Instruction::NotEqual, location)?
+            }
+            Identifier(identifier) => self.compile_identifier(identifier, location)?,
+            Assignment(identifier, _) | Let(identifier, _)
+                if self.assignment_error(identifier, location).is_some() =>
+            {
+                let error = self.assignment_error(identifier, location).unwrap();
+                self.fail(error, location)
+            }
+            Assignment(identifier, right) => {
+                self.compile_node(right)?;
+                match self.assigned_variable(identifier, location) {
+                    Variable::Slot(slot) => self.emit(Instruction::Store(slot), location),
+                    Variable::Reference(slot) => {
+                        self.emit(Instruction::StoreReference(slot), location)
+                    }
+                }
+            }
+            Let(identifier, right) => {
+                self.compile_node(right)?;
+                let slot = if self.scopes.len() == 1 {
+                    self.global_slot(identifier, location, true)
+                } else {
+                    self.new_slot()
+                };
+                self.scopes
+                    .last_mut()
+                    .unwrap()
+                    .insert(identifier.clone(), Variable::Slot(slot));
+                self.emit(Instruction::Store(slot), location);
+            }
+            Block(nodes) if nodes.is_empty() => {
+                self.fail(EvalError::EmptyBlock(location), location)
+            }
+            Block(nodes) => {
+                self.scopes.push(HashMap::new());
+                let res = self.compile_sequence(nodes, location);
+                self.scopes.pop();
+                res?
+            }
+            While(condition, body) => self.compile_while(condition, body, location)?,
+            For(variable, iterable, body) => match &iterable.ntype {
+                Range(start, end) => {
+                    let range = self.compile_range(start, end, iterable.location)?;
+                    self.compile_for(variable, range, body, location)?
+                }
+                _ => return Err(unsupported("'for' over a list", location)),
+            },
+            Break => self.compile_loop_exit(true, location),
+            Continue => self.compile_loop_exit(false, location),
+            Const(..) => return Err(unsupported("'const'", location)),
+            List(_) | Index(..) | Range(..) => return Err(unsupported("lists", location)),
+            Record(_) | Field(..) => return Err(unsupported("records", location)),
+            Function(..) | Call(..) => return Err(unsupported("functions", location)),
+            Quantity(..) | Conversion(..) => return Err(unsupported("units", location)),
+            Imaginary(_) => return Err(unsupported("complex numbers", location)),
+            Bounds(..) => return Err(unsupported("intervals", location)),
+        }
+
+        Ok(())
+    }
+
+    /// Compiles the statements of a block, valued as the last one.
+    fn compile_sequence(
+        &mut self,
+        nodes: &[ParseNode],
+        location: Location,
+    ) -> Result<(), EvalError> {
+        for (index, node) in nodes.iter().enumerate() {
+            if index > 0 {
+                self.emit(Instruction::Pop, location);
+            }
+            self.compile_node(node)?;
+        }
+
+        Ok(())
+    }
+
+    fn compile_binary(
+        &mut self,
+        left: &ParseNode,
+        right: &ParseNode,
+        instruction: Instruction,
+        location: Location,
+    ) -> Result<(), EvalError> {
+        self.compile_node(left)?;
+        self.compile_node(right)?;
+        self.emit(instruction, location);
+        Ok(())
+    }
+
+    fn compile_identifier(
+        &mut self,
+        identifier: &str,
+        location: Location,
+    ) -> Result<(), EvalError> {
+        match self.ctx.constant(identifier) {
+            Some(Value::Number(num)) => self.emit(Instruction::Constant(*num), location),
+            Some(val) => {
+                return Err(unsupported(
+                    &format!("the {} constant '{}'", val.type_name(), identifier),
+                    location,
+                ))
+            }
+            None => match self.local_variable(identifier) {
+                Some(Variable::Slot(slot)) => self.emit(Instruction::Load(slot), location),
+                Some(Variable::Reference(slot)) => {
+                    self.emit(Instruction::LoadReference(slot), location)
+                }
+                None => {
+                    let slot = self.global_slot(identifier, location, false);
+                    self.emit(Instruction::LoadGlobal(slot), location);
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Runs the body while the condition isn't 0, valued as the last iteration or 0.
+    fn compile_while(
+        &mut self,
+        condition: &ParseNode,
+        body: &ParseNode,
+        location: Location,
+    ) -> Result<(), EvalError> {
+        let res = self.new_slot();
+        self.emit(Instruction::Constant(0.0), location);
+        self.emit(Instruction::Store(res), location);
+        self.emit(Instruction::Pop, location);
+
+        let start = self.program.instructions.len();
+        // A 'break' or 'continue' in the condition exits or retries this loop.
+        self.loops.push(Loop {
+            depth: self.depth,
+            breaks: vec![],
+            continues: vec![],
+        });
+        self.compile_node(condition)?;
+        let condition_exits = self.loops.pop().unwrap();
+        let exit = self.emit_jump(Instruction::JumpIfZero(0), location);
+        self.emit(Instruction::Iterate, location);
+        self.compile_loop_body(body, res, location)?;
+        self.emit(Instruction::Jump(start), location);
+
+        let retry = self.program.instructions.len();
+        if !condition_exits.continues.is_empty() {
+            self.emit(Instruction::Iterate, location);
+            self.emit(Instruction::Jump(start), location);
+        }
+
+        let end = self.program.instructions.len();
+        self.patch(exit, end);
+        self.patch_loop(start, end);
+        for jump in condition_exits.breaks {
+            self.patch(jump, end);
+        }
+        for jump in condition_exits.continues {
+            self.patch(jump, retry);
+        }
+        self.emit(Instruction::Load(res), location);
+        Ok(())
+    }
+
+    /// Stores the bounds of a range in two slots, the counter and the limit of a loop.
+    fn compile_range(
+        &mut self,
+        start: &ParseNode,
+        end: &ParseNode,
+        location: Location,
+    ) -> Result<(usize, usize), EvalError> {
+        let (counter, limit) = (self.new_slot(), self.new_slot());
+        self.compile_node(start)?;
+        self.emit(Instruction::Store(counter), location);
+        self.emit(Instruction::Pop, location);
+        self.compile_node(end)?;
+        self.emit(Instruction::Store(limit), location);
+        self.emit(Instruction::Pop, location);
+        self.emit(Instruction::CheckRange(counter, limit), location);
+        Ok((counter, limit))
+    }
+
+    /// Runs the body for each integer of the range, valued as the last iteration or 0.
+    fn compile_for(
+        &mut self,
+        variable: &str,
+        (counter, limit): (usize, usize),
+        body: &ParseNode,
+        location: Location,
+    ) -> Result<(), EvalError> {
+        let res = self.new_slot();
+        self.emit(Instruction::Constant(0.0), location);
+        self.emit(Instruction::Store(res), location);
+        self.emit(Instruction::Pop, location);
+
+        let start = self.program.instructions.len();
+        self.emit(Instruction::Load(counter), location);
+        self.emit(Instruction::Load(limit), location);
+        self.emit(Instruction::LessThan, location);
+        let exit = self.emit_jump(Instruction::JumpIfZero(0), location);
+        self.emit(Instruction::Iterate, location);
+
+        let slot = self.new_slot();
+        self.scopes.push(
+            vec![(variable.to_string(), Variable::Slot(slot))]
+                .into_iter()
+                .collect(),
+        );
+        self.emit(Instruction::Load(counter), location);
+        self.emit(Instruction::Store(slot), location);
+        self.emit(Instruction::Pop, location);
+        let res_body = self.compile_loop_body(body, res, location);
+        self.scopes.pop();
+        res_body?;
+
+        let next = self.program.instructions.len();
+        self.emit(Instruction::Increment(counter), location);
+        self.emit(Instruction::Jump(start), location);
+
+        let end = self.program.instructions.len();
+        self.patch(exit, end);
+        self.patch_loop(next, end);
+        self.emit(Instruction::Load(res), location);
+        Ok(())
+    }
+
+    /// Compiles the body of a loop storing its value in 'res',
+    /// leaving the jumps of its 'break' and 'continue' to patch.
+    fn compile_loop_body(
+        &mut self,
+        body: &ParseNode,
+        res: usize,
+        location: Location,
+    ) -> Result<(), EvalError> {
+        self.loops.push(Loop {
+            depth: self.depth,
+            breaks: vec![],
+            continues: vec![],
+        });
+        self.compile_node(body)?;
+        self.emit(Instruction::Store(res), location);
+        self.emit(Instruction::Pop, location);
+        Ok(())
+    }
+
+    fn patch_loop(&mut self, next: usize, end: usize) {
+        let exits = self.loops.pop().unwrap();
+        for jump in exits.breaks {
+            self.patch(jump, end);
+        }
+        for jump in exits.continues {
+            self.patch(jump, next);
+        }
+    }
+
+    fn compile_loop_exit(&mut self, is_break: bool, location: Location) {
+        let depth = self.depth;
+        let loop_depth = match self.loops.last() {
+            Some(innermost) => innermost.depth,
+            None if is_break => return self.fail(EvalError::Break(location), location),
+            None => return self.fail(EvalError::Continue(location), location),
+        };
+
+        if depth > loop_depth {
+            self.emit(Instruction::Discard(depth - loop_depth), location);
+        }
+        let jump = self.emit_jump(Instruction::Jump(0), location);
+        let innermost = self.loops.last_mut().unwrap();
+        if is_break {
+            innermost.breaks.push(jump);
+        } else {
+            innermost.continues.push(jump);
+        }
+        // The code following the jump is never run, but expects the value of the expression.
+        self.depth = depth + 1;
+    }
+
+    /// The error raised by the evaluation of an assignment to 'identifier', if any.
+    fn assignment_error(&self, identifier: &str, location: Location) -> Option<EvalError> {
+        if Keyword::from_word(identifier).is_some() {
+            Some(EvalError::ReservedWordAssignment(
+                identifier.to_string(),
+                location,
+            ))
+        } else if self.ctx.constant(identifier).is_some() {
+            Some(EvalError::ConstantAssignment(
+                identifier.to_string(),
+                location,
+            ))
+        } else {
+            None
+        }
+    }
+
+    fn fail(&mut self, error: EvalError, location: Location) {
+        self.program.errors.push(error);
+        self.emit(Instruction::Fail(self.program.errors.len() - 1), location);
+    }
+
+    /// Like 'EvalContext::assign', updates the innermost variable or creates one in the
+    /// innermost block. In a block, a global which isn't bound by the program itself
+    /// may be bound in the context when it runs, so the choice is left to 'Choose'.
+    fn assigned_variable(&mut self, identifier: &str, location: Location) -> Variable {
+        if let Some(variable) = self.local_variable(identifier) {
+            return variable;
+        }
+
+        let global = self.global_slot(identifier, location, true);
+        let variable = if self.scopes.len() == 1 {
+            Variable::Slot(global)
+        } else {
+            let reference = self.new_slot();
+            let local = self.new_slot();
+            self.emit(Instruction::Choose(reference, global, local), location);
+            Variable::Reference(reference)
+        };
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(identifier.to_string(), variable);
+        variable
+    }
+
+    fn local_variable(&self, identifier: &str) -> Option<Variable> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(identifier))
+            .copied()
+    }
+
+    /// The slot of a global which the program sets or reads. The reads reaching it
+    /// aren't preceded by a top level assignment, which binds the global in a scope.
+    fn global_slot(&mut self, identifier: &str, location: Location, assigned: bool) -> usize {
+        if let Some(&index) = self.globals.get(identifier) {
+            let global = &mut self.program.globals[index];
+            global.assigned |= assigned;
+            global.free |= !assigned;
+            return global.slot;
+        }
+
+        let slot = self.new_slot();
+        self.globals
+            .insert(identifier.to_string(), self.program.globals.len());
+        self.program.globals.push(Global {
+            name: identifier.to_string(),
+            slot,
+            location,
+            assigned,
+            free: !assigned,
+        });
+        slot
+    }
+
+    fn new_slot(&mut self) -> usize {
+        self.program.slots += 1;
+        self.program.slots - 1
+    }
+
+    fn emit(&mut self, instruction: Instruction, location: Location) {
+        use Instruction::*;
+        self.depth = match instruction {
+            Constant(_) | Load(_) | LoadGlobal(_) | Fail(_) => self.depth + 1,
+            Pop | JumpIfZero(_) => self.depth - 1,
+            Discard(count) => self.depth - count,
+            Add | Substract | Multiply | Divide | Power | GreaterThan | GreaterThanOrEqual
+            | LessThan | LessThanOrEqual | Equal | NotEqual => self.depth - 1,
+            Store(_) | Negate | Jump(_) | Iterate | Increment(_) | CheckRange(..) => self.depth,
+            LoadReference(_) => self.depth + 1,
+            Choose(..) | StoreReference(_) => self.depth,
+        };
+        self.program.instructions.push(instruction);
+        self.program.locations.push(location);
+    }
+
+    /// Emits a jump to patch later, returning its index.
+    fn emit_jump(&mut self, instruction: Instruction, location: Location) -> usize {
+        self.emit(instruction, location);
+        self.program.instructions.len() - 1
+    }
+
+    fn patch(&mut self, jump: usize, target: usize) {
+        self.program.instructions[jump] = match self.program.instructions[jump] {
+            Instruction::Jump(_) => Instruction::Jump(target),
+            Instruction::JumpIfZero(_) => Instruction::JumpIfZero(target),
+            instruction => panic!("Cannot patch {:?} as a jump", instruction),
+        };
+    }
+}
+
+fn unsupported(what: &str, location: Location) -> EvalError {
+    EvalError::Unimplemented(format!("{} at {:?} in compiled programs", what, location))
+}
@@ -0,0 +1,296 @@
+use crate::builtins::Builtin;
+use crate::equality::EqualityMode;
+use std::fmt::{Display, Formatter};
+
+/// A complex number, written with an imaginary literal like '3 + 4i'.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    const ONE: Complex = Complex { re: 1.0, im: 0.0 };
+
+    pub fn new(re: f64, im: f64) -> Self {
+        Complex { re, im }
+    }
+
+    /// Whether the imaginary part is zero, which makes the number ordered.
+    pub fn is_real(self) -> bool {
+        self.im == 0.0
+    }
+
+    /// The modulus, computed without overflowing on large parts.
+    pub fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    /// The argument, in ]-PI, PI].
+    pub fn arg(self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    pub fn conj(self) -> Complex {
+        Complex::new(self.re, -self.im)
+    }
+
+    pub(crate) fn add(left: Complex, right: Complex) -> Complex {
+        Complex::new(left.re + right.re, left.im + right.im)
+    }
+
+    pub(crate) fn substract(left: Complex, right: Complex) -> Complex {
+        Complex::new(left.re - right.re, left.im - right.im)
+    }
+
+    pub(crate) fn multiply(left: Complex, right: Complex) -> Complex {
+        Complex::new(
+            left.re * right.re - left.im * right.im,
+            left.re * right.im + left.im * right.re,
+        )
+    }
+
+    /// Smith's algorithm, scaling 'right' by its larger part so large numbers
+    /// don't overflow. Dividing by 0 is NaN.
+    pub(crate) fn divide(left: Complex, right: Complex) -> Complex {
+        if right.re.abs() >= right.im.abs() {
+            let ratio = right.im / right.re;
+            let denominator = right.re + right.im * ratio;
+            Complex::new(
+                (left.re + left.im * ratio) / denominator,
+                (left.im - left.re * ratio) / denominator,
+            )
+        } else {
+            let ratio = right.re / right.im;
+            let denominator = right.re * ratio + right.im;
+            Complex::new(
+                (left.re * ratio + left.im) / denominator,
+                (left.im * ratio - left.re) / denominator,
+            )
+        }
+    }
+
+    /// Integer exponents are computed by multiplications, which keeps '(1 + 2i) ^ 2'
+    /// exact, and the others by the principal logarithm of 'base'.
+    pub(crate) fn power(base: Complex, exponent: Complex) -> Complex {
+        let integer = exponent.is_real()
+            && exponent.re.fract() == 0.0
+            && exponent.re.abs() <= f64::from(i32::MAX);
+        if integer {
+            let mut res = Complex::ONE;
+            let mut square = base;
+            let mut remaining = exponent.re.abs() as u32;
+            while remaining > 0 {
+                if remaining % 2 == 1 {
+                    res = Complex::multiply(res, square);
+                }
+                square = Complex::multiply(square, square);
+                remaining /= 2;
+            }
+            return if exponent.re < 0.0 {
+                Complex::divide(Complex::ONE, res)
+            } else {
+                res
+            };
+        }
+        if base.re == 0.0 && base.im == 0.0 && exponent.re > 0.0 {
+            return Complex::new(0.0, 0.0);
+        }
+
+        Complex::multiply(exponent, base.ln()).exp()
+    }
+
+    pub(crate) fn negate(self) -> Complex {
+        Complex::new(-self.re, -self.im)
+    }
+
+    fn exp(self) -> Complex {
+        let modulus = self.re.exp();
+        Complex::new(modulus * self.im.cos(), modulus * self.im.sin())
+    }
+
+    fn ln(self) -> Complex {
+        Complex::new(self.abs().ln(), self.arg())
+    }
+
+    /// The principal square root, whose real part is non-negative. The larger part
+    /// is computed first, and the other one from it to avoid cancellations.
+    fn sqrt(self) -> Complex {
+        if self.re == 0.0 && self.im == 0.0 {
+            return Complex::new(0.0, self.im);
+        }
+
+        let larger = ((self.re.abs() + self.abs()) / 2.0).sqrt();
+        let smaller = self.im / (2.0 * larger);
+        if self.re >= 0.0 {
+            Complex::new(larger, smaller)
+        } else {
+            Complex::new(smaller.abs(), larger.copysign(self.im))
+        }
+    }
+
+    /// Applies a math built-in returning a complex number, or 'None' for the
+    /// ones only defined on real numbers.
+    pub(crate) fn apply(self, builtin: Builtin) -> Option<Complex> {
+        match builtin {
+            Builtin::Conj => Some(self.conj()),
+            Builtin::Exp => Some(self.exp()),
+            Builtin::Ln => Some(self.ln()),
+            Builtin::Sqrt => Some(self.sqrt()),
+            _ => None,
+        }
+    }
+
+    /// The real parts of the operands when both are real, the others aren't ordered.
+    fn real_parts(left: Complex, right: Complex) -> Option<(f64, f64)> {
+        if left.is_real() && right.is_real() {
+            Some((left.re, right.re))
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn less_than(left: Complex, right: Complex) -> Option<bool> {
+        Complex::real_parts(left, right).map(|(left, right)| left < right)
+    }
+
+    pub(crate) fn less_than_or_equal(left: Complex, right: Complex) -> Option<bool> {
+        Complex::real_parts(left, right).map(|(left, right)| left <= right)
+    }
+
+    pub(crate) fn greater_than(left: Complex, right: Complex) -> Option<bool> {
+        Complex::less_than(right, left)
+    }
+
+    pub(crate) fn greater_than_or_equal(left: Complex, right: Complex) -> Option<bool> {
+        Complex::less_than_or_equal(right, left)
+    }
+
+    /// Compares the real and imaginary parts under 'mode', all the numbers can be equal.
+    pub(crate) fn equal(left: Complex, right: Complex, mode: EqualityMode) -> Option<bool> {
+        Some(mode.equals(left.re, right.re) && mode.equals(left.im, right.im))
+    }
+}
+
+impl Display for Complex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.im.is_sign_negative() {
+            write!(f, "{}-{}i", self.re, -self.im)
+        } else {
+            write!(f, "{}+{}i", self.re, self.im)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    fn assert_close(res: Complex, re: f64, im: f64) {
+        assert!(
+            (res.re - re).abs() <= 1e-12 && (res.im - im).abs() <= 1e-12,
+            "{} isn't {}",
+            res,
+            Complex::new(re, im)
+        );
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let x = Complex::new(1.0, 2.0);
+        let y = Complex::new(3.0, -4.0);
+        assert_eq!(Complex::new(4.0, -2.0), Complex::add(x, y));
+        assert_eq!(Complex::new(-2.0, 6.0), Complex::substract(x, y));
+        assert_eq!(Complex::new(11.0, 2.0), Complex::multiply(x, y));
+        assert_close(Complex::divide(x, y), -0.2, 0.4);
+        assert_eq!(Complex::new(-1.0, -2.0), x.negate());
+        assert!(Complex::divide(x, Complex::new(0.0, 0.0)).re.is_nan());
+        let large = Complex::new(1e200, 1e200);
+        assert_eq!(Complex::ONE, Complex::divide(large, large));
+        let inverse = Complex::divide(Complex::ONE, large);
+        assert_close(Complex::multiply(inverse, large), 1.0, 0.0);
+    }
+
+    #[test]
+    fn test_power() {
+        let i = Complex::new(0.0, 1.0);
+        let x = Complex::new(1.0, 2.0);
+        assert_eq!(
+            Complex::new(-3.0, 4.0),
+            Complex::power(x, Complex::new(2.0, 0.0))
+        );
+        assert_eq!(
+            Complex::new(-1.0, 0.0),
+            Complex::power(i, Complex::new(2.0, 0.0))
+        );
+        assert_eq!(Complex::ONE, Complex::power(x, Complex::new(0.0, 0.0)));
+        assert_close(Complex::power(x, Complex::new(-1.0, 0.0)), 0.2, -0.4);
+        // i ^ i is the real number e ^ (-PI / 2).
+        assert_close(Complex::power(i, i), (-FRAC_PI_2).exp(), 0.0);
+        assert_close(
+            Complex::power(Complex::new(-4.0, 0.0), Complex::new(0.5, 0.0)),
+            0.0,
+            2.0,
+        );
+        assert_eq!(
+            Complex::new(0.0, 0.0),
+            Complex::power(Complex::new(0.0, 0.0), Complex::new(0.5, 1.0))
+        );
+    }
+
+    #[test]
+    fn test_functions() {
+        let x = Complex::new(3.0, 4.0);
+        assert_eq!(5.0, x.abs());
+        assert_eq!(PI, Complex::new(-1.0, 0.0).arg());
+        assert_eq!(-FRAC_PI_2, Complex::new(0.0, -2.0).arg());
+        assert_eq!(Some(Complex::new(3.0, -4.0)), x.apply(Builtin::Conj));
+        assert_eq!(Some(Complex::new(2.0, 1.0)), x.apply(Builtin::Sqrt));
+        assert_eq!(
+            Some(Complex::new(0.0, 2.0)),
+            Complex::new(-4.0, 0.0).apply(Builtin::Sqrt)
+        );
+        assert_eq!(
+            Some(Complex::new(1.0, -2.0)),
+            Complex::new(-3.0, -4.0).apply(Builtin::Sqrt)
+        );
+        // The real part doesn't cancel out with the modulus.
+        let root = Complex::new(-1e10, 1.0).apply(Builtin::Sqrt).unwrap();
+        assert!((root.re - 5e-6).abs() <= 1e-20, "{}", root);
+        assert_eq!(
+            Some(Complex::new(0.0, 0.0)),
+            Complex::new(0.0, 0.0).apply(Builtin::Sqrt)
+        );
+        assert_close(
+            Complex::new(0.0, PI).apply(Builtin::Exp).unwrap(),
+            -1.0,
+            0.0,
+        );
+        assert_close(Complex::new(-1.0, 0.0).apply(Builtin::Ln).unwrap(), 0.0, PI);
+        assert_eq!(None, x.apply(Builtin::Sin));
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let real = Complex::new(2.0, 0.0);
+        let x = Complex::new(1.0, 2.0);
+        assert_eq!(Some(true), Complex::less_than(Complex::ONE, real));
+        assert_eq!(
+            Some(false),
+            Complex::greater_than_or_equal(Complex::ONE, real)
+        );
+        assert_eq!(None, Complex::less_than(x, real));
+        assert_eq!(None, Complex::greater_than(real, x));
+
+        let mode = EqualityMode::default();
+        assert_eq!(Some(true), Complex::equal(x, Complex::new(1.0, 2.0), mode));
+        assert_eq!(Some(false), Complex::equal(x, x.conj(), mode));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!("3+4i", Complex::new(3.0, 4.0).to_string());
+        assert_eq!("0.5-1i", Complex::new(0.5, -1.0).to_string());
+    }
+}
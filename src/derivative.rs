@@ -0,0 +1,344 @@
+use crate::builtins::Builtin;
+use crate::eval::{EvalContext, EvalError};
+use crate::parser::{Location, NodeType, ParseNode};
+use crate::simplifier;
+
+type Child = Box<ParseNode>;
+
+/// The derivative of a node, 'None' when the node doesn't depend on the variable.
+type Derivative = Result<Option<ParseNode>, EvalError>;
+
+/// Differentiates 'node', a single expression, with respect to 'variable', then simplifies
+/// the derivative for the settings of 'ctx'. The other variables are held constant,
+/// and the nodes of the derivative are located at the nodes they are derived from.
+pub(crate) fn differentiate(
+    ctx: &EvalContext,
+    node: &ParseNode,
+    variable: &str,
+) -> Result<ParseNode, EvalError> {
+    let differentiator = Differentiator { ctx, variable };
+    let derivative = match &node.ntype {
+        NodeType::Root(nodes) if nodes.len() == 1 => ParseNode {
+            ntype: NodeType::Root(vec![differentiator.derive_or_zero(&nodes[0])?]),
+            location: node.location,
+        },
+        _ => differentiator.derive_or_zero(node)?,
+    };
+
+    Ok(simplifier::simplify(ctx, &derivative))
+}
+
+struct Differentiator<'c> {
+    ctx: &'c EvalContext,
+    variable: &'c str,
+}
+
+impl Differentiator<'_> {
+    fn derive_or_zero(&self, node: &ParseNode) -> Result<ParseNode, EvalError> {
+        Ok(self
+            .derive(node)?
+            .unwrap_or_else(|| number(0.0, node.location)))
+    }
+
+    fn derive(&self, node: &ParseNode) -> Derivative {
+        use NodeType::*;
+        let location = node.location;
+        let binary =
+            |build: fn(Child, Child) -> NodeType, left, right| binary(build, left, right, location);
+        let multiply = |left, right| multiply(left, right, location);
+
+        Ok(match &node.ntype {
+            Number(_) | Imaginary(_) => None,
+            Identifier(identifier) => {
+                if identifier == self.variable && self.ctx.constant(identifier).is_none() {
+                    Some(number(1.0, location))
+                } else {
+                    None
+                }
+            }
+            Negation(value) => self
+                .derive(value)?
+                .map(|derivative| unary(Negation, derivative, location)),
+            Sum(left, right) => match (self.derive(left)?, self.derive(right)?) {
+                (Some(left), Some(right)) => Some(binary(Sum, left, right)),
+                (left, right) => left.or(right),
+            },
+            Substraction(left, right) => match (self.derive(left)?, self.derive(right)?) {
+                (Some(left), Some(right)) => Some(binary(Substraction, left, right)),
+                (Some(left), None) => Some(left),
+                (None, Some(right)) => Some(unary(Negation, right, location)),
+                (None, None) => None,
+            },
+            // (f * g)' = f' * g + f * g'
+            Multiplication(left, right) => {
+                let terms = (
+                    self.derive(left)?
+                        .map(|derivative| multiply(derivative, (**right).clone())),
+                    self.derive(right)?
+                        .map(|derivative| multiply((**left).clone(), derivative)),
+                );
+                match terms {
+                    (Some(left), Some(right)) => Some(binary(Sum, left, right)),
+                    (left, right) => left.or(right),
+                }
+            }
+            // (f / g)' = (f' * g - f * g') / g ^ 2
+            Division(left, right) => match (self.derive(left)?, self.derive(right)?) {
+                (None, None) => None,
+                (Some(derivative), None) => Some(binary(Division, derivative, (**right).clone())),
+                (left_derivative, Some(right_derivative)) => {
+                    let term = multiply((**left).clone(), right_derivative);
+                    let numerator = match left_derivative {
+                        Some(derivative) => {
+                            binary(Substraction, multiply(derivative, (**right).clone()), term)
+                        }
+                        None => unary(Negation, term, location),
+                    };
+                    let square = binary(Power, (**right).clone(), number(2.0, location));
+                    Some(binary(Division, numerator, square))
+                }
+            },
+            Power(base, exponent) => match (self.derive(base)?, self.derive(exponent)?) {
+                (None, None) => None,
+                // (f ^ n)' = n * f ^ (n - 1) * f'
+                (Some(derivative), None) => {
+                    let decremented =
+                        binary(Substraction, (**exponent).clone(), number(1.0, location));
+                    let power = binary(Power, (**base).clone(), decremented);
+                    Some(multiply(multiply((**exponent).clone(), power), derivative))
+                }
+                // (a ^ g)' = a ^ g * ln(a) * g'
+                (None, Some(derivative)) => {
+                    let log = call(Builtin::Ln, (**base).clone(), location);
+                    Some(multiply(multiply(node.clone(), log), derivative))
+                }
+                // (f ^ g)' = f ^ g * (g' * ln(f) + g * f' / f)
+                (Some(base_derivative), Some(exponent_derivative)) => {
+                    let log = call(Builtin::Ln, (**base).clone(), location);
+                    let ratio = binary(
+                        Division,
+                        multiply((**exponent).clone(), base_derivative),
+                        (**base).clone(),
+                    );
+                    let factor = binary(Sum, multiply(exponent_derivative, log), ratio);
+                    Some(multiply(node.clone(), factor))
+                }
+            },
+            Call(name, args) if args.len() == 1 && self.is_math(name) => {
+                let derivative = match self.derive(&args[0])? {
+                    Some(derivative) => derivative,
+                    None => return Ok(None),
+                };
+                let arg = args[0].clone();
+                let builtin = Builtin::from_name(name).unwrap();
+                // f(g)' = f'(g) * g'
+                Some(match builtin {
+                    Builtin::Sqrt => {
+                        let double = multiply(number(2.0, location), node.clone());
+                        binary(Division, derivative, double)
+                    }
+                    Builtin::Exp => multiply(node.clone(), derivative),
+                    Builtin::Ln => binary(Division, derivative, arg),
+                    Builtin::Sin => multiply(call(Builtin::Cos, arg, location), derivative),
+                    Builtin::Cos => unary(
+                        Negation,
+                        multiply(call(Builtin::Sin, arg, location), derivative),
+                        location,
+                    ),
+                    Builtin::Tan => {
+                        let cos = call(Builtin::Cos, arg, location);
+                        let square = binary(Power, cos, number(2.0, location));
+                        binary(Division, derivative, square)
+                    }
+                    Builtin::Abs => binary(Division, multiply(arg, derivative), node.clone()),
+                    Builtin::Arg => number(0.0, location),
+                    Builtin::Conj => derivative,
+                    _ => {
+                        return Err(EvalError::NotDifferentiable(
+                            describe(&node.ntype),
+                            location,
+                        ))
+                    }
+                })
+            }
+            Block(nodes) if nodes.len() == 1 => self.derive(&nodes[0])?,
+            ntype => return Err(EvalError::NotDifferentiable(describe(ntype), location)),
+        })
+    }
+
+    /// Whether 'name' calls a math built-in, not hidden by a variable of the context.
+    fn is_math(&self, name: &str) -> bool {
+        self.ctx.global(name).is_none()
+            && Builtin::from_name(name).and_then(Builtin::math).is_some()
+    }
+}
+
+fn describe(ntype: &NodeType) -> String {
+    use NodeType::*;
+    match ntype {
+        Call(name, _) => format!("the call of '{}'", name),
+        GreaterThan(..)
+        | GreaterThanOrEqual(..)
+        | LessThan(..)
+        | LessThanOrEqual(..)
+        | Equal(..)
+        | NotEqual(..) => "a comparison".to_string(),
+        List(_) | Index(..) | Range(..) => "a list".to_string(),
+        Record(_) | Field(..) => "a record".to_string(),
+        Quantity(..) | Conversion(..) => "a quantity".to_string(),
+        Bounds(..) => "an interval".to_string(),
+        Assignment(..) | Let(..) | Const(..) | Function(..) => "a definition".to_string(),
+        While(..) | For(..) | Break | Continue => "a loop".to_string(),
+        Block(_) | Root(_) => "several statements".to_string(),
+        _ => "an invalid expression".to_string(),
+    }
+}
+
+fn number(num: f64, location: Location) -> ParseNode {
+    ParseNode {
+        ntype: NodeType::Number(num),
+        location,
+    }
+}
+
+fn unary(build: fn(Child) -> NodeType, value: ParseNode, location: Location) -> ParseNode {
+    ParseNode {
+        ntype: build(Box::new(value)),
+        location,
+    }
+}
+
+fn binary(
+    build: fn(Child, Child) -> NodeType,
+    left: ParseNode,
+    right: ParseNode,
+    location: Location,
+) -> ParseNode {
+    ParseNode {
+        ntype: build(Box::new(left), Box::new(right)),
+        location,
+    }
+}
+
+/// Multiplies by the derivatives, leaving out the factors of 1 that the simplifier
+/// would keep under 'NumericPolicy::RejectNonFinite'.
+fn multiply(left: ParseNode, right: ParseNode, location: Location) -> ParseNode {
+    let is_one = |node: &ParseNode| node.ntype == NodeType::Number(1.0);
+    if is_one(&left) {
+        right
+    } else if is_one(&right) {
+        left
+    } else {
+        binary(NodeType::Multiplication, left, right, location)
+    }
+}
+
+fn call(builtin: Builtin, arg: ParseNode, location: Location) -> ParseNode {
+    ParseNode {
+        ntype: NodeType::Call(builtin.name().to_string(), vec![arg]),
+        location,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> ParseNode {
+        let tokens = Lexer::get_tokens(input).unwrap();
+        Parser::new(&tokens).parse().unwrap()
+    }
+
+    fn assert_derivative(input: &str, expected: &str) {
+        let ctx = EvalContext::populated();
+        let derivative = differentiate(&ctx, &parse(input), "x").unwrap();
+        assert_eq!(expected, derivative.to_source(), "{}", input);
+    }
+
+    #[test]
+    fn test_rules() {
+        assert_derivative("x ^ 2 + 3 * x", "2 * x + 3");
+        assert_derivative("5 - x * y", "-y");
+        assert_derivative("PI * y", "0");
+        assert_derivative("x / y", "1 / y");
+        assert_derivative("1 / x", "-1 / x ^ 2");
+        assert_derivative("x * x", "x + x");
+        assert_derivative("2 ^ x", "2 ^ x * 0.6931471805599453");
+        assert_derivative("x ^ x", "x ^ x * (ln(x) + x / x)");
+    }
+
+    #[test]
+    fn test_chain_rule() {
+        assert_derivative("sin(x ^ 2)", "cos(x ^ 2) * (2 * x)");
+        assert_derivative("cos(2 * x)", "-(sin(2 * x) * 2)");
+        assert_derivative("exp(x) + ln(x)", "exp(x) + 1 / x");
+        assert_derivative("sqrt(x)", "1 / (2 * sqrt(x))");
+        assert_derivative("tan(x)", "1 / cos(x) ^ 2");
+        assert_derivative("sqrt(y)", "0");
+    }
+
+    /// Compares the derivatives with the central differences of their formulas.
+    #[test]
+    fn test_numerically() {
+        let inputs = [
+            "x ^ 3 - 2 * x",
+            "x ^ x",
+            "sin(x) * cos(x) / (1 + x ^ 2)",
+            "exp(-x ^ 2 / 2) + tan(x)",
+            "sqrt(ln(x) + 4) ^ -1.5",
+        ];
+        let mut ctx = EvalContext::populated();
+        for input in &inputs {
+            let root = parse(input);
+            let derivative = differentiate(&ctx, &root, "x").unwrap();
+            for &x in &[0.5, 1.0, 1.7] {
+                let h = 1e-6;
+                let mut at = |x: f64, node: &ParseNode| {
+                    ctx.bind("x", x);
+                    match ctx.eval(node) {
+                        Ok(crate::value::Value::Number(num)) => num,
+                        res => panic!("{}: {:?}", input, res),
+                    }
+                };
+                let expected = (at(x + h, &root) - at(x - h, &root)) / (2.0 * h);
+                let res = at(x, &derivative);
+                assert!(
+                    (expected - res).abs() < 1e-6 * expected.abs().max(1.0),
+                    "{} at {}: {} != {}",
+                    input,
+                    x,
+                    res,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let ctx = EvalContext::populated();
+        assert_eq!(
+            Err(EvalError::NotDifferentiable(
+                "the call of 'len'".to_string(),
+                Location(0, 4)
+            )),
+            differentiate(&ctx, &parse("x + len(x)"), "x")
+        );
+        assert_eq!(
+            Err(EvalError::NotDifferentiable(
+                "a comparison".to_string(),
+                Location(0, 2)
+            )),
+            differentiate(&ctx, &parse("x < 1"), "x")
+        );
+        assert_eq!(
+            Err(EvalError::NotDifferentiable(
+                "several statements".to_string(),
+                Location(0, 0)
+            )),
+            differentiate(&ctx, &parse("y = 1\nx * y"), "x")
+        );
+    }
+}
@@ -0,0 +1,148 @@
+/// A number along with its partial derivatives with respect to the variables of a gradient,
+/// for the forward-mode differentiation of 'EvalContext::eval_gradient'.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Dual {
+    pub value: f64,
+
+    /// - derivatives. The partial derivatives by variable, the missing ones are 0.
+    pub derivatives: Vec<f64>,
+}
+
+impl Dual {
+    /// A number that doesn't depend on any variable.
+    pub fn constant(value: f64) -> Self {
+        Dual {
+            value,
+            derivatives: vec![],
+        }
+    }
+
+    /// The variable at 'index' among 'count' variables, seeded with a derivative of 1.
+    pub fn variable(value: f64, index: usize, count: usize) -> Self {
+        let mut derivatives = vec![0.0; count];
+        derivatives[index] = 1.0;
+        Dual { value, derivatives }
+    }
+
+    pub fn derivative(&self, index: usize) -> f64 {
+        self.derivatives.get(index).cloned().unwrap_or(0.0)
+    }
+
+    pub(crate) fn add(left: &Dual, right: &Dual) -> Dual {
+        Dual {
+            value: left.value + right.value,
+            derivatives: Self::combine(left, 1.0, right, 1.0),
+        }
+    }
+
+    pub(crate) fn substract(left: &Dual, right: &Dual) -> Dual {
+        Dual {
+            value: left.value - right.value,
+            derivatives: Self::combine(left, 1.0, right, -1.0),
+        }
+    }
+
+    pub(crate) fn multiply(left: &Dual, right: &Dual) -> Dual {
+        Dual {
+            value: left.value * right.value,
+            derivatives: Self::combine(left, right.value, right, left.value),
+        }
+    }
+
+    pub(crate) fn divide(left: &Dual, right: &Dual) -> Dual {
+        let factor = -left.value / (right.value * right.value);
+        Dual {
+            value: left.value / right.value,
+            derivatives: Self::combine(left, 1.0 / right.value, right, factor),
+        }
+    }
+
+    pub(crate) fn power(base: &Dual, exponent: &Dual) -> Dual {
+        let value = base.value.powf(exponent.value);
+        if base.derivatives.is_empty() && exponent.derivatives.is_empty() {
+            return Dual::constant(value);
+        }
+
+        let base_factor = exponent.value * base.value.powf(exponent.value - 1.0);
+        Dual {
+            value,
+            derivatives: Self::combine(base, base_factor, exponent, value * base.value.ln()),
+        }
+    }
+
+    pub(crate) fn negate(&self) -> Dual {
+        self.chain(-self.value, -1.0)
+    }
+
+    /// Applies 'function' to this number, whose 'derivative' is only computed if needed.
+    pub(crate) fn apply(&self, function: fn(f64) -> f64, derivative: fn(f64) -> f64) -> Dual {
+        let value = function(self.value);
+        if self.derivatives.is_empty() {
+            return Dual::constant(value);
+        }
+
+        self.chain(value, derivative(self.value))
+    }
+
+    /// The result of a function of this number, given its value and its derivative here.
+    fn chain(&self, value: f64, derivative: f64) -> Dual {
+        Dual {
+            value,
+            derivatives: (0..self.derivatives.len())
+                .map(|index| self.scaled(index, derivative))
+                .collect(),
+        }
+    }
+
+    /// The derivatives of 'left * left_factor + right * right_factor', where the factors
+    /// are the partial derivatives of an operation with respect to its operands.
+    fn combine(left: &Dual, left_factor: f64, right: &Dual, right_factor: f64) -> Vec<f64> {
+        let len = left.derivatives.len().max(right.derivatives.len());
+        (0..len)
+            .map(|index| left.scaled(index, left_factor) + right.scaled(index, right_factor))
+            .collect()
+    }
+
+    /// A derivative times 'factor', where a null derivative stays null even if the factor
+    /// is infinite or NaN, like for the constant exponent of 'x ^ 2' when 'x' is 0 or negative.
+    fn scaled(&self, index: usize, factor: f64) -> f64 {
+        match self.derivatives.get(index) {
+            Some(&derivative) if derivative != 0.0 => derivative * factor,
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operations() {
+        let x = Dual::variable(3.0, 0, 2);
+        let y = Dual::variable(2.0, 1, 2);
+        let two = Dual::constant(2.0);
+
+        let product = Dual::multiply(&x, &y);
+        assert_eq!(vec![2.0, 3.0], product.derivatives);
+        let quotient = Dual::divide(&x, &y);
+        assert_eq!(vec![0.5, -0.75], quotient.derivatives);
+        let square = Dual::power(&x, &two);
+        assert_eq!(9.0, square.value);
+        assert_eq!(6.0, square.derivative(0));
+        assert_eq!(0.0, square.derivative(1));
+        let difference = Dual::substract(&two, &x.negate());
+        assert_eq!(vec![1.0, 0.0], difference.derivatives);
+    }
+
+    #[test]
+    fn test_null_derivatives() {
+        let zero = Dual::variable(0.0, 0, 1);
+        let square = Dual::power(&zero, &Dual::constant(2.0));
+        assert_eq!(vec![0.0], square.derivatives);
+
+        let negative = Dual::variable(-2.0, 0, 1);
+        let cube = Dual::power(&negative, &Dual::constant(3.0));
+        assert_eq!(vec![12.0], cube.derivatives);
+    }
+}
@@ -0,0 +1,111 @@
+/// How '==' and '!=' compare two numbers.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum EqualityMode {
+    /// Equal only when bit for bit the same number, but for '0 == -0'.
+    Exact,
+    /// Equal when they differ by at most the tolerance,
+    /// which is only meaningful for numbers close to 1.
+    Absolute(f64),
+    /// Equal when they differ by at most the tolerance times the largest magnitude,
+    /// 'Relative(1e-9)' ignores the digits after the ninth.
+    Relative(f64),
+    /// Equal when at most this many floats lie between them.
+    Ulps(u64),
+}
+
+impl Default for EqualityMode {
+    fn default() -> Self {
+        EqualityMode::Absolute(f64::EPSILON)
+    }
+}
+
+impl EqualityMode {
+    /// NaN is never equal to anything, and an infinity only to itself.
+    pub fn equals(self, left: f64, right: f64) -> bool {
+        if left == right {
+            return true;
+        }
+        if !left.is_finite() || !right.is_finite() {
+            return false;
+        }
+
+        let difference = (left - right).abs();
+        match self {
+            EqualityMode::Exact => false,
+            EqualityMode::Absolute(tolerance) => difference <= tolerance,
+            EqualityMode::Relative(tolerance) => {
+                difference <= tolerance * left.abs().max(right.abs())
+            }
+            EqualityMode::Ulps(max_distance) => ulps_between(left, right) <= max_distance,
+        }
+    }
+}
+
+/// Equality used by the 'approx' built-in: absolute below 1 and relative above,
+/// so that 'approx(x, y, 1e-9)' works at any magnitude.
+pub fn approx(left: f64, right: f64, tolerance: f64) -> bool {
+    let scale = left.abs().max(right.abs()).max(1.0);
+    EqualityMode::Absolute(tolerance * scale).equals(left, right)
+}
+
+/// The number of floats from 'left' to 'right'.
+fn ulps_between(left: f64, right: f64) -> u64 {
+    (ordered_bits(left) - ordered_bits(right)).unsigned_abs() as u64
+}
+
+/// Maps the floats to integers in the same order, with consecutive floats
+/// mapped to consecutive integers and both zeros to 0.
+fn ordered_bits(num: f64) -> i128 {
+    let bits = num.to_bits() as i64;
+    if bits < 0 {
+        i128::from(i64::MIN) - i128::from(bits)
+    } else {
+        i128::from(bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact() {
+        assert!(EqualityMode::Exact.equals(0.0, -0.0));
+        assert!(!EqualityMode::Exact.equals(0.1 + 0.2, 0.3));
+        assert!(!EqualityMode::Exact.equals(f64::NAN, f64::NAN));
+    }
+
+    #[test]
+    fn test_absolute() {
+        assert!(EqualityMode::default().equals(0.1 + 0.2, 0.3));
+        assert!(!EqualityMode::default().equals(1e20, 1e20 + 1e5));
+        assert!(EqualityMode::Absolute(0.5).equals(1.0, 1.4));
+    }
+
+    #[test]
+    fn test_relative() {
+        let mode = EqualityMode::Relative(1e-9);
+        assert!(mode.equals(1e20, 1e20 + 1e5));
+        assert!(!mode.equals(1e-20, 2e-20));
+        assert!(!mode.equals(f64::INFINITY, f64::MAX));
+        assert!(mode.equals(f64::INFINITY, f64::INFINITY));
+    }
+
+    #[test]
+    fn test_ulps() {
+        let next = f64::from_bits(1.0_f64.to_bits() + 1);
+        assert!(EqualityMode::Ulps(1).equals(1.0, next));
+        assert!(!EqualityMode::Ulps(0).equals(1.0, next));
+        // The distance goes through the zeros.
+        let smallest = f64::from_bits(1);
+        assert_eq!(2, ulps_between(-smallest, smallest));
+        assert!(EqualityMode::Ulps(4).equals(0.1 + 0.2, 0.3));
+    }
+
+    #[test]
+    fn test_approx() {
+        assert!(approx(1e20, 1e20 + 1e5, 1e-9));
+        assert!(approx(1e-12, 0.0, 1e-9));
+        assert!(!approx(1.0, 1.1, 1e-9));
+    }
+}
@@ -1,26 +1,226 @@
+use crate::builtins::{self, Builtin};
+use crate::compiler::{self, Program};
+use crate::complex::Complex;
+use crate::derivative;
+use crate::dual::Dual;
+use crate::equality::{self, EqualityMode};
+use crate::interval::Interval;
+use crate::lexer::Keyword;
 use crate::parser::{Location, NodeType, ParseNode};
-use std::collections::HashMap;
-use std::f64::EPSILON;
+use crate::simplifier;
+use crate::units::{self, Quantity, Unit};
+use crate::value::{Function, Value};
+use crate::vm::{self, Machine};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
 
-#[derive(Debug, PartialEq, Fail)]
+#[derive(Clone, Debug, PartialEq, Fail)]
 pub enum EvalError {
     #[fail(display = "Unimplemented: {}", _0)]
     Unimplemented(String),
     #[fail(display = "Symbol '{}' not found at {:?}", _0, _1)]
     SymbolNotFound(String, Location),
+    #[fail(display = "Cannot assign to reserved word '{}' at {:?}", _0, _1)]
+    ReservedWordAssignment(String, Location),
+    #[fail(display = "Cannot assign to constant '{}' at {:?}", _0, _1)]
+    ConstantAssignment(String, Location),
+    #[fail(display = "Cannot evaluate the invalid expression at {:?}", _0)]
+    InvalidExpression(Location),
+    #[fail(display = "Empty block at {:?} has no value", _0)]
+    EmptyBlock(Location),
+    #[fail(display = "'break' outside of a loop at {:?}", _0)]
+    Break(Location),
+    #[fail(display = "'continue' outside of a loop at {:?}", _0)]
+    Continue(Location),
+    /// A 'break' or 'continue' in a function body, which can't exit the loops of the caller.
+    #[fail(display = "'{}' outside of a loop at {:?}", _0, _1)]
+    LoopExitFromFunction(&'static str, Location),
+    #[fail(display = "Loop at {:?} exceeded the budget of {} iterations", _1, _0)]
+    IterationBudgetExceeded(usize, Location),
+    #[fail(display = "Expected a {} but found a {} at {:?}", _0, _1, _2)]
+    TypeMismatch(&'static str, &'static str, Location),
+    #[fail(
+        display = "Index {} is out of bounds of a list of length {} at {:?}",
+        _0, _1, _2
+    )]
+    IndexOutOfBounds(i64, usize, Location),
+    #[fail(display = "Index {} is not an integer at {:?}", _0, _1)]
+    InvalidIndex(f64, Location),
+    #[fail(
+        display = "'{}' expects {} argument(s) but got {} at {:?}",
+        _0, _1, _2, _3
+    )]
+    ArgumentCount(String, usize, usize, Location),
+    #[fail(display = "'{}' of an empty list at {:?}", _0, _1)]
+    EmptyList(&'static str, Location),
+    #[fail(display = "Call at {:?} exceeded the depth of {} calls", _1, _0)]
+    CallDepthExceeded(usize, Location),
+    #[fail(display = "Record has no field '{}' at {:?}", _0, _1)]
+    MissingField(String, Location),
+    #[fail(display = "Division by zero at {:?}", _0)]
+    DivisionByZero(Location),
+    #[fail(display = "Operation at {:?} has the non-finite result {}", _1, _0)]
+    NonFiniteResult(f64, Location),
+    #[fail(display = "Cannot differentiate {} at {:?}", _0, _1)]
+    NotDifferentiable(String, Location),
+    #[fail(display = "Incompatible units '{}' and '{}' at {:?}", _0, _1, _2)]
+    IncompatibleUnits(String, String, Location),
+    #[fail(display = "Cannot raise '{}' to the power {} at {:?}", _0, _1, _2)]
+    InvalidUnitPower(String, f64, Location),
+    #[fail(display = "Cannot order the complex number {} at {:?}", _0, _1)]
+    ComplexOrdering(String, Location),
+    #[fail(display = "Invalid interval [{}, {}] at {:?}", _0, _1, _2)]
+    InvalidInterval(f64, f64, Location),
+    /// A range whose bounds aren't integers that a 'f64' counts exactly.
+    #[fail(display = "Invalid range {}..{} at {:?}", _0, _1, _2)]
+    InvalidRange(f64, f64, Location),
+    #[fail(display = "List at {:?} exceeds the limit of {} elements", _1, _0)]
+    ListTooLong(usize, Location),
 }
 
-type EvalResult = Result<f64, EvalError>;
-type SymbolTable = HashMap<String, f64>;
+/// How the evaluator treats the operations without a finite result.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum NumericPolicy {
+    /// Infinities and NaN are results like any other, '1 / 0' is 'inf'.
+    Ieee,
+    /// An operation resulting in an infinity or NaN is an error,
+    /// 'EvalError::DivisionByZero' for a division by zero.
+    RejectNonFinite,
+    /// Only a division by zero is an error, 'INF + 1' is still 'inf'.
+    RejectDivisionByZero,
+}
+
+impl NumericPolicy {
+    /// Applies the policy to the result of the operation at 'location'.
+    pub(crate) fn check(self, res: f64, location: Location) -> Result<f64, EvalError> {
+        if !res.is_finite() && self == NumericPolicy::RejectNonFinite {
+            Err(EvalError::NonFiniteResult(res, location))
+        } else {
+            Ok(res)
+        }
+    }
+
+    /// Divides under the policy, for the division at 'location'.
+    pub(crate) fn divide(
+        self,
+        left: f64,
+        right: f64,
+        location: Location,
+    ) -> Result<f64, EvalError> {
+        if right == 0.0 && self != NumericPolicy::Ieee {
+            return Err(EvalError::DivisionByZero(location));
+        }
+
+        self.check(left / right, location)
+    }
+}
+
+/// Iterations of all the loops run by a single call to 'EvalContext::eval'.
+const DEFAULT_ITERATION_BUDGET: usize = 1_000_000;
+
+/// Nested calls allowed before giving up on a runaway recursion.
+const MAX_CALL_DEPTH: usize = 64;
+
+/// Elements of the list built from a range, which loops iterate without building it.
+const MAX_LIST_LENGTH: usize = 1_000_000;
+
+/// The largest bound of a range, beyond which adding 1 doesn't change every 'f64'.
+const MAX_RANGE_BOUND: f64 = 9_007_199_254_740_992.0;
+
+type EvalResult = Result<Value, EvalError>;
+type SymbolTable = HashMap<String, Value>;
+/// The constants of a scope, with the location of their 'const' declaration.
+type ConstantTable = HashMap<String, (Value, Option<Location>)>;
+type Quantities<'v> = (Cow<'v, Quantity>, Cow<'v, Quantity>);
+
+/// The numbers given to a built-in, as intervals, quantities or complex numbers
+/// when one of them is.
+enum Numbers {
+    Duals(Vec<Dual>),
+    Intervals(Vec<Interval>),
+    Quantities(Vec<Quantity>),
+    Complexes(Vec<Complex>),
+}
+
+/// An arithmetic operation, for each kind of number.
+struct Arithmetic {
+    dual: fn(&Dual, &Dual) -> Dual,
+    interval: fn(Interval, Interval) -> Interval,
+    quantity: fn(&Quantity, &Quantity, Location) -> Result<Quantity, EvalError>,
+    complex: fn(Complex, Complex) -> Complex,
+}
+
+const SUM: Arithmetic = Arithmetic {
+    dual: Dual::add,
+    interval: Interval::add,
+    quantity: Quantity::add,
+    complex: Complex::add,
+};
+
+const SUBSTRACTION: Arithmetic = Arithmetic {
+    dual: Dual::substract,
+    interval: Interval::substract,
+    quantity: Quantity::substract,
+    complex: Complex::substract,
+};
+
+const MULTIPLICATION: Arithmetic = Arithmetic {
+    dual: Dual::multiply,
+    interval: Interval::multiply,
+    quantity: Quantity::multiply,
+    complex: Complex::multiply,
+};
+
+const POWER: Arithmetic = Arithmetic {
+    dual: Dual::power,
+    interval: Interval::power,
+    quantity: Quantity::power,
+    complex: Complex::power,
+};
 
 pub struct EvalContext {
-    syms: SymbolTable,
+    /// - scopes. The symbols of the enclosing blocks, innermost last.
+    ///           The first one holds the global symbols.
+    scopes: Vec<SymbolTable>,
+
+    /// - iteration_budget. The iterations allowed to the loops
+    ///                     evaluated by a single call to 'eval'.
+    iteration_budget: usize,
+
+    /// - iterations.       The iterations run by the current call to 'eval'.
+    iterations: usize,
+
+    /// - call_depth. The calls to user functions being evaluated.
+    call_depth: usize,
+
+    /// - numeric_policy. What to do with an operation without a finite result.
+    numeric_policy: NumericPolicy,
+
+    /// - equality. How '==' and '!=' compare numbers.
+    equality: EqualityMode,
+
+    /// - constants. The symbols that can't be reassigned, built-in or declared with 'const',
+    ///              by scope like 'scopes'. They hide the variables of the same name.
+    constants: Vec<ConstantTable>,
+}
+
+impl Default for EvalContext {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl EvalContext {
     pub fn new() -> Self {
         EvalContext {
-            syms: HashMap::new(),
+            scopes: vec![HashMap::new()],
+            iteration_budget: DEFAULT_ITERATION_BUDGET,
+            iterations: 0,
+            call_depth: 0,
+            numeric_policy: NumericPolicy::Ieee,
+            equality: EqualityMode::default(),
+            constants: vec![HashMap::new()],
         }
     }
 
@@ -30,41 +230,346 @@ impl EvalContext {
         ctx
     }
 
+    /// Limits the iterations of the loops run by every call to 'eval',
+    /// which fails with 'EvalError::IterationBudgetExceeded' beyond it.
+    pub fn set_iteration_budget(&mut self, budget: usize) {
+        self.iteration_budget = budget;
+    }
+
+    pub fn set_numeric_policy(&mut self, policy: NumericPolicy) {
+        self.numeric_policy = policy;
+    }
+
+    pub fn set_equality(&mut self, mode: EqualityMode) {
+        self.equality = mode;
+    }
+
+    /// Binds 'name' to 'value' in the global symbols, to pass data like records to the formulas.
+    pub fn bind(&mut self, name: &str, value: impl Into<Value>) {
+        self.scopes[0].insert(name.to_string(), value.into());
+    }
+
     pub fn eval(&mut self, node: &ParseNode) -> EvalResult {
+        self.reset_iterations();
+        self.eval_node(node)
+    }
+
+    /// Evaluates 'node' along with its partial derivatives with respect to 'variables',
+    /// in a single pass where each variable is a dual number seeded with its own derivative.
+    /// The variables must hold numbers, and the ones set by 'node' aren't kept.
+    pub fn eval_gradient(
+        &mut self,
+        node: &ParseNode,
+        variables: &[&str],
+    ) -> Result<(f64, Vec<f64>), EvalError> {
+        let globals = self.scopes[0].clone();
+        let constants = self.constants.clone();
+        let res = self.eval_seeded(node, variables);
+        self.scopes[0] = globals;
+        self.constants = constants;
+
+        match res? {
+            Value::Number(num) => Ok((num, vec![0.0; variables.len()])),
+            Value::Dual(dual) => {
+                let derivatives = (0..variables.len())
+                    .map(|index| dual.derivative(index))
+                    .collect();
+                Ok((dual.value, derivatives))
+            }
+            val => Err(EvalError::TypeMismatch(
+                "number",
+                val.type_name(),
+                node.location,
+            )),
+        }
+    }
+
+    fn eval_seeded(&mut self, node: &ParseNode, variables: &[&str]) -> EvalResult {
+        for (index, variable) in variables.iter().enumerate() {
+            let val = self
+                .lookup(variable)
+                .ok_or_else(|| EvalError::SymbolNotFound(variable.to_string(), node.location))?;
+            let num = Self::as_number(&val, node.location)?;
+            let seed = Dual::variable(num, index, variables.len());
+            self.bind(variable, Value::Dual(Rc::new(seed)));
+        }
+
+        self.eval(node)
+    }
+
+    /// Compiles 'node' to bytecode for 'run', resolving its variables against
+    /// the global symbols and constants defined so far.
+    pub fn compile(&self, node: &ParseNode) -> Result<Program, EvalError> {
+        compiler::compile(self, node)
+    }
+
+    /// Runs a program compiled by 'compile', with the same results as 'eval'.
+    pub fn run(&mut self, program: &Program) -> EvalResult {
+        vm::run(self, program)
+    }
+
+    /// Folds the constant operations of 'node' and drops its dead assignments, for
+    /// the constants, numeric policy and equality mode of the context.
+    pub fn simplify(&self, node: &ParseNode) -> ParseNode {
+        simplifier::simplify(self, node)
+    }
+
+    /// Differentiates the expression 'node' with respect to 'variable', see 'simplify'
+    /// for the simplification of the derivative.
+    pub fn differentiate(&self, node: &ParseNode, variable: &str) -> Result<ParseNode, EvalError> {
+        derivative::differentiate(self, node, variable)
+    }
+
+    fn eval_node(&mut self, node: &ParseNode) -> EvalResult {
         use NodeType::*;
         match &node.ntype {
             Root(nodes) => {
                 let mut res = None;
                 for node in nodes {
-                    res = Some(self.eval(node)?);
+                    res = Some(self.eval_node(node)?);
                 }
 
-                Ok(res.unwrap())
+                res.ok_or(EvalError::EmptyBlock(node.location))
+            }
+            Number(num) => self.apply_policy(*num, node.location).map(Value::Number),
+            List(nodes) => nodes
+                .iter()
+                .map(|node| self.eval_node(node))
+                .collect::<Result<_, _>>()
+                .map(Value::list),
+            Record(fields) => fields
+                .iter()
+                .map(|(name, node)| self.eval_node(node).map(|val| (name.clone(), val)))
+                .collect::<Result<BTreeMap<_, _>, _>>()
+                .map(|fields| Value::Record(Rc::new(fields))),
+            Error => Err(EvalError::InvalidExpression(node.location)),
+            Quantity(num, unit) => {
+                let num = self.apply_policy(*num, node.location)?;
+                Ok(units::Quantity::new(num, unit.clone()).into())
+            }
+            Imaginary(num) => {
+                let num = self.apply_policy(*num, node.location)?;
+                Ok(Complex::new(0.0, num).into())
+            }
+            Sum(left, right) => self.perform_arithmetic_op(left, right, node.location, &SUM),
+            Substraction(left, right) => {
+                self.perform_arithmetic_op(left, right, node.location, &SUBSTRACTION)
+            }
+            Multiplication(left, right) => {
+                self.perform_arithmetic_op(left, right, node.location, &MULTIPLICATION)
+            }
+            Division(left, right) => self.perform_division(left, right, node.location),
+            Power(left, right) => self.perform_arithmetic_op(left, right, node.location, &POWER),
+            Negation(child) => match self.eval_node(child)? {
+                Value::Interval(interval) => Ok(Value::Interval(interval.negate())),
+                Value::Quantity(quantity) => Ok(quantity.negate().into()),
+                Value::Complex(num) => Ok(num.negate().into()),
+                val => {
+                    let res = Self::as_dual(&val, child.location)?.negate();
+                    self.check_dual(res, node.location)
+                }
+            },
+            GreaterThan(left, right) => {
+                let ops = (|l, r| l > r, Interval::greater_than, Complex::greater_than);
+                self.perform_comparison_op(left, right, node.location, ops)
             }
-            Number(num) => Ok(*num),
-            Sum(left, right) => self.perform_arithmetic_op(left, right, |l, r| l + r),
-            Substraction(left, right) => self.perform_arithmetic_op(left, right, |l, r| l - r),
-            Multiplication(left, right) => self.perform_arithmetic_op(left, right, |l, r| l * r),
-            Division(left, right) => self.perform_arithmetic_op(left, right, |l, r| l / r),
-            GreaterThan(left, right) => self.perform_comparison_op(left, right, |l, r| l > r),
             GreaterThanOrEqual(left, right) => {
-                self.perform_comparison_op(left, right, |l, r| l >= r)
+                let ops = (
+                    |l, r| l >= r,
+                    Interval::greater_than_or_equal,
+                    Complex::greater_than_or_equal,
+                );
+                self.perform_comparison_op(left, right, node.location, ops)
+            }
+            LessThan(left, right) => {
+                let ops = (|l, r| l < r, Interval::less_than, Complex::less_than);
+                self.perform_comparison_op(left, right, node.location, ops)
+            }
+            LessThanOrEqual(left, right) => {
+                let ops = (
+                    |l, r| l <= r,
+                    Interval::less_than_or_equal,
+                    Complex::less_than_or_equal,
+                );
+                self.perform_comparison_op(left, right, node.location, ops)
             }
-            LessThan(left, right) => self.perform_comparison_op(left, right, |l, r| l < r),
-            LessThanOrEqual(left, right) => self.perform_comparison_op(left, right, |l, r| l <= r),
             Equal(left, right) => {
-                self.perform_comparison_op(left, right, |l, r| (l - r).abs() < EPSILON)
+                let mode = self.equality;
+                let ops = (
+                    |l, r| mode.equals(l, r),
+                    |l, r| Interval::equal(l, r, mode),
+                    |l, r| Complex::equal(l, r, mode),
+                );
+                self.perform_comparison_op(left, right, node.location, ops)
+            }
+            NotEqual(left, right) => {
+                let mode = self.equality;
+                let ops = (
+                    |l, r| !mode.equals(l, r),
+                    |l, r| Interval::equal(l, r, mode).map(|equal| !equal),
+                    |l, r| Complex::equal(l, r, mode).map(|equal| !equal),
+                );
+                self.perform_comparison_op(left, right, node.location, ops)
+            }
+            Conversion(child, unit) => {
+                let val = self.eval_node(child)?;
+                let quantity = Self::as_quantity(&val, child.location)?;
+                let res = quantity
+                    .convert(unit)
+                    .ok_or_else(|| units::mismatch(&quantity.unit, unit, node.location))?;
+                self.check_quantity(res, node.location)
+            }
+            Bounds(lo, hi) => match (self.eval_number(lo)?, self.eval_number(hi)?) {
+                (lo, hi) if lo <= hi => Ok(Interval::new(lo, hi).into()),
+                (lo, hi) => Err(EvalError::InvalidInterval(lo, hi, node.location)),
+            },
+            Assignment(identifier, _)
+            | Let(identifier, _)
+            | Const(identifier, _)
+            | Function(identifier, _, _)
+                if Keyword::from_word(identifier).is_some() =>
+            {
+                Err(EvalError::ReservedWordAssignment(
+                    identifier.clone(),
+                    node.location,
+                ))
+            }
+            Assignment(identifier, _) | Let(identifier, _) | Function(identifier, _, _)
+                if self.constant(identifier).is_some() =>
+            {
+                Err(EvalError::ConstantAssignment(
+                    identifier.clone(),
+                    node.location,
+                ))
+            }
+            // Running the same declaration again, like in a loop, isn't a reassignment.
+            Const(identifier, _)
+                if self
+                    .declaration(identifier)
+                    .is_some_and(|declaration| declaration != Some(node.location)) =>
+            {
+                Err(EvalError::ConstantAssignment(
+                    identifier.clone(),
+                    node.location,
+                ))
             }
             Assignment(identifier, right) => {
-                let val = self.eval(right)?;
-                self.syms.insert(identifier.clone(), val);
+                let val = self.eval_node(right)?;
+                self.assign(identifier, val.clone());
+                Ok(val)
+            }
+            Let(identifier, right) => {
+                let val = self.eval_node(right)?;
+                self.innermost_scope()
+                    .insert(identifier.clone(), val.clone());
                 Ok(val)
             }
-            Identifier(identifier) => self
-                .syms
-                .get(identifier)
-                .copied()
-                .ok_or_else(|| EvalError::SymbolNotFound(identifier.clone(), node.location)),
+            Const(identifier, right) => {
+                let val = self.eval_node(right)?;
+                self.constants
+                    .last_mut()
+                    .unwrap()
+                    .insert(identifier.clone(), (val.clone(), Some(node.location)));
+                Ok(val)
+            }
+            Function(name, params, body) => {
+                let val = Value::Function(Rc::new(crate::value::Function {
+                    name: name.clone(),
+                    params: params.clone(),
+                    body: (**body).clone(),
+                }));
+                self.innermost_scope().insert(name.clone(), val.clone());
+                Ok(val)
+            }
+            Call(name, args) => {
+                let callee = self.eval_identifier(name, node.location)?;
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval_node(arg).map(|val| (val, arg.location)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.call(&callee, args, node.location)
+            }
+            Index(list, index) => {
+                let values = self.eval_list(list)?;
+                let position = self.eval_number(index)?;
+                Self::get_index(&values, position, index.location).cloned()
+            }
+            Field(record, name) => match self.eval_node(record)? {
+                Value::Record(fields) => fields
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| EvalError::MissingField(name.clone(), node.location)),
+                val => Err(EvalError::TypeMismatch(
+                    "record",
+                    val.type_name(),
+                    record.location,
+                )),
+            },
+            Range(start, end) => {
+                let (start, end) = self.eval_range(start, end, node.location)?;
+                if end - start > MAX_LIST_LENGTH as f64 {
+                    return Err(EvalError::ListTooLong(MAX_LIST_LENGTH, node.location));
+                }
+
+                Ok(Value::list(Self::range(start, end).collect()))
+            }
+            Block(nodes) => {
+                self.push_scope(HashMap::new());
+                let res = nodes
+                    .iter()
+                    .try_fold(None, |_, node| self.eval_node(node).map(Some));
+                self.pop_scope();
+                res?.ok_or(EvalError::EmptyBlock(node.location))
+            }
+            While(condition, body) => {
+                let mut res = Value::Number(0.0);
+                loop {
+                    // A 'break' or 'continue' in the condition exits or retries this loop.
+                    match self.eval_number(condition) {
+                        Ok(0.0) => break,
+                        Ok(_) => (),
+                        Err(EvalError::Break(_)) => break,
+                        Err(EvalError::Continue(_)) => {
+                            self.count_iteration(node.location)?;
+                            continue;
+                        }
+                        Err(error) => return Err(error),
+                    }
+                    self.count_iteration(node.location)?;
+                    match self.eval_node(body) {
+                        Ok(val) => res = val,
+                        Err(EvalError::Break(_)) => break,
+                        Err(EvalError::Continue(_)) => continue,
+                        Err(error) => return Err(error),
+                    }
+                }
+
+                Ok(res)
+            }
+            For(variable, iterable, body) => {
+                // Ranges are iterated without building the list of their values.
+                let values: Box<dyn Iterator<Item = Value>> = match &iterable.ntype {
+                    Range(start, end) => {
+                        let (start, end) = self.eval_range(start, end, iterable.location)?;
+                        Box::new(Self::range(start, end))
+                    }
+                    _ => {
+                        let values = self.eval_list(iterable)?;
+                        Box::new((0..values.len()).map(move |index| values[index].clone()))
+                    }
+                };
+                self.push_scope(HashMap::new());
+                let res = self.eval_for(variable, values, body, node.location);
+                self.pop_scope();
+                res
+            }
+            Break => Err(EvalError::Break(node.location)),
+            Continue => Err(EvalError::Continue(node.location)),
+            // Constants like 'INF' and the numbers bound to the context follow the policy too.
+            Identifier(identifier) => match self.eval_identifier(identifier, node.location)? {
+                Value::Number(num) => self.apply_policy(num, node.location).map(Value::Number),
+                val => Ok(val),
+            },
         }
     }
 
@@ -86,37 +591,737 @@ impl EvalContext {
         Ok(())
     }
 
-    fn perform_arithmetic_op<F>(
+    fn eval_identifier(&self, identifier: &str, location: Location) -> EvalResult {
+        self.lookup(identifier)
+            .or_else(|| Builtin::from_name(identifier).map(Value::Builtin))
+            .ok_or_else(|| EvalError::SymbolNotFound(identifier.to_string(), location))
+    }
+
+    fn eval_number(&mut self, node: &ParseNode) -> Result<f64, EvalError> {
+        let val = self.eval_node(node)?;
+        Self::as_number(&val, node.location)
+    }
+
+    fn eval_list(&mut self, node: &ParseNode) -> Result<Rc<Vec<Value>>, EvalError> {
+        let val = self.eval_node(node)?;
+        Self::as_list(&val, node.location)
+    }
+
+    /// The value of a number, without the derivatives of a dual number.
+    fn as_number(val: &Value, location: Location) -> Result<f64, EvalError> {
+        match val {
+            Value::Number(num) => Ok(*num),
+            Value::Dual(dual) => Ok(dual.value),
+            _ => Err(EvalError::TypeMismatch("number", val.type_name(), location)),
+        }
+    }
+
+    /// A number as a dual number, borrowed if it already is one.
+    fn as_dual(val: &Value, location: Location) -> Result<Cow<'_, Dual>, EvalError> {
+        match val {
+            Value::Number(num) => Ok(Cow::Owned(Dual::constant(*num))),
+            Value::Dual(dual) => Ok(Cow::Borrowed(dual)),
+            _ => Err(EvalError::TypeMismatch("number", val.type_name(), location)),
+        }
+    }
+
+    /// A number as an interval, which holds only that number.
+    fn as_interval(val: &Value, location: Location) -> Result<Interval, EvalError> {
+        match val {
+            Value::Number(num) => Ok(Interval::point(*num)),
+            Value::Interval(interval) => Ok(*interval),
+            _ => Err(EvalError::TypeMismatch("number", val.type_name(), location)),
+        }
+    }
+
+    /// The operands as intervals, if one of them is an interval.
+    fn as_intervals(
+        left: (&Value, Location),
+        right: (&Value, Location),
+    ) -> Result<Option<(Interval, Interval)>, EvalError> {
+        match (left.0, right.0) {
+            (Value::Interval(_), _) | (_, Value::Interval(_)) => Ok(Some((
+                Self::as_interval(left.0, left.1)?,
+                Self::as_interval(right.0, right.1)?,
+            ))),
+            _ => Ok(None),
+        }
+    }
+
+    /// A number as a quantity without unit, borrowed if it already is a quantity.
+    fn as_quantity(val: &Value, location: Location) -> Result<Cow<'_, Quantity>, EvalError> {
+        match val {
+            Value::Number(num) => Ok(Cow::Owned(Quantity::new(*num, Unit::none()))),
+            Value::Quantity(quantity) => Ok(Cow::Borrowed(quantity)),
+            _ => Err(EvalError::TypeMismatch("number", val.type_name(), location)),
+        }
+    }
+
+    /// The operands as quantities, if one of them is a quantity.
+    fn as_quantities<'v>(
+        left: (&'v Value, Location),
+        right: (&'v Value, Location),
+    ) -> Result<Option<Quantities<'v>>, EvalError> {
+        match (left.0, right.0) {
+            (Value::Quantity(_), _) | (_, Value::Quantity(_)) => Ok(Some((
+                Self::as_quantity(left.0, left.1)?,
+                Self::as_quantity(right.0, right.1)?,
+            ))),
+            _ => Ok(None),
+        }
+    }
+
+    /// A number as a complex number, with a null imaginary part.
+    fn as_complex(val: &Value, location: Location) -> Result<Complex, EvalError> {
+        match val {
+            Value::Number(num) => Ok(Complex::new(*num, 0.0)),
+            Value::Complex(num) => Ok(*num),
+            _ => Err(EvalError::TypeMismatch("number", val.type_name(), location)),
+        }
+    }
+
+    /// The operands as complex numbers, if one of them is a complex number.
+    fn as_complexes(
+        left: (&Value, Location),
+        right: (&Value, Location),
+    ) -> Result<Option<(Complex, Complex)>, EvalError> {
+        match (left.0, right.0) {
+            (Value::Complex(_), _) | (_, Value::Complex(_)) => Ok(Some((
+                Self::as_complex(left.0, left.1)?,
+                Self::as_complex(right.0, right.1)?,
+            ))),
+            _ => Ok(None),
+        }
+    }
+
+    fn as_list(val: &Value, location: Location) -> Result<Rc<Vec<Value>>, EvalError> {
+        match val {
+            Value::List(values) => Ok(Rc::clone(values)),
+            _ => Err(EvalError::TypeMismatch("list", val.type_name(), location)),
+        }
+    }
+
+    /// Returns the value at 'position', counting from the end of the list when negative.
+    fn get_index(values: &[Value], position: f64, location: Location) -> Result<&Value, EvalError> {
+        if position.fract() != 0.0 || !position.is_finite() {
+            return Err(EvalError::InvalidIndex(position, location));
+        }
+
+        let position = position as i64;
+        let len = values.len() as i64;
+        let index = if position < 0 {
+            position + len
+        } else {
+            position
+        };
+        if (0..len).contains(&index) {
+            Ok(&values[index as usize])
+        } else {
+            Err(EvalError::IndexOutOfBounds(
+                position,
+                values.len(),
+                location,
+            ))
+        }
+    }
+
+    /// The successive integers from 'start', inclusive, to 'end', exclusive.
+    fn eval_range(
+        &mut self,
+        start: &ParseNode,
+        end: &ParseNode,
+        location: Location,
+    ) -> Result<(f64, f64), EvalError> {
+        let start = self.eval_number(start)?;
+        let end = self.eval_number(end)?;
+        Self::check_range(start, end, location)?;
+        Ok((start, end))
+    }
+
+    /// Ranges go from an integer to another, both small enough to be counted one by one.
+    pub(crate) fn check_range(start: f64, end: f64, location: Location) -> Result<(), EvalError> {
+        let valid = |bound: f64| bound.fract() == 0.0 && bound.abs() <= MAX_RANGE_BOUND;
+        if valid(start) && valid(end) {
+            Ok(())
+        } else {
+            Err(EvalError::InvalidRange(start, end, location))
+        }
+    }
+
+    fn range(start: f64, end: f64) -> impl Iterator<Item = Value> {
+        std::iter::successors(Some(start), |index| Some(index + 1.0))
+            .take_while(move |index| *index < end)
+            .map(Value::Number)
+    }
+
+    /// Calls 'callee' with 'args', each paired with the location of its expression.
+    fn call(
+        &mut self,
+        callee: &Value,
+        args: Vec<(Value, Location)>,
+        location: Location,
+    ) -> EvalResult {
+        match callee {
+            Value::Function(function) => self.call_function(function, args, location),
+            Value::Builtin(builtin) => self.call_builtin(*builtin, args, location),
+            _ => Err(EvalError::TypeMismatch(
+                "function",
+                callee.type_name(),
+                location,
+            )),
+        }
+    }
+
+    /// Evaluates the body of 'function' with the global symbols and its parameters,
+    /// leaving out the scopes of the caller.
+    fn call_function(
+        &mut self,
+        function: &Function,
+        args: Vec<(Value, Location)>,
+        location: Location,
+    ) -> EvalResult {
+        if args.len() != function.params.len() {
+            return Err(EvalError::ArgumentCount(
+                function.name.clone(),
+                function.params.len(),
+                args.len(),
+                location,
+            ));
+        }
+        if self.call_depth >= MAX_CALL_DEPTH {
+            return Err(EvalError::CallDepthExceeded(MAX_CALL_DEPTH, location));
+        }
+
+        let params = function
+            .params
+            .iter()
+            .cloned()
+            .zip(args.into_iter().map(|(val, _)| val))
+            .collect();
+        let caller_scopes = self.scopes.split_off(1);
+        let caller_constants = self.constants.split_off(1);
+        self.push_scope(params);
+        self.call_depth += 1;
+        let res = self.eval_node(&function.body).map_err(|error| match error {
+            EvalError::Break(location) => EvalError::LoopExitFromFunction("break", location),
+            EvalError::Continue(location) => EvalError::LoopExitFromFunction("continue", location),
+            error => error,
+        });
+        self.call_depth -= 1;
+        self.scopes.truncate(1);
+        self.scopes.extend(caller_scopes);
+        self.constants.truncate(1);
+        self.constants.extend(caller_constants);
+        res
+    }
+
+    fn call_builtin(
+        &mut self,
+        builtin: Builtin,
+        args: Vec<(Value, Location)>,
+        location: Location,
+    ) -> EvalResult {
+        let expected = match builtin {
+            Builtin::Map => 2,
+            Builtin::Approx => 3,
+            _ => 1,
+        };
+        let variadic = builtin == Builtin::Min || builtin == Builtin::Max;
+        if args.len() != expected && !(variadic && args.len() > expected) {
+            return Err(EvalError::ArgumentCount(
+                builtin.name().to_string(),
+                expected,
+                args.len(),
+                location,
+            ));
+        }
+
+        // 'min' and 'max' take either a list or several numbers.
+        let numbers = |args: &[(Value, Location)]| -> Result<Numbers, EvalError> {
+            let values = match args {
+                [(val, location)] => Self::as_list(val, *location)?
+                    .iter()
+                    .map(|val| (val.clone(), *location))
+                    .collect(),
+                _ => args.to_vec(),
+            };
+            if values
+                .iter()
+                .any(|(val, _)| matches!(val, Value::Interval(_)))
+            {
+                let intervals = values
+                    .iter()
+                    .map(|(val, location)| Self::as_interval(val, *location))
+                    .collect::<Result<_, _>>()?;
+                return Ok(Numbers::Intervals(intervals));
+            }
+            if values
+                .iter()
+                .any(|(val, _)| matches!(val, Value::Quantity(_)))
+            {
+                let quantities = values
+                    .iter()
+                    .map(|(val, location)| Self::as_quantity(val, *location).map(Cow::into_owned))
+                    .collect::<Result<_, _>>()?;
+                return Ok(Numbers::Quantities(quantities));
+            }
+            if values
+                .iter()
+                .any(|(val, _)| matches!(val, Value::Complex(_)))
+            {
+                let complexes = values
+                    .iter()
+                    .map(|(val, location)| Self::as_complex(val, *location))
+                    .collect::<Result<_, _>>()?;
+                return Ok(Numbers::Complexes(complexes));
+            }
+            values
+                .iter()
+                .map(|(val, location)| Self::as_dual(val, *location).map(Cow::into_owned))
+                .collect::<Result<_, _>>()
+                .map(Numbers::Duals)
+        };
+        let sum = |numbers: &[Dual]| {
+            numbers
+                .iter()
+                .fold(Dual::constant(0.0), |sum, num| Dual::add(&sum, num))
+        };
+        let interval_sum = |intervals: &[Interval]| {
+            intervals
+                .iter()
+                .fold(Interval::point(0.0), |sum, &num| Interval::add(sum, num))
+        };
+        let complex_sum = |complexes: &[Complex]| {
+            complexes
+                .iter()
+                .fold(Complex::new(0.0, 0.0), |sum, &num| Complex::add(sum, num))
+        };
+        // The quantities hold at least one quantity, in whose unit they are summed.
+        let quantity_sum = |quantities: &[Quantity]| {
+            quantities
+                .iter()
+                .skip(1)
+                .try_fold(quantities[0].clone(), |sum, num| {
+                    Quantity::add(&sum, num, location)
+                })
+        };
+
+        let res = match builtin {
+            Builtin::Len => Dual::constant(Self::as_list(&args[0].0, args[0].1)?.len() as f64),
+            Builtin::Sum => match numbers(&args)? {
+                Numbers::Duals(numbers) => sum(&numbers),
+                Numbers::Intervals(intervals) => {
+                    return self.check_interval(interval_sum(&intervals), location)
+                }
+                Numbers::Quantities(quantities) => {
+                    return self.check_quantity(quantity_sum(&quantities)?, location)
+                }
+                Numbers::Complexes(complexes) => {
+                    return self.check_complex(complex_sum(&complexes), location)
+                }
+            },
+            Builtin::Avg => match numbers(&args)? {
+                Numbers::Duals(numbers) if !numbers.is_empty() => {
+                    Dual::divide(&sum(&numbers), &Dual::constant(numbers.len() as f64))
+                }
+                Numbers::Intervals(intervals) => {
+                    let count = Interval::point(intervals.len() as f64);
+                    let res = Interval::divide(interval_sum(&intervals), count);
+                    return self.check_interval(res, location);
+                }
+                Numbers::Quantities(quantities) => {
+                    let sum = quantity_sum(&quantities)?;
+                    let res = Quantity::new(sum.value / quantities.len() as f64, sum.unit);
+                    return self.check_quantity(res, location);
+                }
+                Numbers::Complexes(complexes) => {
+                    let count = Complex::new(complexes.len() as f64, 0.0);
+                    let res = Complex::divide(complex_sum(&complexes), count);
+                    return self.check_complex(res, location);
+                }
+                Numbers::Duals(_) => return Err(EvalError::EmptyList(builtin.name(), location)),
+            },
+            Builtin::Min | Builtin::Max => {
+                let numbers = match numbers(&args)? {
+                    Numbers::Duals(numbers) => numbers,
+                    Numbers::Intervals(intervals) => {
+                        let bound = if builtin == Builtin::Min {
+                            Interval::min
+                        } else {
+                            Interval::max
+                        };
+                        let res = intervals.into_iter().reduce(bound).unwrap();
+                        return self.check_interval(res, location);
+                    }
+                    Numbers::Quantities(quantities) => {
+                        let mut kept = quantities[0].clone();
+                        for num in &quantities[1..] {
+                            let value = kept.align(num, location)?;
+                            let replaces = if builtin == Builtin::Min {
+                                value < kept.value
+                            } else {
+                                value > kept.value
+                            };
+                            if replaces || kept.value.is_nan() {
+                                kept = num.clone();
+                            }
+                        }
+                        return self.check_quantity(kept, location);
+                    }
+                    // Real complex numbers are ordered by their real part.
+                    Numbers::Complexes(complexes) => {
+                        if let Some(num) = complexes.iter().find(|num| !num.is_real()) {
+                            return Err(EvalError::ComplexOrdering(num.to_string(), location));
+                        }
+                        complexes.iter().map(|num| Dual::constant(num.re)).collect()
+                    }
+                };
+                // Like 'f64::min' and 'f64::max', NaN is ignored.
+                numbers
+                    .into_iter()
+                    .reduce(|kept, num| {
+                        let replaces = if builtin == Builtin::Min {
+                            num.value < kept.value
+                        } else {
+                            num.value > kept.value
+                        };
+                        if replaces || kept.value.is_nan() {
+                            num
+                        } else {
+                            kept
+                        }
+                    })
+                    .ok_or(EvalError::EmptyList(builtin.name(), location))?
+            }
+            Builtin::Approx => {
+                let numbers = args
+                    .iter()
+                    .map(|(val, location)| Self::as_number(val, *location))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let equal = equality::approx(numbers[0], numbers[1], numbers[2]);
+                Dual::constant(if equal { 1.0 } else { 0.0 })
+            }
+            Builtin::Sqrt
+            | Builtin::Exp
+            | Builtin::Ln
+            | Builtin::Sin
+            | Builtin::Cos
+            | Builtin::Tan
+            | Builtin::Abs
+            | Builtin::Arg
+            | Builtin::Conj => {
+                match &args[0].0 {
+                    Value::Interval(interval) => {
+                        return self.check_interval(interval.apply(builtin), location)
+                    }
+                    // The square root of an area is a length.
+                    Value::Quantity(quantity) if builtin == Builtin::Sqrt => {
+                        let half = Quantity::new(0.5, Unit::none());
+                        let res = Quantity::power(quantity, &half, location)?;
+                        return self.check_quantity(res, location);
+                    }
+                    Value::Quantity(quantity)
+                        if builtin == Builtin::Abs || builtin == Builtin::Conj =>
+                    {
+                        let math = builtin.math().unwrap();
+                        let res = Quantity::new(math(quantity.value), quantity.unit.clone());
+                        return self.check_quantity(res, location);
+                    }
+                    Value::Complex(num) if builtin == Builtin::Abs => {
+                        return self.check_dual(Dual::constant(num.abs()), location)
+                    }
+                    Value::Complex(num) if builtin == Builtin::Arg => {
+                        return self.check_dual(Dual::constant(num.arg()), location)
+                    }
+                    Value::Complex(num) => {
+                        if let Some(res) = num.apply(builtin) {
+                            return self.check_complex(res, location);
+                        }
+                    }
+                    _ => {}
+                }
+                let num = Self::as_dual(&args[0].0, args[0].1)?;
+                let res = num.apply(builtin.math().unwrap(), builtin.math_derivative().unwrap());
+                return self.check_dual(res, location);
+            }
+            Builtin::Map => {
+                let (function, function_location) = &args[0];
+                let values = Self::as_list(&args[1].0, args[1].1)?;
+                let values = values
+                    .iter()
+                    .map(|val| {
+                        self.call(function, vec![(val.clone(), args[1].1)], *function_location)
+                    })
+                    .collect::<Result<_, _>>()?;
+                return Ok(Value::list(values));
+            }
+        };
+
+        self.check_dual(res, location)
+    }
+
+    /// Operations are computed on dual numbers, which are plain numbers when no operand
+    /// carries derivatives, or on intervals, quantities or complex numbers when an operand
+    /// is one.
+    fn perform_arithmetic_op(
         &mut self,
         left_child: &ParseNode,
         right_child: &ParseNode,
-        op: F,
-    ) -> EvalResult
-    where
-        F: FnOnce(f64, f64) -> f64,
-    {
-        let left_res = self.eval(&left_child)?;
-        let right_res = self.eval(&right_child)?;
-        Ok(op(left_res, right_res))
+        location: Location,
+        op: &Arithmetic,
+    ) -> EvalResult {
+        let left_res = self.eval_node(left_child)?;
+        let right_res = self.eval_node(right_child)?;
+        let operands = (
+            (&left_res, left_child.location),
+            (&right_res, right_child.location),
+        );
+        if let Some((left, right)) = Self::as_intervals(operands.0, operands.1)? {
+            return self.check_interval((op.interval)(left, right), location);
+        }
+        if let Some((left, right)) = Self::as_quantities(operands.0, operands.1)? {
+            let res = (op.quantity)(&left, &right, location)?;
+            return self.check_quantity(res, location);
+        }
+        if let Some((left, right)) = Self::as_complexes(operands.0, operands.1)? {
+            return self.check_complex((op.complex)(left, right), location);
+        }
+        let res = (op.dual)(
+            &*Self::as_dual(&left_res, left_child.location)?,
+            &*Self::as_dual(&right_res, right_child.location)?,
+        );
+        self.check_dual(res, location)
     }
 
-    fn perform_comparison_op<F>(
+    fn perform_division(
         &mut self,
         left_child: &ParseNode,
         right_child: &ParseNode,
-        op: F,
+        location: Location,
+    ) -> EvalResult {
+        let left_res = self.eval_node(left_child)?;
+        let right_res = self.eval_node(right_child)?;
+        let operands = (&left_res, left_child.location);
+        if let Some((left, right)) =
+            Self::as_intervals(operands, (&right_res, right_child.location))?
+        {
+            // Dividing by an interval holding 0 may divide by 0.
+            if right.contains(0.0) {
+                self.divide(left.lo, 0.0, location)?;
+            }
+            return self.check_interval(Interval::divide(left, right), location);
+        }
+        let operands = (&left_res, left_child.location);
+        if let Some((left, right)) =
+            Self::as_quantities(operands, (&right_res, right_child.location))?
+        {
+            self.divide(left.value, right.value, location)?;
+            let res = Quantity::divide(&left, &right, location)?;
+            return self.check_quantity(res, location);
+        }
+        let operands = (&left_res, left_child.location);
+        if let Some((left, right)) =
+            Self::as_complexes(operands, (&right_res, right_child.location))?
+        {
+            if right.re == 0.0 && right.im == 0.0 {
+                self.divide(left.re, 0.0, location)?;
+            }
+            return self.check_complex(Complex::divide(left, right), location);
+        }
+        let left_res = Self::as_dual(&left_res, left_child.location)?;
+        let right_res = Self::as_dual(&right_res, right_child.location)?;
+        self.divide(left_res.value, right_res.value, location)?;
+        Ok(Dual::divide(&left_res, &right_res).into())
+    }
+
+    fn divide(&self, left: f64, right: f64, location: Location) -> Result<f64, EvalError> {
+        self.numeric_policy.divide(left, right, location)
+    }
+
+    fn check_dual(&self, res: Dual, location: Location) -> EvalResult {
+        self.apply_policy(res.value, location)?;
+        Ok(res.into())
+    }
+
+    fn check_interval(&self, res: Interval, location: Location) -> EvalResult {
+        self.apply_policy(res.lo, location)?;
+        self.apply_policy(res.hi, location)?;
+        Ok(Value::Interval(res))
+    }
+
+    fn check_quantity(&self, res: Quantity, location: Location) -> EvalResult {
+        self.apply_policy(res.value, location)?;
+        Ok(res.into())
+    }
+
+    fn check_complex(&self, res: Complex, location: Location) -> EvalResult {
+        self.apply_policy(res.re, location)?;
+        self.apply_policy(res.im, location)?;
+        Ok(res.into())
+    }
+
+    fn apply_policy(&self, res: f64, location: Location) -> Result<f64, EvalError> {
+        self.numeric_policy.check(res, location)
+    }
+
+    /// Comparisons of intervals are 1 when true for all their numbers, 0 when false for all
+    /// of them, and 'Interval::UNKNOWN' otherwise. Quantities are compared in the unit
+    /// of the left one. Complex numbers are equal when both their parts are, and only
+    /// ordered when real.
+    fn perform_comparison_op<F, G, H>(
+        &mut self,
+        left_child: &ParseNode,
+        right_child: &ParseNode,
+        location: Location,
+        (op, interval_op, complex_op): (F, G, H),
     ) -> EvalResult
     where
         F: FnOnce(f64, f64) -> bool,
+        G: FnOnce(Interval, Interval) -> Option<bool>,
+        H: FnOnce(Complex, Complex) -> Option<bool>,
     {
-        let left_res = self.eval(&left_child)?;
-        let right_res = self.eval(&right_child)?;
-        let res = op(left_res, right_res);
-        Ok(if res { 1.0 } else { 0.0 })
+        let left_res = self.eval_node(left_child)?;
+        let right_res = self.eval_node(right_child)?;
+        let operands = (
+            (&left_res, left_child.location),
+            (&right_res, right_child.location),
+        );
+        let res = if let Some((left, right)) = Self::as_intervals(operands.0, operands.1)? {
+            match interval_op(left, right) {
+                Some(res) => res,
+                None => return Ok(Value::Interval(Interval::UNKNOWN)),
+            }
+        } else if let Some((left, right)) = Self::as_quantities(operands.0, operands.1)? {
+            op(left.value, left.align(&right, location)?)
+        } else if let Some((left, right)) = Self::as_complexes(operands.0, operands.1)? {
+            match complex_op(left, right) {
+                Some(res) => res,
+                None => {
+                    let unordered = if left.is_real() { right } else { left };
+                    return Err(EvalError::ComplexOrdering(unordered.to_string(), location));
+                }
+            }
+        } else {
+            op(
+                Self::as_number(&left_res, left_child.location)?,
+                Self::as_number(&right_res, right_child.location)?,
+            )
+        };
+        Ok(Value::Number(if res { 1.0 } else { 0.0 }))
+    }
+
+    /// Runs 'body' with 'variable' bound to each of 'values'.
+    fn eval_for(
+        &mut self,
+        variable: &str,
+        values: impl Iterator<Item = Value>,
+        body: &ParseNode,
+        location: Location,
+    ) -> EvalResult {
+        let mut res = Value::Number(0.0);
+        for val in values {
+            self.count_iteration(location)?;
+            self.innermost_scope().insert(variable.to_string(), val);
+            match self.eval_node(body) {
+                Ok(val) => res = val,
+                Err(EvalError::Break(_)) => break,
+                Err(EvalError::Continue(_)) => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(res)
+    }
+
+    fn reset_iterations(&mut self) {
+        self.iterations = 0;
+    }
+
+    fn count_iteration(&mut self, location: Location) -> Result<(), EvalError> {
+        self.iterations += 1;
+        if self.iterations > self.iteration_budget {
+            Err(EvalError::IterationBudgetExceeded(
+                self.iteration_budget,
+                location,
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The settings of the stack machine running the compiled programs.
+    pub(crate) fn machine(&self) -> Machine {
+        Machine {
+            numeric_policy: self.numeric_policy,
+            equality: self.equality,
+            iteration_budget: self.iteration_budget,
+        }
+    }
+
+    pub(crate) fn constant(&self, identifier: &str) -> Option<&Value> {
+        self.constants
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(identifier))
+            .map(|(val, _)| val)
+    }
+
+    /// The location of the 'const' declaring 'identifier', 'None' for a built-in constant.
+    fn declaration(&self, identifier: &str) -> Option<Option<Location>> {
+        self.constants
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(identifier))
+            .map(|(_, declaration)| *declaration)
+    }
+
+    pub(crate) fn global(&self, identifier: &str) -> Option<&Value> {
+        self.scopes[0].get(identifier)
+    }
+
+    fn lookup(&self, identifier: &str) -> Option<Value> {
+        self.constant(identifier)
+            .or_else(|| {
+                self.scopes
+                    .iter()
+                    .rev()
+                    .find_map(|scope| scope.get(identifier))
+            })
+            .cloned()
+    }
+
+    /// Updates the innermost binding of 'identifier',
+    /// binding it in the innermost scope if there is none.
+    fn assign(&mut self, identifier: &str, val: Value) {
+        let scope = match self
+            .scopes
+            .iter()
+            .rposition(|scope| scope.contains_key(identifier))
+        {
+            Some(index) => &mut self.scopes[index],
+            None => self.innermost_scope(),
+        };
+        scope.insert(identifier.to_string(), val);
+    }
+
+    fn innermost_scope(&mut self) -> &mut SymbolTable {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn push_scope(&mut self, symbols: SymbolTable) {
+        self.scopes.push(symbols);
+        self.constants.push(HashMap::new());
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+        self.constants.pop();
     }
 
     fn populate_symbol_table(&mut self) {
-        self.syms.insert(String::from("PI"), std::f64::consts::PI);
+        for (name, num) in builtins::CONSTANTS {
+            self.constants[0].insert(name.to_string(), (Value::Number(*num), None));
+        }
     }
 }
 
@@ -140,13 +1345,28 @@ mod tests {
         ctx.eval(node)
     }
 
-    fn assert_res(lhs: EvalResult, rhs: EvalResult) {
+    /// Evaluates 'input' despite the errors found by the parser that keep the tree valid,
+    /// like the reassignments of constants.
+    fn eval_unchecked(input: &str) -> EvalResult {
+        let tokens = Lexer::get_tokens(input).unwrap();
+        let (root, _) = Parser::new(&tokens).parse_partial();
+        EvalContext::populated().eval(&root)
+    }
+
+    fn assert_res(lhs: EvalResult, rhs: Result<f64, EvalError>) {
         match (lhs, rhs) {
-            (Ok(a), Ok(b)) => assert!((a - b).abs() <= EPSILON),
-            (lhs, rhs) => assert_eq!(lhs, rhs),
+            (Ok(Value::Number(a)), Ok(b)) => assert!(a == b || (a - b).abs() <= f64::EPSILON),
+            (lhs, rhs) => assert_eq!(lhs, rhs.map(Value::Number)),
         }
     }
 
+    fn assert_display(input: &str, expected: &str) {
+        assert_eq!(
+            Ok(String::from(expected)),
+            eval(input).map(|val| val.to_string())
+        );
+    }
+
     #[test]
     fn test_eval_number() {
         assert_res(eval("3.2"), Ok(3.2));
@@ -172,6 +1392,19 @@ mod tests {
         assert_res(eval("3.2 / 2.0"), Ok(1.6));
     }
 
+    #[test]
+    fn test_eval_power() {
+        assert_res(eval("2 ^ 3 ^ 2"), Ok(512.0));
+        assert_res(eval("4 ** 0.5 * 3"), Ok(6.0));
+    }
+
+    #[test]
+    fn test_eval_negation() {
+        assert_res(eval("-2 ^ 2"), Ok(-4.0));
+        assert_res(eval("3 - -2 * 4"), Ok(11.0));
+        assert_res(eval("2 ^ -1"), Ok(0.5));
+    }
+
     #[test]
     fn test_eval_greater_than() {
         assert_res(eval("3.2 > 2.0"), Ok(1.0));
@@ -222,6 +1455,19 @@ mod tests {
         assert_res(eval("3.2 == 5.0"), Ok(0.0));
     }
 
+    #[test]
+    fn test_eval_not_equal() {
+        assert_res(eval("3.2 != 5.0"), Ok(1.0));
+        assert_res(eval("3.2 ≠ 3.2"), Ok(0.0));
+    }
+
+    #[test]
+    fn test_eval_unicode() {
+        assert_res(eval("Δt = 2\nΔt × 3 ÷ 4"), Ok(1.5));
+        assert_res(eval("größe = 3\ngröße ≥ 3"), Ok(1.0));
+        assert_res(eval("π = 3\nπ ≤ PI"), Ok(1.0));
+    }
+
     #[test]
     fn test_eval_pi() {
         assert_res(eval("PI >= 3.14159265358979312"), Ok(1.0));
@@ -237,10 +1483,853 @@ mod tests {
         assert_res(eval("hello = 5.0\n2.3 + hello"), Ok(7.3));
     }
 
+    #[test]
+    fn test_statement_separators() {
+        assert_res(eval("a = 1; b = 2\n(a +\n b) * 2"), Ok(6.0));
+    }
+
+    #[test]
+    fn test_eval_block() {
+        assert_res(eval("{ 1; 2 } + 1"), Ok(3.0));
+        assert_res(eval("x = 1\ny = { let x = 10; x * 2 }\nx + y"), Ok(21.0));
+    }
+
+    #[test]
+    fn test_let_is_scoped_to_the_block() {
+        assert_res(
+            eval("{ let helper = 2 }\nhelper"),
+            Err(EvalError::SymbolNotFound(
+                String::from("helper"),
+                Location(1, 0),
+            )),
+        );
+        assert_res(
+            eval("{ inner = 2 }\ninner"),
+            Err(EvalError::SymbolNotFound(
+                String::from("inner"),
+                Location(1, 0),
+            )),
+        );
+    }
+
+    #[test]
+    fn test_assignment_updates_the_enclosing_binding() {
+        assert_res(eval("total = 1\n{ total = total + 2 }\ntotal"), Ok(3.0));
+        assert_res(eval("x = 1\n{ let x = 5; { x = 7 }; x }"), Ok(7.0));
+        assert_res(eval("x = 1\n{ let x = 5; { x = 7 } }\nx"), Ok(1.0));
+    }
+
+    #[test]
+    fn test_empty_block() {
+        assert_res(eval("2 * {}"), Err(EvalError::EmptyBlock(Location(0, 4))));
+        assert_res(eval(";;;"), Err(EvalError::EmptyBlock(Location(0, 0))));
+    }
+
+    #[test]
+    fn test_let_constant() {
+        assert_res(
+            eval_unchecked("{ let PI = 3 }"),
+            Err(EvalError::ConstantAssignment(
+                String::from("PI"),
+                Location(0, 9),
+            )),
+        );
+    }
+
+    #[test]
+    fn test_eval_while() {
+        // Newton's method for the square root of 2.
+        assert_res(
+            eval("x = 1\nwhile (x * x - 2) ^ 2 > 1e-24 { x = x - (x * x - 2) / (2 * x) }\nx"),
+            Ok(std::f64::consts::SQRT_2),
+        );
+        assert_res(eval("while 0 { 1 }"), Ok(0.0));
+    }
+
+    #[test]
+    fn test_eval_for() {
+        assert_res(
+            eval("total = 0\nfor i in 1..5 { total = total + i }\ntotal"),
+            Ok(10.0),
+        );
+        assert_res(eval("for i in 0..3 { i * 2 }"), Ok(4.0));
+        assert_res(
+            eval("for i in 0..3 { i }\ni"),
+            Err(EvalError::SymbolNotFound(String::from("i"), Location(1, 0))),
+        );
+    }
+
+    #[test]
+    fn test_eval_range_bounds() {
+        assert_res(
+            eval("0..1e10"),
+            Err(EvalError::ListTooLong(MAX_LIST_LENGTH, Location(0, 1))),
+        );
+        assert_res(
+            eval("0.5..3"),
+            Err(EvalError::InvalidRange(0.5, 3.0, Location(0, 3))),
+        );
+        assert_res(
+            eval("for i in 0..1e300 { i }"),
+            Err(EvalError::InvalidRange(0.0, 1e300, Location(0, 10))),
+        );
+        assert!(matches!(
+            eval("for i in 0..NAN { i }"),
+            Err(EvalError::InvalidRange(_, end, Location(0, 10))) if end.is_nan()
+        ));
+        assert_res(eval("len(-2..2)"), Ok(4.0));
+    }
+
+    #[test]
+    fn test_eval_break_and_continue() {
+        assert_res(
+            eval("n = 0\nfor i in 0..10 { n = n + 1; break; n = 100 }\nn"),
+            Ok(1.0),
+        );
+        assert_res(
+            eval("n = 0\nfor i in 0..10 { n = n + 1; continue; n = 100 }\nn"),
+            Ok(10.0),
+        );
+        assert_res(
+            eval("n = 0\nfor i in 0..3 { while 1 { break }; n = n + 1 }\nn"),
+            Ok(3.0),
+        );
+        // The condition exits its own loop, not the enclosing one.
+        assert_res(
+            eval("n = 0; for i in 0..3 { while ({ break }) { 1 }; n = n + 1 }; n"),
+            Ok(3.0),
+        );
+        assert_res(eval("while ({ break }) { 1 }"), Ok(0.0));
+    }
+
+    #[test]
+    fn test_eval_break_outside_of_loop() {
+        assert_res(eval("1\nbreak"), Err(EvalError::Break(Location(1, 0))));
+        assert_res(
+            eval("{ continue }"),
+            Err(EvalError::Continue(Location(0, 2))),
+        );
+        // A function can't exit the loop it is called from.
+        assert_res(
+            eval("n = 0; fn f() { break }; for i in 0..3 { n = n + 1; f() }; n"),
+            Err(EvalError::LoopExitFromFunction("break", Location(0, 16))),
+        );
+        assert_res(
+            eval("fn f() { continue }\nwhile 1 { f() }"),
+            Err(EvalError::LoopExitFromFunction("continue", Location(0, 9))),
+        );
+        assert_res(eval("fn f() { for i in 0..3 { break }; 2 }\nf()"), Ok(2.0));
+    }
+
+    #[test]
+    fn test_iteration_budget() {
+        let tokens = Lexer::get_tokens("for i in 0..10 { for j in 0..10 { j } }").unwrap();
+        let root = Parser::new(&tokens).parse().unwrap();
+        let mut ctx = EvalContext::populated();
+        ctx.set_iteration_budget(110);
+        assert_res(ctx.eval(&root), Ok(9.0));
+        ctx.set_iteration_budget(109);
+        assert_res(
+            ctx.eval(&root),
+            Err(EvalError::IterationBudgetExceeded(109, Location(0, 17))),
+        );
+
+        // A 'continue' in the condition retries it, which counts as an iteration.
+        let tokens = Lexer::get_tokens("n = 0\nwhile ({ n += 1; continue }) { 1 }").unwrap();
+        let root = Parser::new(&tokens).parse().unwrap();
+        ctx.set_iteration_budget(5);
+        assert_res(
+            ctx.eval(&root),
+            Err(EvalError::IterationBudgetExceeded(5, Location(1, 0))),
+        );
+        assert_eq!(Some(&Value::Number(6.0)), ctx.global("n"));
+    }
+
+    #[test]
+    fn test_constant_assignment() {
+        assert_res(
+            eval_unchecked("PI = 3"),
+            Err(EvalError::ConstantAssignment(
+                String::from("PI"),
+                Location(0, 3),
+            )),
+        );
+    }
+
+    #[test]
+    fn test_reserved_word_assignment() {
+        let node = ParseNode {
+            ntype: NodeType::Assignment(
+                String::from("fn"),
+                Box::new(ParseNode {
+                    ntype: NodeType::Number(1.0),
+                    location: Location(0, 5),
+                }),
+            ),
+            location: Location(0, 3),
+        };
+        assert_res(
+            EvalContext::populated().eval(&node),
+            Err(EvalError::ReservedWordAssignment(
+                String::from("fn"),
+                Location(0, 3),
+            )),
+        );
+    }
+
+    #[test]
+    fn test_eval_list() {
+        assert_display("[1, 2 * 3, [4], []]", "[1, 6, [4], []]");
+        assert_display("2..5", "[2, 3, 4]");
+        assert_display("3..1", "[]");
+    }
+
+    #[test]
+    fn test_eval_index() {
+        assert_res(eval("xs = [10, 20, 30]\nxs[0] + xs[-1]"), Ok(40.0));
+        assert_res(eval("[[1, 2], [3]][0][1]"), Ok(2.0));
+        assert_res(eval("(0..10)[-2]"), Ok(8.0));
+    }
+
+    #[test]
+    fn test_eval_index_out_of_bounds() {
+        assert_res(
+            eval("xs = [1, 2]\nxs[1 + 1]"),
+            Err(EvalError::IndexOutOfBounds(2, 2, Location(1, 5))),
+        );
+        assert_res(
+            eval("xs = [1, 2]\nxs[-3]"),
+            Err(EvalError::IndexOutOfBounds(-3, 2, Location(1, 3))),
+        );
+        assert_res(
+            eval("[1][0.5]"),
+            Err(EvalError::InvalidIndex(0.5, Location(0, 4))),
+        );
+    }
+
+    #[test]
+    fn test_eval_builtins() {
+        assert_res(eval("len([1, 2, 3])"), Ok(3.0));
+        assert_res(eval("sum(1..5)"), Ok(10.0));
+        assert_res(eval("avg([1, 2, 6])"), Ok(3.0));
+        assert_res(eval("min([4, -1, 2])"), Ok(-1.0));
+        assert_res(eval("max(4, -1, 2)"), Ok(4.0));
+        assert_res(eval("sum([])"), Ok(0.0));
+    }
+
+    #[test]
+    fn test_eval_math_builtins() {
+        assert_res(eval("sqrt(16) + exp(0) + ln(E)"), Ok(6.0));
+        assert_res(eval("sin(PI / 2) + cos(0) + tan(0)"), Ok(2.0));
+        assert_res(eval("map(sqrt, [1, 4])[1]"), Ok(2.0));
+        assert_res(eval("abs(-3) + arg(-1) / PI + arg(2) + conj(2)"), Ok(6.0));
+
+        let tokens = Lexer::get_tokens("ln(0)").unwrap();
+        let root = Parser::new(&tokens).parse().unwrap();
+        let mut ctx = EvalContext::populated();
+        ctx.set_numeric_policy(NumericPolicy::RejectNonFinite);
+        assert_res(
+            ctx.eval(&root),
+            Err(EvalError::NonFiniteResult(
+                f64::NEG_INFINITY,
+                Location(0, 0),
+            )),
+        );
+    }
+
+    fn gradient(ctx: &mut EvalContext, input: &str, variables: &[&str]) -> (f64, Vec<f64>) {
+        let tokens = Lexer::get_tokens(input).unwrap();
+        let root = Parser::new(&tokens).parse().unwrap();
+        ctx.eval_gradient(&root, variables).unwrap()
+    }
+
+    #[test]
+    fn test_eval_gradient() {
+        let mut ctx = EvalContext::populated();
+        ctx.bind("x", 1.0);
+        ctx.bind("y", 2.0);
+        let (value, derivatives) = gradient(&mut ctx, "x * y + sin(x) - y / 4", &["x", "y"]);
+        assert_eq!(2.0 + 1f64.sin() - 0.5, value);
+        assert_eq!(vec![2.0 + 1f64.cos(), 0.75], derivatives);
+
+        // The derivatives flow through functions, lists, built-ins and loops.
+        let input = "fn sq(a) { a * a }\nsum(map(sq, [x, y])) + avg([x, 3])";
+        assert_eq!(vec![2.5, 4.0], gradient(&mut ctx, input, &["x", "y"]).1);
+        let input = "total = 0\nfor i in 0..4 { total += x ^ 2 * i }\ntotal";
+        assert_eq!((6.0, vec![12.0]), gradient(&mut ctx, input, &["x"]));
+        assert_eq!(
+            vec![0.0, 1.0],
+            gradient(&mut ctx, "max(x, y, 1)", &["x", "y"]).1
+        );
+        // Comparisons only use the values.
+        assert_eq!(vec![0.0], gradient(&mut ctx, "x < y", &["y"]).1);
+        assert_eq!(
+            (std::f64::consts::PI, vec![0.0]),
+            gradient(&mut ctx, "PI", &["x"])
+        );
+
+        // The variables are left as they were.
+        assert_eq!(Some(&Value::Number(1.0)), ctx.global("x"));
+        assert_eq!(None, ctx.global("total"));
+        assert_eq!(None, ctx.global("sq"));
+    }
+
+    #[test]
+    fn test_eval_gradient_errors() {
+        let tokens = Lexer::get_tokens("x * z").unwrap();
+        let root = Parser::new(&tokens).parse().unwrap();
+        let mut ctx = EvalContext::populated();
+        ctx.bind("x", 1.0);
+        assert_eq!(
+            Err(EvalError::SymbolNotFound("z".to_string(), Location(0, 0))),
+            ctx.eval_gradient(&root, &["x", "z"])
+        );
+        assert_eq!(
+            Err(EvalError::SymbolNotFound("z".to_string(), Location(0, 4))),
+            ctx.eval_gradient(&root, &["x"])
+        );
+
+        let tokens = Lexer::get_tokens("[x]").unwrap();
+        let root = Parser::new(&tokens).parse().unwrap();
+        assert_eq!(
+            Err(EvalError::TypeMismatch("number", "list", Location(0, 0))),
+            ctx.eval_gradient(&root, &["x"])
+        );
+        assert_eq!(Some(&Value::Number(1.0)), ctx.global("x"));
+    }
+
+    /// Forward-mode derivatives agree with the symbolic ones.
+    #[test]
+    fn test_gradient_and_derivative() {
+        let mut ctx = EvalContext::populated();
+        ctx.bind("x", 0.7);
+        for input in &["x ^ x", "exp(-x ^ 2) / (1 + x)", "sqrt(tan(x)) * ln(x + 2)"] {
+            let tokens = Lexer::get_tokens(input).unwrap();
+            let root = Parser::new(&tokens).parse().unwrap();
+            let derivative = ctx.differentiate(&root, "x").unwrap();
+            let (_, derivatives) = ctx.eval_gradient(&root, &["x"]).unwrap();
+            assert_res(ctx.eval(&derivative), Ok(derivatives[0]));
+        }
+    }
+
+    fn eval_bounds(ctx: &mut EvalContext, input: &str) -> EvalResult {
+        let tokens = Lexer::get_tokens(input).unwrap();
+        let root = Parser::new(&tokens).parse().unwrap();
+        ctx.eval(&root)
+    }
+
+    /// Checks the bounds up to their rounding, tested in the module of 'Interval'.
+    fn assert_holds(res: EvalResult, lo: f64, hi: f64) {
+        let close =
+            |bound: f64, expected: f64| bound == expected || (bound - expected).abs() < 1e-9;
+        match res {
+            Ok(Value::Interval(interval)) => assert!(
+                close(interval.lo, lo) && close(interval.hi, hi),
+                "{:?} should be [{}, {}]",
+                interval,
+                lo,
+                hi
+            ),
+            res => panic!("{:?} should be an interval", res),
+        }
+    }
+
+    #[test]
+    fn test_eval_intervals() {
+        let mut ctx = EvalContext::populated();
+        ctx.bind("x", Interval::new(9.8, 10.2));
+        ctx.bind("y", Interval::new(-1.0, 2.0));
+        assert_holds(eval_bounds(&mut ctx, "2 * x - 1"), 18.6, 19.4);
+        assert_holds(eval_bounds(&mut ctx, "x * y"), -10.2, 20.4);
+        assert_holds(eval_bounds(&mut ctx, "y ^ 2"), 0.0, 4.0);
+        assert_holds(eval_bounds(&mut ctx, "-y"), -2.0, 1.0);
+        assert_holds(eval_bounds(&mut ctx, "1 / x"), 1.0 / 10.2, 1.0 / 9.8);
+        assert_holds(
+            eval_bounds(&mut ctx, "sqrt(x - 9.8)"),
+            0.0,
+            (10.2f64 - 9.8).sqrt(),
+        );
+        assert_holds(eval_bounds(&mut ctx, "sum([x, y, 1])"), 9.8, 13.2);
+        assert_holds(eval_bounds(&mut ctx, "avg([x, y])"), 4.4, 6.1);
+        assert_holds(eval_bounds(&mut ctx, "max(y, 0)"), 0.0, 2.0);
+        assert_holds(eval_bounds(&mut ctx, "z in [1, 1 + 1]\nx * z"), 9.8, 20.4);
+        assert_holds(eval_bounds(&mut ctx, "(w in [1, 2]) * 2 + w"), 3.0, 6.0);
+        assert_holds(eval_bounds(&mut ctx, "y ^ 0.5"), 0.0, 2f64.sqrt());
+        assert_res(
+            eval_bounds(&mut ctx, "z in [2, 1]"),
+            Err(EvalError::InvalidInterval(2.0, 1.0, Location(0, 5))),
+        );
+
+        // Dividing by an interval holding 0 is unbounded on one side or both.
+        assert_holds(
+            eval_bounds(&mut ctx, "x / (y + 1)"),
+            9.8 / 3.0,
+            f64::INFINITY,
+        );
+        assert_holds(
+            eval_bounds(&mut ctx, "x / y"),
+            f64::NEG_INFINITY,
+            f64::INFINITY,
+        );
+        ctx.set_numeric_policy(NumericPolicy::RejectDivisionByZero);
+        assert_res(
+            eval_bounds(&mut ctx, "x / y"),
+            Err(EvalError::DivisionByZero(Location(0, 2))),
+        );
+        ctx.set_numeric_policy(NumericPolicy::RejectNonFinite);
+        assert_res(
+            eval_bounds(&mut ctx, "x ^ 400"),
+            Err(EvalError::NonFiniteResult(f64::INFINITY, Location(0, 2))),
+        );
+    }
+
+    #[test]
+    fn test_eval_interval_comparisons() {
+        let mut ctx = EvalContext::populated();
+        ctx.bind("x", Interval::new(9.8, 10.2));
+        assert_res(eval_bounds(&mut ctx, "x > 9"), Ok(1.0));
+        assert_res(eval_bounds(&mut ctx, "x <= 9.8 - 0.1"), Ok(0.0));
+        assert_res(eval_bounds(&mut ctx, "x == 11"), Ok(0.0));
+        assert_res(eval_bounds(&mut ctx, "x != 11"), Ok(1.0));
+        assert_eq!(
+            Ok(Value::Interval(Interval::UNKNOWN)),
+            eval_bounds(&mut ctx, "x < 10")
+        );
+
+        // A condition must be definite.
+        assert_res(
+            eval_bounds(&mut ctx, "y = 0\nwhile x > 9 { x = x - 2; y = y + 1 }\ny"),
+            Ok(1.0),
+        );
+        ctx.bind("x", Interval::new(9.8, 10.2));
+        assert_res(
+            eval_bounds(&mut ctx, "while x < 10 {}"),
+            Err(EvalError::TypeMismatch(
+                "number",
+                "interval",
+                Location(0, 8),
+            )),
+        );
+    }
+
+    fn quantity(value: f64, unit: &str) -> EvalResult {
+        Ok(Quantity::new(value, Unit::parse(unit).unwrap()).into())
+    }
+
+    #[test]
+    fn test_eval_units() {
+        assert_eq!(quantity(1.5, "km"), eval("1 km + 500 m"));
+        assert_eq!(quantity(20.0, "m/s"), eval("72 km/h in m/s"));
+        assert_eq!(quantity(9.0, "m^2"), eval("(3 m) ^ 2"));
+        assert_eq!(quantity(-3.0, "m"), eval("sqrt(9 m^2) * -1"));
+        assert_eq!(quantity(200.0, "cm"), eval("max([1 m, 200 cm, 150 cm])"));
+        assert_eq!(quantity(150.0, "cm"), eval("avg([1 m, 200 cm]) in cm"));
+        assert_eq!(quantity(2.0, "kg*m/s^2"), eval("2 kg * 1 m/s^2"));
+        assert_eq!(quantity(12.0, "N*m"), eval("d = 3 m\n4 N * d"));
+        assert_res(eval("1 km / 1 m"), Ok(1000.0));
+        assert_res(eval("1 inch in cm == 2.54 cm"), Ok(1.0));
+        assert_res(eval("1 mi > 5000 ft"), Ok(1.0));
+
+        let mut ctx = EvalContext::populated();
+        ctx.bind("speed", Quantity::new(36.0, Unit::parse("km/h").unwrap()));
+        assert_eq!(
+            quantity(0.5, "km"),
+            eval_bounds(&mut ctx, "speed * 50 s in km")
+        );
+        assert_eq!(
+            "10 m/s",
+            eval_bounds(&mut ctx, "speed in m/s").unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_eval_unit_errors() {
+        let incompatible = |left: &str, right: &str, location| {
+            Err(EvalError::IncompatibleUnits(
+                left.to_string(),
+                right.to_string(),
+                location,
+            ))
+        };
+        assert_eq!(incompatible("m", "s", Location(0, 4)), eval("1 m + 2 s"));
+        assert_eq!(incompatible("m/s", "1", Location(0, 6)), eval("2 m/s > 1"));
+        assert_eq!(incompatible("1", "m", Location(0, 2)), eval("2 ^ 3 m"));
+        assert_eq!(incompatible("h", "m", Location(0, 4)), eval("2 h in m"));
+        assert_eq!(
+            incompatible("kg", "s", Location(0, 0)),
+            eval("sum([1 kg, 2 s])")
+        );
+        assert_eq!(
+            Err(EvalError::InvalidUnitPower(
+                "m".to_string(),
+                0.5,
+                Location(0, 0)
+            )),
+            eval("sqrt(4 m)")
+        );
+        // The exponents of the dimension of 'N' would overflow.
+        assert_eq!(
+            Err(EvalError::InvalidUnitPower(
+                "N".to_string(),
+                2e9,
+                Location(0, 6)
+            )),
+            eval("(1 N) ^ 2000000000")
+        );
+        assert_eq!(
+            Err(EvalError::InvalidUnitPower(
+                "m".to_string(),
+                4e9,
+                Location(0, 17)
+            )),
+            eval("(1 m)^2000000000 * (1 m)^2000000000")
+        );
+        assert_eq!(
+            Err(EvalError::TypeMismatch(
+                "number",
+                "quantity",
+                Location(0, 4)
+            )),
+            eval("sin(2 m)")
+        );
+        assert_res(
+            eval_with_policy("1 m / (0 s)", NumericPolicy::RejectDivisionByZero),
+            Err(EvalError::DivisionByZero(Location(0, 4))),
+        );
+    }
+
+    fn complex(re: f64, im: f64) -> EvalResult {
+        Ok(Complex::new(re, im).into())
+    }
+
+    #[test]
+    fn test_eval_complex() {
+        assert_eq!(complex(11.0, 2.0), eval("(1 + 2i) * (3 - 4i)"));
+        assert_eq!(complex(-4.0, 0.0), eval("2i ^ 2"));
+        assert_eq!(complex(0.0, 2.0), eval("sqrt(-4 + 0i)"));
+        assert_eq!(complex(1.0, -2.0), eval("conj(1 + 2j)"));
+        assert_eq!(complex(4.0, 2.0), eval("sum([1, 2i, 3])"));
+        assert_eq!(complex(0.0, 2.0), eval("avg([1i, 3i])"));
+        assert_res(eval("abs(3 + 4i)"), Ok(5.0));
+        assert_res(eval("arg(1i) == PI / 2"), Ok(1.0));
+        assert_res(eval("exp(1i * PI) == -1"), Ok(1.0));
+        assert_res(eval("1i * 1i != -1"), Ok(0.0));
+        assert_eq!("5+0i", eval("x = 2 + 1j\nx * conj(x)").unwrap().to_string());
+    }
+
+    #[test]
+    fn test_eval_complex_errors() {
+        // Only the complex numbers without imaginary part are ordered.
+        assert_res(eval("1i * 1i < 0"), Ok(1.0));
+        assert_res(eval("max([1 + 0i, 2])"), Ok(2.0));
+        assert_res(
+            eval("1i < 2"),
+            Err(EvalError::ComplexOrdering(
+                "0+1i".to_string(),
+                Location(0, 3),
+            )),
+        );
+        assert_res(
+            eval("min([1, 2 - 1i])"),
+            Err(EvalError::ComplexOrdering(
+                "2-1i".to_string(),
+                Location(0, 0),
+            )),
+        );
+        assert_res(
+            eval("sin(1i)"),
+            Err(EvalError::TypeMismatch(
+                "number",
+                "complex number",
+                Location(0, 4),
+            )),
+        );
+        assert_res(
+            eval("1 m * 2i"),
+            Err(EvalError::TypeMismatch(
+                "number",
+                "complex number",
+                Location(0, 6),
+            )),
+        );
+        assert_res(
+            eval_with_policy("1 / 0i", NumericPolicy::RejectDivisionByZero),
+            Err(EvalError::DivisionByZero(Location(0, 2))),
+        );
+    }
+
+    #[test]
+    fn test_eval_builtin_errors() {
+        assert_res(
+            eval("avg([])"),
+            Err(EvalError::EmptyList("avg", Location(0, 0))),
+        );
+        assert_res(
+            eval("len(1, 2)"),
+            Err(EvalError::ArgumentCount(
+                String::from("len"),
+                1,
+                2,
+                Location(0, 0),
+            )),
+        );
+        assert_res(
+            eval("sum(3)"),
+            Err(EvalError::TypeMismatch("list", "number", Location(0, 4))),
+        );
+        assert_res(
+            eval("[1] + 1"),
+            Err(EvalError::TypeMismatch("number", "list", Location(0, 0))),
+        );
+    }
+
+    #[test]
+    fn test_eval_functions() {
+        assert_display(
+            "fn square(x) { x * x }\nmap(square, [1, 2, 3])",
+            "[1, 4, 9]",
+        );
+        assert_display("map(len, [[1], []])", "[1, 0]");
+        assert_res(eval("fn add(a, b) { a + b }\nadd(2, 3)"), Ok(5.0));
+        // The body sees the global symbols, but not the scopes of the caller.
+        assert_res(
+            eval("y = 1\nfn f(x) { x + y }\n{ let y = 10; f(1) }"),
+            Ok(2.0),
+        );
+        assert_res(
+            eval("fn f() { 1 }\nf(2)"),
+            Err(EvalError::ArgumentCount(
+                String::from("f"),
+                0,
+                1,
+                Location(1, 0),
+            )),
+        );
+    }
+
+    #[test]
+    fn test_eval_call_depth() {
+        assert_res(
+            eval("fn f(x) { f(x) }\nf(1)"),
+            Err(EvalError::CallDepthExceeded(
+                MAX_CALL_DEPTH,
+                Location(0, 10),
+            )),
+        );
+    }
+
+    #[test]
+    fn test_eval_for_over_list() {
+        assert_res(
+            eval("total = 0\nfor x in [1, 2, 3] { total = total + x }\ntotal"),
+            Ok(6.0),
+        );
+        assert_res(
+            eval("for x in 3 { x }"),
+            Err(EvalError::TypeMismatch("list", "number", Location(0, 9))),
+        );
+    }
+
+    #[test]
+    fn test_eval_record() {
+        assert_res(
+            eval("order = { qty: 3, price: 2.5 }\norder.qty * order.price"),
+            Ok(7.5),
+        );
+        assert_display("{ b: [1], a: { c: 2 } }", "{ a: { c: 2 }, b: [1] }");
+        assert_res(
+            eval("order = { qty: 3 }\norder.price"),
+            Err(EvalError::MissingField(
+                String::from("price"),
+                Location(1, 5),
+            )),
+        );
+        assert_res(
+            eval("x = 1\nx.qty"),
+            Err(EvalError::TypeMismatch("record", "number", Location(1, 0))),
+        );
+    }
+
+    #[test]
+    fn test_bind_record() {
+        let tokens = Lexer::get_tokens("sensor.reading.value * sensor.scale").unwrap();
+        let root = Parser::new(&tokens).parse().unwrap();
+        let mut ctx = EvalContext::populated();
+        ctx.bind(
+            "sensor",
+            Value::record(vec![
+                (
+                    "reading",
+                    Value::record(vec![("value", Value::Number(4.0))]),
+                ),
+                ("scale", Value::Number(0.5)),
+            ]),
+        );
+        assert_res(ctx.eval(&root), Ok(2.0));
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        assert_res(
+            eval("total = 0\nfor x in [1, 2, 3] { total += x }\ntotal"),
+            Ok(6.0),
+        );
+        assert_res(eval("x = 10; x -= 4; x *= 3; x /= 2"), Ok(9.0));
+        assert_res(
+            eval("x = 1\n  count += x"),
+            Err(EvalError::SymbolNotFound(
+                String::from("count"),
+                Location(1, 2),
+            )),
+        );
+    }
+
+    #[test]
+    fn test_builtin_constants() {
+        assert_res(eval("E"), Ok(std::f64::consts::E));
+        assert_res(eval("TAU / PI"), Ok(2.0));
+        assert_res(eval("-INF < -1e308"), Ok(1.0));
+        assert_res(eval("INF > 1e308"), Ok(1.0));
+        assert!(matches!(eval("NAN"), Ok(Value::Number(num)) if num.is_nan()));
+    }
+
+    #[test]
+    fn test_const_declaration() {
+        assert_res(eval("const RATE = 0.2\n{ 100 * RATE }"), Ok(20.0));
+        assert_res(
+            eval_unchecked("const RATE = 0.2\nRATE += 1"),
+            Err(EvalError::ConstantAssignment(
+                String::from("RATE"),
+                Location(1, 5),
+            )),
+        );
+        assert_res(
+            eval_unchecked("const E = 2"),
+            Err(EvalError::ConstantAssignment(
+                String::from("E"),
+                Location(0, 8),
+            )),
+        );
+
+        // Each iteration runs the declaration in a new scope.
+        assert_res(
+            eval("n = 0\nfor i in 1..4 { const K = i; n += K }\nn"),
+            Ok(6.0),
+        );
+        assert_res(
+            eval("{ const K = 1 }\nK"),
+            Err(EvalError::SymbolNotFound(String::from("K"), Location(1, 0))),
+        );
+        assert_res(eval("{ const K = 1 }\nK = 2\nK + 1"), Ok(3.0));
+
+        // Evaluating the same declaration again rebinds it.
+        let tokens = Lexer::get_tokens("const K = 2\nK").unwrap();
+        let root = Parser::new(&tokens).parse().unwrap();
+        let mut ctx = EvalContext::populated();
+        assert_res(ctx.eval(&root), Ok(2.0));
+        assert_res(ctx.eval(&root), Ok(2.0));
+    }
+
+    fn eval_with_policy(input: &str, policy: NumericPolicy) -> EvalResult {
+        let tokens = Lexer::get_tokens(input).unwrap();
+        let root = Parser::new(&tokens).parse().unwrap();
+        let mut ctx = EvalContext::populated();
+        ctx.set_numeric_policy(policy);
+        ctx.eval(&root)
+    }
+
+    #[test]
+    fn test_ieee_policy() {
+        assert_res(eval("1 / 0"), Ok(f64::INFINITY));
+        assert!(matches!(eval("0 / 0"), Ok(Value::Number(num)) if num.is_nan()));
+    }
+
+    #[test]
+    fn test_reject_non_finite_policy() {
+        let policy = NumericPolicy::RejectNonFinite;
+        assert_res(
+            eval_with_policy("x = 0\n2 + 1 / x", policy),
+            Err(EvalError::DivisionByZero(Location(1, 6))),
+        );
+        assert_res(
+            eval_with_policy("10 ^ 400", policy),
+            Err(EvalError::NonFiniteResult(f64::INFINITY, Location(0, 3))),
+        );
+        assert_res(
+            eval_with_policy("-INF", policy),
+            Err(EvalError::NonFiniteResult(f64::INFINITY, Location(0, 1))),
+        );
+        assert_res(
+            eval_with_policy("x = 1e999", policy),
+            Err(EvalError::NonFiniteResult(f64::INFINITY, Location(0, 4))),
+        );
+        assert_res(
+            eval_with_policy("x = 1e999 m", policy),
+            Err(EvalError::NonFiniteResult(f64::INFINITY, Location(0, 4))),
+        );
+        assert_res(
+            eval_with_policy("x = 1e999i", policy),
+            Err(EvalError::NonFiniteResult(f64::INFINITY, Location(0, 4))),
+        );
+        assert_res(
+            eval_with_policy("1 + sum([1e308, 1e308])", policy),
+            Err(EvalError::NonFiniteResult(f64::INFINITY, Location(0, 4))),
+        );
+        assert_res(
+            eval_with_policy("1e308 km in cm", policy),
+            Err(EvalError::NonFiniteResult(f64::INFINITY, Location(0, 9))),
+        );
+        assert_res(eval_with_policy("1 / 4", policy), Ok(0.25));
+    }
+
+    #[test]
+    fn test_reject_division_by_zero_policy() {
+        let policy = NumericPolicy::RejectDivisionByZero;
+        assert_res(
+            eval_with_policy("x = 1\nx /= 0", policy),
+            Err(EvalError::DivisionByZero(Location(1, 2))),
+        );
+        assert_res(eval_with_policy("INF + 1", policy), Ok(f64::INFINITY));
+    }
+
+    #[test]
+    fn test_equality_modes() {
+        let tokens = Lexer::get_tokens("1e20 == 1e20 + 1e5").unwrap();
+        let root = Parser::new(&tokens).parse().unwrap();
+        let mut ctx = EvalContext::populated();
+        assert_res(ctx.eval(&root), Ok(0.0));
+        ctx.set_equality(EqualityMode::Relative(1e-9));
+        assert_res(ctx.eval(&root), Ok(1.0));
+        ctx.set_equality(EqualityMode::Exact);
+        assert_res(ctx.eval(&root), Ok(0.0));
+
+        let tokens = Lexer::get_tokens("0.1 + 0.2 != 0.3").unwrap();
+        let root = Parser::new(&tokens).parse().unwrap();
+        assert_res(ctx.eval(&root), Ok(1.0));
+        ctx.set_equality(EqualityMode::Ulps(1));
+        assert_res(ctx.eval(&root), Ok(0.0));
+    }
+
+    #[test]
+    fn test_approx() {
+        assert_res(eval("approx(1e20, 1e20 + 1e5, 1e-9)"), Ok(1.0));
+        assert_res(eval("approx(PI, 3.14, 1e-3)"), Ok(1.0));
+        assert_res(eval("approx(PI, 3.14, 1e-4)"), Ok(0.0));
+        assert_res(
+            eval("approx(1, 2)"),
+            Err(EvalError::ArgumentCount(
+                String::from("approx"),
+                3,
+                2,
+                Location(0, 0),
+            )),
+        );
+    }
+
     #[test]
     fn test_nested() {
         assert_res(eval("(6 * 5) / 4 + 2"), Ok(9.5));
-        assert_res(eval("(2 * 5) / 3 - 2"), Ok(1.33333333333333333));
+        assert_res(eval("(2 * 5) / 3 - 2"), Ok(1.333_333_333_333_333_3));
         assert_res(eval("(6 * 5) / 4 + (8 * PI)"), Ok(32.632741228718345));
         assert_res(eval("(6 * 5) / 4 + (8 * PI) > 32"), Ok(1.0));
         assert_res(eval("(((((3)))))"), Ok(3.0));
@@ -7,31 +7,83 @@ pub struct FSM<T: std::cmp::Eq + std::hash::Hash> {
     pub next_state: Box<dyn Fn(T, char) -> Option<T>>,
 }
 
+/// Record of a run of an 'FSM' over some input, see 'FSM::run_traced'.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Trace<T> {
+    /// - states.         Every state visited by the FSM,
+    ///                   starting with the initial one.
+    pub states: Vec<T>,
+
+    /// - size.           The number of bytes of the input
+    ///                   consumed before the FSM stopped.
+    pub size: usize,
+
+    /// - last_accepting. The size in bytes of the longest prefix
+    ///                   of the input ending in an accepting state.
+    pub last_accepting: Option<usize>,
+
+    /// - stuck_on.       The character without a transition from the last
+    ///                   state, 'None' if the whole input was consumed.
+    pub stuck_on: Option<char>,
+}
+
+impl<T: Copy> Trace<T> {
+    /// The state where the FSM stopped.
+    pub fn last_state(&self) -> T {
+        *self.states.last().unwrap()
+    }
+}
+
 impl<'a, T: Eq + std::hash::Hash + Copy> FSM<T> {
     /// Runs this FSM on the specified 'input' string.
     /// Returns 'true' if 'input' or a subset of 'input' matches
     /// the regular expression corresponding to this FSM.
     pub fn run(&self, input: &'a str) -> Option<&'a str> {
+        let trace = self.run_traced(input);
+
+        if self.accepting_states.contains(&trace.last_state()) {
+            Some(&input[..trace.size])
+        } else {
+            None
+        }
+    }
+
+    /// Runs this FSM on the specified 'input' string until it gets stuck,
+    /// recording the states it goes through.
+    pub fn run_traced(&self, input: &str) -> Trace<T> {
         let mut current_state = self.initial_state;
-        let mut size: usize = 0;
+        let mut trace = Trace {
+            states: vec![current_state],
+            size: 0,
+            last_accepting: None,
+            stuck_on: None,
+        };
+
+        if self.accepting_states.contains(&current_state) {
+            trace.last_accepting = Some(0);
+        }
 
         for character in input.chars() {
             let next_state_fn = &self.next_state;
 
             match next_state_fn(current_state, character) {
-                Some(ref next_state) => {
-                    size += 1;
-                    current_state = *next_state
+                Some(next_state) => {
+                    trace.size += character.len_utf8();
+                    trace.states.push(next_state);
+                    current_state = next_state;
+
+                    if self.accepting_states.contains(&current_state) {
+                        trace.last_accepting = Some(trace.size);
+                    }
+                }
+                None => {
+                    trace.stuck_on = Some(character);
+                    break;
                 }
-                None => break,
             }
         }
 
-        if self.accepting_states.contains(&current_state) {
-            Some(&input[..size])
-        } else {
-            None
-        }
+        trace
     }
 }
 
@@ -114,4 +166,40 @@ mod tests {
         let input = "ident1f1er_cont4ining_d1g1ts";
         assert_eq!(Some(input), fsm.run(input));
     }
+
+    #[test]
+    fn test_traced_run() {
+        let fsm = identifier_fsm();
+        assert_eq!(
+            Trace {
+                states: vec![
+                    InitalState,
+                    AlphanumericOrUnderscore,
+                    AlphanumericOrUnderscore
+                ],
+                size: 2,
+                last_accepting: Some(2),
+                stuck_on: Some('+'),
+            },
+            fsm.run_traced("a1+b")
+        );
+    }
+
+    #[test]
+    fn test_traced_run_stuck_on_first_character() {
+        let fsm = identifier_fsm();
+        let trace = fsm.run_traced("1a");
+        assert_eq!(vec![InitalState], trace.states);
+        assert_eq!(None, trace.last_accepting);
+        assert_eq!(Some('1'), trace.stuck_on);
+        assert_eq!(InitalState, trace.last_state());
+    }
+
+    #[test]
+    fn test_traced_run_consumes_whole_input() {
+        let fsm = identifier_fsm();
+        let trace = fsm.run_traced("abc");
+        assert_eq!(3, trace.size);
+        assert_eq!(None, trace.stuck_on);
+    }
 }
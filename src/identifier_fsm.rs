@@ -0,0 +1,53 @@
+use crate::fsm::FSM;
+use unicode_xid::UnicodeXID;
+
+#[derive(Hash, Eq, PartialEq, Copy, Clone, Debug)]
+pub enum IdentifierFSMState {
+    Initial,
+    Identifier,
+}
+
+/// Recognizes identifiers made of a Unicode XID_Start character
+/// followed by any number of XID_Continue characters.
+pub fn build_identifier_recognizer() -> FSM<IdentifierFSMState> {
+    use IdentifierFSMState::*;
+    FSM {
+        states: [Initial, Identifier].iter().cloned().collect(),
+        initial_state: Initial,
+        accepting_states: [Identifier].iter().cloned().collect(),
+        next_state: Box::new(|current_state, character| match current_state {
+            Initial if UnicodeXID::is_xid_start(character) => Some(Identifier),
+            Identifier if UnicodeXID::is_xid_continue(character) => Some(Identifier),
+            _ => None,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_identifiers() {
+        let fsm = build_identifier_recognizer();
+        let identifiers = ["hello", "h3llo_w0rld", "Δt", "π", "größe", "résumé2"];
+        for identifier in identifiers.iter() {
+            assert_eq!(Some(*identifier), fsm.run(identifier));
+        }
+    }
+
+    #[test]
+    fn test_identifier_with_extra_data() {
+        let fsm = build_identifier_recognizer();
+        assert_eq!(Some("Δt"), fsm.run("Δt×2"));
+    }
+
+    #[test]
+    fn test_invalid_identifiers() {
+        let fsm = build_identifier_recognizer();
+        let identifiers = ["_hidden", "2pi", "≥", "×"];
+        for identifier in identifiers.iter() {
+            assert_eq!(None, fsm.run(identifier));
+        }
+    }
+}
@@ -0,0 +1,529 @@
+use crate::builtins::Builtin;
+use crate::equality::EqualityMode;
+use std::f64::consts::{FRAC_PI_2, PI, TAU};
+
+/// A closed range of numbers, evaluated by 'EvalContext' into guaranteed bounds: the bounds
+/// of a result are rounded outward, so that it holds the results of all the numbers of
+/// its operands.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Interval {
+    pub lo: f64,
+    pub hi: f64,
+}
+
+impl Interval {
+    /// The result of a comparison that may be either true or false.
+    pub const UNKNOWN: Interval = Interval { lo: 0.0, hi: 1.0 };
+
+    const ENTIRE: Interval = Interval {
+        lo: f64::NEG_INFINITY,
+        hi: f64::INFINITY,
+    };
+
+    const NAN: Interval = Interval {
+        lo: f64::NAN,
+        hi: f64::NAN,
+    };
+
+    /// Panics unless 'lo' is at most 'hi'.
+    pub fn new(lo: f64, hi: f64) -> Self {
+        assert!(lo <= hi, "Invalid interval [{}, {}]", lo, hi);
+        Interval { lo, hi }
+    }
+
+    pub fn point(num: f64) -> Self {
+        Interval { lo: num, hi: num }
+    }
+
+    pub fn contains(self, num: f64) -> bool {
+        self.lo <= num && num <= self.hi
+    }
+
+    fn is_nan(self) -> bool {
+        self.lo.is_nan() || self.hi.is_nan()
+    }
+
+    /// The interval from 'lo' to 'hi' widened by one float on each side,
+    /// which covers the rounding of the operation computing them.
+    fn rounded(lo: f64, hi: f64) -> Self {
+        Interval {
+            lo: lo.next_down(),
+            hi: hi.next_up(),
+        }
+    }
+
+    /// The interval holding all of 'bounds', each widened by one float for the functions
+    /// whose rounding error isn't known.
+    fn hull(bounds: &[f64]) -> Self {
+        if bounds.iter().any(|bound| bound.is_nan()) {
+            return Interval::NAN;
+        }
+
+        let lo = bounds.iter().cloned().fold(f64::INFINITY, f64::min);
+        let hi = bounds.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        Interval::rounded(lo, hi)
+    }
+
+    /// The interval holding 'op' of each pair of 'operands', where 'op' rounds
+    /// its result up or down as asked.
+    fn bounds(op: fn(f64, f64, bool) -> f64, operands: &[(f64, f64)]) -> Self {
+        if operands.iter().any(|&(l, r)| op(l, r, false).is_nan()) {
+            return Interval::NAN;
+        }
+
+        let bound = |up| operands.iter().map(move |&(l, r)| op(l, r, up));
+        Interval {
+            lo: bound(false).fold(f64::INFINITY, f64::min),
+            hi: bound(true).fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+
+    pub(crate) fn add(left: Interval, right: Interval) -> Interval {
+        Interval::bounds(sum, &[(left.lo, right.lo), (left.hi, right.hi)])
+    }
+
+    pub(crate) fn substract(left: Interval, right: Interval) -> Interval {
+        Interval::add(left, right.negate())
+    }
+
+    pub(crate) fn multiply(left: Interval, right: Interval) -> Interval {
+        Interval::bounds(
+            product,
+            &[
+                (left.lo, right.lo),
+                (left.lo, right.hi),
+                (left.hi, right.lo),
+                (left.hi, right.hi),
+            ],
+        )
+    }
+
+    /// Divides by an interval holding 0 into the smallest interval holding all the finite
+    /// quotients, which is unbounded.
+    pub(crate) fn divide(left: Interval, right: Interval) -> Interval {
+        if left.is_nan() || right.is_nan() {
+            return Interval::NAN;
+        }
+        if !right.contains(0.0) {
+            return Interval::bounds(
+                quotient,
+                &[
+                    (left.lo, right.lo),
+                    (left.lo, right.hi),
+                    (left.hi, right.lo),
+                    (left.hi, right.hi),
+                ],
+            );
+        }
+
+        let (inf, neg_inf) = (f64::INFINITY, f64::NEG_INFINITY);
+        let (lo, hi) = match (right.lo == 0.0, right.hi == 0.0) {
+            // The divisor is in [0, hi], with quotients of the sign of the dividend.
+            (true, false) if left.lo >= 0.0 => (quotient(left.lo, right.hi, false), inf),
+            (true, false) if left.hi <= 0.0 => (neg_inf, quotient(left.hi, right.hi, true)),
+            // The divisor is in [lo, 0], with quotients of the opposite sign.
+            (false, true) if left.lo >= 0.0 => (neg_inf, quotient(left.lo, right.lo, true)),
+            (false, true) if left.hi <= 0.0 => (quotient(left.hi, right.lo, false), inf),
+            _ => return Interval::ENTIRE,
+        };
+        Interval { lo, hi }
+    }
+
+    /// Raises to a power. Like 'sqrt', the other exponents only take the non-negative
+    /// numbers of the base, which is NaN when it has none.
+    pub(crate) fn power(base: Interval, exponent: Interval) -> Interval {
+        if base.is_nan() || exponent.is_nan() {
+            return Interval::NAN;
+        }
+
+        let n = exponent.lo;
+        if exponent.hi == n && n.fract() == 0.0 {
+            if n == 0.0 {
+                return Interval::point(1.0);
+            }
+            if n < 0.0 {
+                let power = Interval::power(base, Interval::point(-n));
+                return Interval::divide(Interval::point(1.0), power);
+            }
+
+            let (lo, hi) = (base.lo.powf(n), base.hi.powf(n));
+            let even = n % 2.0 == 0.0;
+            return if even && base.contains(0.0) {
+                Interval::hull(&[0.0, lo.max(hi)]).clamp_below(0.0)
+            } else {
+                Interval::hull(&[lo, hi])
+            };
+        }
+        if base.hi < 0.0 {
+            return Interval::NAN;
+        }
+
+        // The power is monotonic in each operand, and so bounded at the corners.
+        let base = Interval::new(base.lo.max(0.0), base.hi);
+        Interval::hull(&[
+            base.lo.powf(exponent.lo),
+            base.lo.powf(exponent.hi),
+            base.hi.powf(exponent.lo),
+            base.hi.powf(exponent.hi),
+        ])
+        .clamp_below(0.0)
+    }
+
+    pub(crate) fn negate(self) -> Interval {
+        Interval {
+            lo: -self.hi,
+            hi: -self.lo,
+        }
+    }
+
+    /// Applies a math built-in, where 'sqrt' and 'ln' only take the non-negative numbers.
+    pub(crate) fn apply(self, builtin: Builtin) -> Interval {
+        if self.is_nan() {
+            return Interval::NAN;
+        }
+
+        match builtin {
+            Builtin::Sqrt | Builtin::Ln if self.hi < 0.0 => Interval::NAN,
+            Builtin::Sqrt => {
+                Interval::hull(&[self.lo.max(0.0).sqrt(), self.hi.sqrt()]).clamp_below(0.0)
+            }
+            Builtin::Ln => Interval::hull(&[self.lo.max(0.0).ln(), self.hi.ln()]),
+            Builtin::Exp => Interval::hull(&[self.lo.exp(), self.hi.exp()]).clamp_below(0.0),
+            Builtin::Sin => self.periodic(f64::sin, FRAC_PI_2, -FRAC_PI_2),
+            Builtin::Cos => self.periodic(f64::cos, 0.0, PI),
+            Builtin::Tan if self.hi - self.lo >= PI || self.reaches(FRAC_PI_2, PI) => {
+                Interval::ENTIRE
+            }
+            Builtin::Tan => Interval::hull(&[self.lo.tan(), self.hi.tan()]),
+            Builtin::Abs if self.lo >= 0.0 => self,
+            Builtin::Abs if self.hi <= 0.0 => self.negate(),
+            Builtin::Abs => Interval {
+                lo: 0.0,
+                hi: self.hi.max(-self.lo),
+            },
+            Builtin::Arg if self.lo >= 0.0 => Interval::point(0.0),
+            // The float closest to PI is below it.
+            Builtin::Arg if self.hi < 0.0 => Interval::hull(&[PI]),
+            Builtin::Arg => Interval {
+                lo: 0.0,
+                hi: PI.next_up(),
+            },
+            Builtin::Conj => self,
+            _ => panic!("'{}' is not a math function", builtin.name()),
+        }
+    }
+
+    /// Applies 'sin' or 'cos', which reach 1 at 'maximum' and -1 at 'minimum' modulo 2 PI.
+    fn periodic(self, function: fn(f64) -> f64, maximum: f64, minimum: f64) -> Interval {
+        if self.hi - self.lo >= TAU {
+            return Interval::new(-1.0, 1.0);
+        }
+
+        let (lo, hi) = (function(self.lo), function(self.hi));
+        let mut res = Interval::hull(&[lo, hi]);
+        if self.reaches(maximum, TAU) {
+            res.hi = 1.0;
+        }
+        if self.reaches(minimum, TAU) {
+            res.lo = -1.0;
+        }
+
+        Interval {
+            lo: res.lo.max(-1.0),
+            hi: res.hi.min(1.0),
+        }
+    }
+
+    /// Whether the interval holds 'offset' plus a multiple of 'period'.
+    fn reaches(self, offset: f64, period: f64) -> bool {
+        let multiple = ((self.lo - offset) / period).ceil();
+        offset + multiple * period <= self.hi
+    }
+
+    /// Raises the lower bound to 'min', when the rounding took it below.
+    fn clamp_below(self, min: f64) -> Interval {
+        Interval {
+            lo: self.lo.max(min),
+            hi: self.hi,
+        }
+    }
+
+    pub(crate) fn min(left: Interval, right: Interval) -> Interval {
+        Interval {
+            lo: left.lo.min(right.lo),
+            hi: left.hi.min(right.hi),
+        }
+    }
+
+    pub(crate) fn max(left: Interval, right: Interval) -> Interval {
+        Interval {
+            lo: left.lo.max(right.lo),
+            hi: left.hi.max(right.hi),
+        }
+    }
+
+    /// 'Some(true)' when every number of 'left' is below every number of 'right',
+    /// 'Some(false)' when none is, and 'None' when it depends on the numbers.
+    pub(crate) fn less_than(left: Interval, right: Interval) -> Option<bool> {
+        if left.hi < right.lo {
+            Some(true)
+        } else if left.lo >= right.hi {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn less_than_or_equal(left: Interval, right: Interval) -> Option<bool> {
+        if left.hi <= right.lo {
+            Some(true)
+        } else if left.lo > right.hi {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn greater_than(left: Interval, right: Interval) -> Option<bool> {
+        Interval::less_than(right, left)
+    }
+
+    pub(crate) fn greater_than_or_equal(left: Interval, right: Interval) -> Option<bool> {
+        Interval::less_than_or_equal(right, left)
+    }
+
+    /// Intervals of a single number compare under 'mode', the others are never equal
+    /// when they are disjoint.
+    pub(crate) fn equal(left: Interval, right: Interval, mode: EqualityMode) -> Option<bool> {
+        if left.lo == left.hi && right.lo == right.hi {
+            Some(mode.equals(left.lo, right.lo))
+        } else if left.hi < right.lo || right.hi < left.lo {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+/// Rounds 'res' up or down, given 'error', the exact result minus 'res'.
+fn directed(res: f64, error: f64, up: bool) -> f64 {
+    if up && error > 0.0 {
+        res.next_up()
+    } else if !up && error < 0.0 {
+        res.next_down()
+    } else {
+        res
+    }
+}
+
+/// The bound for an infinite 'res' of finite operands, which overflowed past the
+/// largest float when rounded to the nearest one.
+fn overflowed(res: f64, up: bool) -> f64 {
+    match (res > 0.0, up) {
+        (true, false) => f64::MAX,
+        (false, true) => f64::MIN,
+        _ => res,
+    }
+}
+
+fn sum(left: f64, right: f64, up: bool) -> f64 {
+    let res = left + right;
+    if !res.is_finite() {
+        return if left.is_finite() && right.is_finite() {
+            overflowed(res, up)
+        } else {
+            res
+        };
+    }
+
+    // The exact rounding error of the addition, by Knuth's two-sum.
+    let right_part = res - left;
+    let error = (left - (res - right_part)) + (right - right_part);
+    directed(res, error, up)
+}
+
+fn product(left: f64, right: f64, up: bool) -> f64 {
+    // A null bound stays null when multiplied by an infinite one.
+    if left == 0.0 || right == 0.0 {
+        return 0.0;
+    }
+    let res = left * right;
+    if !res.is_finite() {
+        return if left.is_finite() && right.is_finite() {
+            overflowed(res, up)
+        } else {
+            res
+        };
+    }
+
+    // The exact rounding error of the product, computed with a single rounding.
+    directed(res, left.mul_add(right, -res), up)
+}
+
+fn quotient(left: f64, right: f64, up: bool) -> f64 {
+    let res = left / right;
+    if !res.is_finite() {
+        return if left.is_finite() && right != 0.0 {
+            overflowed(res, up)
+        } else {
+            res
+        };
+    }
+    if right.is_infinite() {
+        return res;
+    }
+
+    // The remainder 'left - res * right' has the sign of the error times 'right'.
+    let remainder = -res.mul_add(right, -left);
+    directed(res, remainder * right.signum(), up)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that 'interval' holds 'lo' to 'hi', and is at most a few floats wider than needed.
+    fn assert_bounds(interval: Interval, lo: f64, hi: f64) {
+        assert!(
+            interval.lo <= lo && hi <= interval.hi,
+            "{:?} doesn't hold [{}, {}]",
+            interval,
+            lo,
+            hi
+        );
+        let slack = |bound: f64| 4.0 * f64::EPSILON * bound.abs().max(1.0);
+        assert!(
+            (interval.lo == lo || lo - interval.lo <= slack(lo))
+                && (interval.hi == hi || interval.hi - hi <= slack(hi)),
+            "{:?} is wider than [{}, {}]",
+            interval,
+            lo,
+            hi
+        );
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let x = Interval::new(9.8, 10.2);
+        let y = Interval::new(-1.0, 2.0);
+        assert_bounds(Interval::add(x, y), 9.8 - 1.0, 10.2 + 2.0);
+        assert_bounds(Interval::substract(x, y), 9.8 - 2.0, 10.2 + 1.0);
+        assert_bounds(Interval::multiply(x, y), -10.2, 10.2 * 2.0);
+        assert_bounds(Interval::divide(y, x), -1.0 / 9.8, 2.0 / 9.8);
+        assert_eq!(Interval::new(-2.0, 1.0), y.negate());
+
+        // The bounds are rounded outward.
+        let sum = Interval::add(Interval::point(0.1), Interval::point(0.2));
+        assert_ne!(0.3, 0.1 + 0.2);
+        assert!(sum.contains(0.3));
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        let positive = Interval::new(1.0, 2.0);
+        assert_eq!(
+            Interval::ENTIRE,
+            Interval::divide(positive, Interval::new(-1.0, 1.0))
+        );
+        assert_eq!(
+            Interval::ENTIRE,
+            Interval::divide(positive, Interval::point(0.0))
+        );
+
+        let res = Interval::divide(positive, Interval::new(0.0, 4.0));
+        assert_bounds(res, 0.25, f64::INFINITY);
+        let res = Interval::divide(positive.negate(), Interval::new(0.0, 4.0));
+        assert_bounds(res, f64::NEG_INFINITY, -0.25);
+        let res = Interval::divide(positive, Interval::new(-4.0, 0.0));
+        assert_bounds(res, f64::NEG_INFINITY, -0.25);
+        let res = Interval::divide(Interval::new(-1.0, 1.0), Interval::new(0.0, 4.0));
+        assert_eq!(Interval::ENTIRE, res);
+    }
+
+    #[test]
+    fn test_power() {
+        let x = Interval::new(-2.0, 3.0);
+        assert_bounds(Interval::power(x, Interval::point(2.0)), 0.0, 9.0);
+        assert_bounds(Interval::power(x, Interval::point(3.0)), -8.0, 27.0);
+        assert_eq!(
+            Interval::point(1.0),
+            Interval::power(x, Interval::point(0.0))
+        );
+        let res = Interval::power(Interval::new(2.0, 4.0), Interval::point(-1.0));
+        assert_bounds(res, 0.25, 0.5);
+        let res = Interval::power(Interval::new(0.25, 4.0), Interval::new(0.5, 2.0));
+        assert_bounds(res, 0.0625, 16.0);
+        // Like 'sqrt', the negative numbers are left out.
+        let res = Interval::power(Interval::new(-1.0, 2.0), Interval::point(0.5));
+        assert_bounds(res, 0.0, 2f64.sqrt());
+        assert_eq!(
+            Interval::new(-1.0, 2.0).apply(Builtin::Sqrt),
+            Interval::power(Interval::new(-1.0, 2.0), Interval::point(0.5))
+        );
+        assert!(Interval::power(Interval::new(-2.0, -1.0), Interval::point(0.5)).is_nan());
+    }
+
+    #[test]
+    fn test_math() {
+        let x = Interval::new(1.0, 4.0);
+        assert_bounds(x.apply(Builtin::Sqrt), 1.0, 2.0);
+        assert_bounds(x.apply(Builtin::Ln), 0.0, 4f64.ln());
+        assert_bounds(x.apply(Builtin::Exp), 1f64.exp(), 4f64.exp());
+        assert_bounds(x.apply(Builtin::Sin), 4f64.sin(), 1.0);
+        assert_bounds(Interval::new(0.0, 1.0).apply(Builtin::Sin), 0.0, 1f64.sin());
+        assert_bounds(Interval::new(1.0, 2.0).apply(Builtin::Sin), 1f64.sin(), 1.0);
+        assert_bounds(
+            Interval::new(-1.0, 1.0).apply(Builtin::Cos),
+            1f64.cos(),
+            1.0,
+        );
+        assert_bounds(
+            Interval::new(3.0, 4.0).apply(Builtin::Cos),
+            -1.0,
+            4f64.cos(),
+        );
+        assert_eq!(Interval::ENTIRE, x.apply(Builtin::Tan));
+        assert_bounds(
+            Interval::new(-1.0, 1.0).apply(Builtin::Tan),
+            -1f64.tan(),
+            1f64.tan(),
+        );
+        assert_bounds(Interval::new(-1.0, 4.0).apply(Builtin::Sqrt), 0.0, 2.0);
+        assert_eq!(
+            Interval::new(1.0, 3.0),
+            Interval::new(-3.0, -1.0).apply(Builtin::Abs)
+        );
+        assert_eq!(
+            Interval::new(0.0, 3.0),
+            Interval::new(-3.0, 1.0).apply(Builtin::Abs)
+        );
+        assert_bounds(Interval::new(-3.0, -1.0).apply(Builtin::Arg), PI, PI);
+        assert_bounds(Interval::new(-3.0, 1.0).apply(Builtin::Arg), 0.0, PI);
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let x = Interval::new(1.0, 2.0);
+        assert_eq!(Some(true), Interval::less_than(x, Interval::new(3.0, 4.0)));
+        assert_eq!(Some(false), Interval::less_than(x, Interval::point(1.0)));
+        assert_eq!(None, Interval::less_than(x, Interval::point(1.5)));
+        assert_eq!(
+            Some(true),
+            Interval::less_than_or_equal(x, Interval::point(2.0))
+        );
+        assert_eq!(
+            Some(true),
+            Interval::greater_than_or_equal(x, Interval::point(1.0))
+        );
+        assert_eq!(None, Interval::greater_than(x, Interval::point(1.0)));
+
+        let mode = EqualityMode::default();
+        assert_eq!(None, Interval::equal(x, Interval::point(1.5), mode));
+        assert_eq!(Some(false), Interval::equal(x, Interval::point(3.0), mode));
+        let sum = 0.1 + 0.2;
+        assert_eq!(
+            Some(true),
+            Interval::equal(Interval::point(sum), Interval::point(0.3), mode)
+        );
+    }
+}
@@ -1,7 +1,70 @@
-use crate::number_fsm;
-
-const ARITHMETIC_OPERATORS: &str = "+-*/";
+use crate::fsm::FSM;
+use crate::identifier_fsm;
+use crate::lexer_generator::{LexerGenerator, Rejection};
+use crate::number_fsm::{self, NumberFSMState};
+use crate::regex;
+use std::rc::Rc;
+
+const ARITHMETIC_OPERATORS: &str = "+-*/^";
 const COMPARISON_OPERATORS: &str = "=<>";
+const UNICODE_OPERATORS: &str = "×÷≥≤≠";
+
+/// Reserved words of the language. They can't be used as identifiers.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Keyword {
+    If,
+    Else,
+    Let,
+    Const,
+    Fn,
+    True,
+    False,
+    And,
+    Or,
+    Not,
+    While,
+    For,
+    In,
+    Break,
+    Continue,
+}
+
+/// Table consulted when recognizing identifiers.
+const KEYWORDS: &[(&str, Keyword)] = &[
+    ("if", Keyword::If),
+    ("else", Keyword::Else),
+    ("let", Keyword::Let),
+    ("const", Keyword::Const),
+    ("fn", Keyword::Fn),
+    ("true", Keyword::True),
+    ("false", Keyword::False),
+    ("and", Keyword::And),
+    ("or", Keyword::Or),
+    ("not", Keyword::Not),
+    ("while", Keyword::While),
+    ("for", Keyword::For),
+    ("in", Keyword::In),
+    ("break", Keyword::Break),
+    ("continue", Keyword::Continue),
+];
+
+impl Keyword {
+    /// Returns the 'Keyword' spelled 'word', if it is reserved.
+    pub fn from_word(word: &str) -> Option<Self> {
+        KEYWORDS
+            .iter()
+            .find(|(keyword, _)| *keyword == word)
+            .map(|(_, keyword)| *keyword)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        KEYWORDS
+            .iter()
+            .find(|(_, keyword)| *keyword == self)
+            .map(|(word, _)| *word)
+            .unwrap()
+    }
+}
 
 /// Enumeration of all types of token.
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -10,26 +73,57 @@ pub enum TokenType {
     Identifier,
     Number,
 
+    /// Reserved words
+    Keyword(Keyword),
+
     /// Arithmetic operators
     Plus, // +
     Minus, // -
-    Times, // *
-    Div,   // /
+    Times, // * or ×
+    Div,   // / or ÷
+    Power, // ^ or **
 
     /// Comparison operators
     GreaterThan, // >
-    GreaterThanOrEqual, // >=
+    GreaterThanOrEqual, // >= or ≥
     LessThan,           // <
-    LessThanOrEqual,    // <=
+    LessThanOrEqual,    // <= or ≤
     Equal,              // ==
+    NotEqual,           // != or ≠
 
-    /// Assignment operator
+    /// Assignment operators
     Assign, // =
+    PlusAssign,  // +=
+    MinusAssign, // -=
+    TimesAssign, // *=
+    DivAssign,   // /=
+
+    /// Operators added by embedders, told apart by their lexeme
+    Operator,
 
     /// Parenthesis
     LeftParenthesis, // (
     RightParenthesis, // )
 
+    /// Braces
+    LeftBrace, // {
+    RightBrace, // }
+
+    /// Brackets
+    LeftBracket, // [
+    RightBracket, // ]
+
+    /// Separators
+    Comma, // ,
+    Semicolon, // ;
+    Colon,     // :
+
+    /// Field access
+    Dot, // .
+
+    /// Range of numbers
+    Range, // ..
+
     /// Special tokens
     EndOfInput,
 }
@@ -53,18 +147,70 @@ pub struct Token<'a> {
     pub column: usize,
 }
 
+/// Describes the tokens of a type, as in "expected number or '('".
+impl std::fmt::Display for TokenType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use TokenType::*;
+        let description = match self {
+            Identifier => "identifier",
+            Number => "number",
+            Keyword(keyword) => return write!(f, "'{}'", keyword.as_str()),
+            Plus => "'+'",
+            Minus => "'-'",
+            Times => "'*'",
+            Div => "'/'",
+            Power => "'^'",
+            GreaterThan => "'>'",
+            GreaterThanOrEqual => "'>='",
+            LessThan => "'<'",
+            LessThanOrEqual => "'<='",
+            Equal => "'=='",
+            NotEqual => "'!='",
+            Assign => "'='",
+            PlusAssign => "'+='",
+            MinusAssign => "'-='",
+            TimesAssign => "'*='",
+            DivAssign => "'/='",
+            Operator => "operator",
+            LeftParenthesis => "'('",
+            RightParenthesis => "')'",
+            LeftBrace => "'{'",
+            RightBrace => "'}'",
+            LeftBracket => "'['",
+            RightBracket => "']'",
+            Comma => "','",
+            Semicolon => "';'",
+            Colon => "':'",
+            Dot => "'.'",
+            Range => "'..'",
+            EndOfInput => "end of input",
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
 impl std::fmt::Display for Token<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use TokenType::*;
         let type_value = match self.ttype {
             Identifier => format!("Iden({})", self.value),
             Number => format!("Num({})", self.value),
-            Plus | Minus | Times | Div => format!("ArOp({})", self.value),
-            GreaterThan | GreaterThanOrEqual | LessThan | LessThanOrEqual | Equal => {
+            Keyword(_) => format!("Kw({})", self.value),
+            Plus | Minus | Times | Div | Power => format!("ArOp({})", self.value),
+            GreaterThan | GreaterThanOrEqual | LessThan | LessThanOrEqual | Equal | NotEqual => {
                 format!("ComOp({})", self.value)
             }
-            Assign => format!("Assi({})", self.value),
+            Assign | PlusAssign | MinusAssign | TimesAssign | DivAssign => {
+                format!("Assi({})", self.value)
+            }
+            Operator => format!("Op({})", self.value),
             LeftParenthesis | RightParenthesis => format!("Paren({})", self.value),
+            LeftBrace | RightBrace => format!("Brace({})", self.value),
+            LeftBracket | RightBracket => format!("Bracket({})", self.value),
+            Comma | Semicolon | Colon => format!("Sep({})", self.value),
+            Dot => format!("Dot({})", self.value),
+            Range => format!("Range({})", self.value),
             EndOfInput => String::from("EOI"),
         };
 
@@ -73,9 +219,9 @@ impl std::fmt::Display for Token<'_> {
 }
 
 pub struct Lexer<'a> {
-    input: &'a str,
+    generator: Rc<LexerGenerator>,
 
-    iter: std::iter::Peekable<std::str::Chars<'a>>,
+    input: &'a str,
 
     position: usize,
 
@@ -95,23 +241,92 @@ pub enum LexingError {
         line: usize,
         column: usize,
     },
-    #[fail(display = "Invalid number at line {} and column {}.", line, column)]
-    InvalidNumber { line: usize, column: usize },
+    #[fail(
+        display = "Invalid number at line {} and column {}, expected {}.",
+        line, column, expected
+    )]
+    InvalidNumber {
+        line: usize,
+        column: usize,
+        expected: &'static str,
+    },
 }
 
 type TokenRes<'a> = Result<Token<'a>, LexingError>;
 
+thread_local! {
+    static STANDARD_GENERATOR: Rc<LexerGenerator> = Rc::new(Lexer::standard_generator());
+}
+
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
+        Self::with_generator(input, STANDARD_GENERATOR.with(Rc::clone))
+    }
+
+    /// Creates a 'Lexer' recognizing the tokens described by 'generator'.
+    pub fn with_generator(input: &'a str, generator: Rc<LexerGenerator>) -> Self {
         Lexer {
+            generator,
             input,
-            iter: input.chars().peekable(),
             position: 0,
             line: 0,
             column: 0,
         }
     }
 
+    /// Returns the rules of the language, ready to be extended with new tokens.
+    pub fn standard_generator() -> LexerGenerator {
+        let mut generator = LexerGenerator::new();
+        generator
+            .skip_regex(r"\s+")
+            .expect("Invalid whitespace pattern");
+        generator.add_fsm(
+            identifier_fsm::build_identifier_recognizer(),
+            TokenType::Identifier,
+            0,
+        );
+        let number_recognizer = number_fsm::build_number_recognizer();
+        generator
+            .add_fsm(number_fsm::build_number_recognizer(), TokenType::Number, 0)
+            .on_reject(move |rejection| invalid_number(&number_recognizer, rejection));
+
+        let comparison_operators = COMPARISON_OPERATORS
+            .chars()
+            .flat_map(|op| vec![op.to_string(), format!("{}=", op)]);
+        let symbols = ARITHMETIC_OPERATORS
+            .chars()
+            .map(|op| op.to_string())
+            .chain(comparison_operators)
+            .chain(UNICODE_OPERATORS.chars().map(|op| op.to_string()))
+            .chain(vec![
+                String::from("**"),
+                String::from("!="),
+                String::from("+="),
+                String::from("-="),
+                String::from("*="),
+                String::from("/="),
+                String::from("("),
+                String::from(")"),
+                String::from("{"),
+                String::from("}"),
+                String::from("["),
+                String::from("]"),
+                String::from(","),
+                String::from(";"),
+                String::from(":"),
+                String::from("."),
+                String::from(".."),
+            ]);
+
+        for symbol in symbols {
+            generator
+                .add_regex(&regex::escape(&symbol), Self::match_token_type(&symbol), 0)
+                .expect("Invalid symbol pattern");
+        }
+
+        generator
+    }
+
     pub fn all_tokens(&mut self) -> Result<Vec<Token<'a>>, LexingError> {
         let mut tokens: Vec<Token<'a>> = vec![];
         let mut token_res = self.next_token();
@@ -141,159 +356,55 @@ impl<'a> Lexer<'a> {
 
     /// Returns the next recognized 'Token' in the input.
     fn next_token(&mut self) -> TokenRes<'a> {
-        // We skip all the whitespaces and new lines in the input.
-        self.skip_whitespaces_and_new_lines();
-
-        if self.position >= self.input.len() {
-            return Ok(Token {
-                ttype: TokenType::EndOfInput,
-                value: "",
-                line: self.line,
-                column: self.column,
-            });
-        }
-
-        match self.iter.peek() {
-            Some(character) if character.is_ascii_alphabetic() => self.recognize_identifier(),
-            Some('(') | Some(')') => self.recognize_parenthesis(),
-            Some(&op) if ARITHMETIC_OPERATORS.contains(op) => self.recognize_arithmetic_operator(),
-            Some(&op) if COMPARISON_OPERATORS.contains(op) => self.recognize_comparison_operator(),
-            Some(character) if character.is_digit(10) => self.recognize_number(),
-            Some(&character) => Err(unrecognized_character(self, character)),
-            None => panic!(
-                "Missing expected character in input at line {} and column {}.",
-                self.line, self.column
-            ),
-        }
-    }
-
-    fn expect_next(&mut self, message: &str) -> char {
-        self.iter.next().unwrap_or_else(|| {
-            panic!(
-                "Expected {} in input at line {} and column {}.",
-                message, self.line, self.column,
-            )
-        })
-    }
+        loop {
+            let Self { line, column, .. } = *self;
+            let rest = &self.input[self.position..];
 
-    fn recognize_identifier(&mut self) -> TokenRes<'a> {
-        let mut size = 0;
-        let line = self.line;
-        let column = self.column;
-        let position = self.position;
+            if rest.is_empty() {
+                return Ok(Token {
+                    ttype: TokenType::EndOfInput,
+                    value: "",
+                    line,
+                    column,
+                });
+            }
 
-        while let Some(&character) = self.iter.peek() {
-            if character.is_ascii_alphanumeric() || character == '_' {
-                size += 1;
-                self.iter.next();
-            } else {
-                break;
+            let (ttype, size) = self.generator.recognize(rest, line, column)?;
+            let value = &rest[..size];
+            self.consume(value);
+
+            // Lexemes without a token type, like whitespaces and new lines, are skipped.
+            if let Some(ttype) = ttype {
+                return Ok(Token {
+                    ttype: Self::recognize_identifier(ttype, value),
+                    value,
+                    line,
+                    column,
+                });
             }
         }
-
-        self.position += size;
-        self.column += size;
-
-        Ok(Token {
-            ttype: TokenType::Identifier,
-            value: &self.input[position..position + size],
-            line,
-            column,
-        })
-    }
-
-    fn recognize_parenthesis(&mut self) -> TokenRes<'a> {
-        let Self { line, column, .. } = *self;
-
-        let character = self.expect_next("parenthesis");
-
-        let (ttype, value) = if character == '(' {
-            (TokenType::LeftParenthesis, "(")
-        } else {
-            (TokenType::RightParenthesis, ")")
-        };
-
-        self.position += 1;
-        self.column += 1;
-
-        Ok(Token {
-            ttype,
-            value,
-            line,
-            column,
-        })
-    }
-
-    fn recognize_arithmetic_operator(&mut self) -> TokenRes<'a> {
-        let line = self.line;
-        let column = self.column;
-        let position = self.position;
-
-        self.expect_next("arithmetic operator");
-        self.position += 1;
-        self.column += 1;
-
-        let value = &self.input[position..position + 1];
-
-        Ok(Token {
-            ttype: Self::match_token_type(value),
-            value,
-            line,
-            column,
-        })
     }
 
-    fn recognize_comparison_operator(&mut self) -> TokenRes<'a> {
-        let line = self.line;
-        let column = self.column;
-        let position = self.position;
-
-        self.expect_next("comparison operator");
-
-        let value = if let Some('=') = self.iter.peek() {
-            self.iter.next();
-            self.position += 2;
-            self.column += 2;
-            &self.input[position..position + 2]
-        } else {
-            self.position += 1;
-            self.column += 1;
-
-            &self.input[position..position + 1]
-        };
-
-        Ok(Token {
-            ttype: Self::match_token_type(value),
-            value,
-            line,
-            column,
-        })
+    /// Turns identifiers found in the keyword table into keywords.
+    fn recognize_identifier(ttype: TokenType, value: &str) -> TokenType {
+        match ttype {
+            TokenType::Identifier => Keyword::from_word(value)
+                .map(TokenType::Keyword)
+                .unwrap_or(ttype),
+            _ => ttype,
+        }
     }
 
-    fn recognize_number(&mut self) -> TokenRes<'a> {
-        let line = self.line;
-        let column = self.column;
-        let position = self.position;
+    fn consume(&mut self, lexeme: &str) {
+        self.position += lexeme.len();
 
-        let fsm = number_fsm::build_number_recognizer();
-        let fsm_input = &self.input[position..];
-
-        if let Some(number) = fsm.run(&fsm_input) {
-            let size = number.len();
-            self.position += size;
-            self.column += size;
-            for _ in 0..size {
-                self.iter.next();
+        for character in lexeme.chars() {
+            if character == '\n' {
+                self.line += 1;
+                self.column = 0;
+            } else {
+                self.column += 1;
             }
-
-            Ok(Token {
-                ttype: TokenType::Number,
-                value: number,
-                line,
-                column,
-            })
-        } else {
-            Err(invalid_number(&self))
         }
     }
 
@@ -302,58 +413,62 @@ impl<'a> Lexer<'a> {
             // Arithmetic operators
             "+" => TokenType::Plus,
             "-" => TokenType::Minus,
-            "*" => TokenType::Times,
-            "/" => TokenType::Div,
+            "*" | "×" => TokenType::Times,
+            "/" | "÷" => TokenType::Div,
+            "^" | "**" => TokenType::Power,
 
             // Comparison operators
             ">" => TokenType::GreaterThan,
-            ">=" => TokenType::GreaterThanOrEqual,
+            ">=" | "≥" => TokenType::GreaterThanOrEqual,
             "<" => TokenType::LessThan,
-            "<=" => TokenType::LessThanOrEqual,
+            "<=" | "≤" => TokenType::LessThanOrEqual,
             "==" => TokenType::Equal,
+            "!=" | "≠" => TokenType::NotEqual,
 
-            // Assignment operator
+            // Assignment operators
             "=" => TokenType::Assign,
+            "+=" => TokenType::PlusAssign,
+            "-=" => TokenType::MinusAssign,
+            "*=" => TokenType::TimesAssign,
+            "/=" => TokenType::DivAssign,
 
             // Parenthesis
             "(" => TokenType::LeftParenthesis,
             ")" => TokenType::RightParenthesis,
 
-            _ => panic!("Operator {} not found in match token type.", value),
-        }
-    }
+            // Braces
+            "{" => TokenType::LeftBrace,
+            "}" => TokenType::RightBrace,
 
-    fn skip_whitespaces_and_new_lines(&mut self) {
-        while let Some(&character) = self.iter.peek() {
-            if !character.is_ascii_whitespace() {
-                break;
-            }
+            // Brackets
+            "[" => TokenType::LeftBracket,
+            "]" => TokenType::RightBracket,
 
-            self.iter.next();
-            self.position += 1;
+            // Separators
+            "," => TokenType::Comma,
+            ";" => TokenType::Semicolon,
+            ":" => TokenType::Colon,
 
-            if character == '\n' {
-                self.line += 1;
-                self.column = 0;
-            } else {
-                self.column += 1;
-            }
+            // Field access
+            "." => TokenType::Dot,
+
+            // Range
+            ".." => TokenType::Range,
+
+            _ => panic!("Operator {} not found in match token type.", value),
         }
     }
 }
 
-fn unrecognized_character(lexer: &Lexer, character: char) -> LexingError {
-    LexingError::UnrecognizedCharacter {
-        character,
-        line: lexer.line,
-        column: lexer.column,
-    }
-}
+/// Reports where and why 'fsm' got stuck reading the number in 'rejection'.
+fn invalid_number(fsm: &FSM<NumberFSMState>, rejection: &Rejection) -> LexingError {
+    let trace = fsm.run_traced(rejection.input);
+    let consumed = &rejection.input[..trace.size];
 
-fn invalid_number(lexer: &Lexer) -> LexingError {
     LexingError::InvalidNumber {
-        line: lexer.line,
-        column: lexer.column,
+        line: rejection.line,
+        column: rejection.column + consumed.chars().count(),
+        expected: trace.last_state().expected().unwrap_or("digit"),
     }
 }
 
@@ -361,6 +476,7 @@ fn invalid_number(lexer: &Lexer) -> LexingError {
 mod tests {
 
     use super::*;
+    use crate::sampler::Sampler;
     use LexingError::*;
 
     #[test]
@@ -369,7 +485,7 @@ mod tests {
         assert_eq!(Ok(vec![]), tokens);
     }
 
-    fn token_for_identifier(identifier: &str, column: usize) -> Token {
+    fn token_for_identifier(identifier: &str, column: usize) -> Token<'_> {
         Token {
             ttype: TokenType::Identifier,
             value: identifier,
@@ -406,6 +522,52 @@ mod tests {
         assert_eq!(Ok(vec![expected_token]), tokens);
     }
 
+    #[test]
+    fn test_unicode_identifiers() {
+        for identifier in ["Δt", "π", "größe", "Größe_2"].iter() {
+            let tokens = Lexer::get_tokens(identifier);
+            let expected_token = token_for_identifier(identifier, 0);
+            assert_eq!(Ok(vec![expected_token]), tokens);
+        }
+    }
+
+    #[test]
+    fn test_identifier_starting_with_underscore() {
+        let tokens = Lexer::get_tokens("_hello");
+        assert_eq!(
+            Err(UnrecognizedCharacter {
+                character: '_',
+                line: 0,
+                column: 0
+            }),
+            tokens
+        );
+    }
+
+    #[test]
+    fn test_keywords() {
+        for (word, keyword) in KEYWORDS.iter() {
+            let tokens = Lexer::get_tokens(word);
+            let expected_token = Token {
+                ttype: TokenType::Keyword(*keyword),
+                value: word,
+                line: 0,
+                column: 0,
+            };
+            assert_eq!(Ok(vec![expected_token]), tokens);
+            assert_eq!(*word, keyword.as_str());
+        }
+    }
+
+    #[test]
+    fn test_identifiers_containing_keywords() {
+        for identifier in ["iffy", "letter", "for_each", "True", "android"].iter() {
+            let tokens = Lexer::get_tokens(identifier);
+            let expected_token = token_for_identifier(identifier, 0);
+            assert_eq!(Ok(vec![expected_token]), tokens);
+        }
+    }
+
     fn left_paren(column: usize) -> Token<'static> {
         Token {
             ttype: TokenType::LeftParenthesis,
@@ -450,6 +612,79 @@ mod tests {
         assert_eq!(Ok(vec![right_paren(0), left_paren(1)]), tokens);
     }
 
+    #[test]
+    fn test_brackets() {
+        let tokens = Lexer::get_tokens("xs[-1]").unwrap();
+        let ttypes = tokens.iter().map(|token| token.ttype).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenType::Identifier,
+                TokenType::LeftBracket,
+                TokenType::Minus,
+                TokenType::Number,
+                TokenType::RightBracket
+            ],
+            ttypes
+        );
+    }
+
+    #[test]
+    fn test_dot_and_colon() {
+        let tokens = Lexer::get_tokens("{ a: r.b }").unwrap();
+        let ttypes = tokens.iter().map(|token| token.ttype).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenType::LeftBrace,
+                TokenType::Identifier,
+                TokenType::Colon,
+                TokenType::Identifier,
+                TokenType::Dot,
+                TokenType::Identifier,
+                TokenType::RightBrace
+            ],
+            ttypes
+        );
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        let tokens = Lexer::get_tokens("a += 1; b -= 2; c *= 3; d /= -4").unwrap();
+        let ttypes = tokens
+            .iter()
+            .filter(|token| !matches!(token.ttype, TokenType::Identifier | TokenType::Number))
+            .map(|token| token.ttype)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenType::PlusAssign,
+                TokenType::Semicolon,
+                TokenType::MinusAssign,
+                TokenType::Semicolon,
+                TokenType::TimesAssign,
+                TokenType::Semicolon,
+                TokenType::DivAssign,
+                TokenType::Minus
+            ],
+            ttypes
+        );
+    }
+
+    #[test]
+    fn test_comma() {
+        let tokens = Lexer::get_tokens("(a, b)").unwrap();
+        let ttypes = tokens.iter().map(|token| token.ttype).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenType::LeftParenthesis,
+                TokenType::Identifier,
+                TokenType::Comma,
+                TokenType::Identifier,
+                TokenType::RightParenthesis
+            ],
+            ttypes
+        );
+    }
+
     #[test]
     fn test_identifier_inside_paren() {
         let tokens = Lexer::get_tokens("(hello_world)");
@@ -490,7 +725,7 @@ mod tests {
         );
     }
 
-    fn an_operator(op: &str, column: usize) -> (Token, usize) {
+    fn an_operator(op: &str, column: usize) -> (Token<'_>, usize) {
         let ttype = Lexer::match_token_type(op);
         let token = Token {
             ttype,
@@ -499,7 +734,7 @@ mod tests {
             column,
         };
 
-        (token, column + op.len())
+        (token, column + op.chars().count())
     }
 
     #[test]
@@ -524,6 +759,47 @@ mod tests {
         assert_eq!(Ok(expected_tokens), tokens);
     }
 
+    #[test]
+    fn test_power_operators() {
+        let tokens = Lexer::get_tokens("2**3^4").unwrap();
+        let ttypes = tokens.iter().map(|token| token.ttype).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenType::Number,
+                TokenType::Power,
+                TokenType::Number,
+                TokenType::Power,
+                TokenType::Number
+            ],
+            ttypes
+        );
+    }
+
+    #[test]
+    fn test_range_after_number() {
+        let tokens = Lexer::get_tokens("1..10").unwrap();
+        let values = tokens
+            .iter()
+            .map(|token| (token.ttype, token.value))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (TokenType::Number, "1"),
+                (TokenType::Range, ".."),
+                (TokenType::Number, "10")
+            ],
+            values
+        );
+        assert_eq!(
+            Err(LexingError::InvalidNumber {
+                line: 0,
+                column: 2,
+                expected: "digit after decimal point"
+            }),
+            Lexer::get_tokens("1.+2")
+        );
+    }
+
     #[test]
     fn test_comparison_operators() {
         let ops = {
@@ -547,6 +823,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_unicode_operators() {
+        let ops = UNICODE_OPERATORS.chars().map(|op| op.to_string());
+        for op in ops.chain(vec![String::from("!=")]) {
+            let tokens = Lexer::get_tokens(&op);
+            let (expected_token, _) = an_operator(&op, 0);
+            assert_eq!(Ok(vec![expected_token]), tokens);
+        }
+
+        assert_eq!(TokenType::Times, Lexer::match_token_type("×"));
+        assert_eq!(TokenType::Div, Lexer::match_token_type("÷"));
+        assert_eq!(TokenType::GreaterThanOrEqual, Lexer::match_token_type("≥"));
+        assert_eq!(TokenType::LessThanOrEqual, Lexer::match_token_type("≤"));
+        assert_eq!(TokenType::NotEqual, Lexer::match_token_type("≠"));
+    }
+
+    #[test]
+    fn test_columns_count_characters() {
+        let tokens = Lexer::get_tokens("größe≥Δt × π");
+        assert_eq!(
+            Ok(vec![
+                token_for_identifier("größe", 0),
+                an_operator("≥", 5).0,
+                token_for_identifier("Δt", 6),
+                an_operator("×", 9).0,
+                token_for_identifier("π", 11),
+            ]),
+            tokens
+        );
+    }
+
     #[test]
     fn test_combination1() {
         let tokens = Lexer::get_tokens("=(hello>=<world+");
@@ -564,7 +871,7 @@ mod tests {
         );
     }
 
-    fn a_number(num: &str, column: usize) -> (Token, usize) {
+    fn a_number(num: &str, column: usize) -> (Token<'_>, usize) {
         let token = Token {
             ttype: TokenType::Number,
             value: num,
@@ -577,7 +884,9 @@ mod tests {
 
     #[test]
     fn test_a_single_number() {
-        let numbers = ["5", "2.37", "83e2", "4E57", "91.5e4", "2.83e+3", "3E+7"];
+        let numbers = [
+            "5", "2.37", "83e2", "4E57", "91.5e4", "2.83e+3", "3E+7", "3i", "2.5j", "1e-3i",
+        ];
         for number in numbers.iter() {
             let tokens = Lexer::get_tokens(number);
             let expected_token = a_number(number, 0).0;
@@ -587,10 +896,38 @@ mod tests {
 
     #[test]
     fn test_invalid_numbers() {
-        let numbers = ["2.", "83e", "4E", "91.e4"];
-        for number in numbers.iter() {
+        let numbers = [
+            ("2.", 2, "digit after decimal point"),
+            ("83e", 3, "digit after exponent marker"),
+            ("4E", 2, "digit after exponent marker"),
+            ("91.e4", 3, "digit after decimal point"),
+            ("2.i", 2, "digit after decimal point"),
+        ];
+        for (number, column, expected) in numbers.iter() {
             let tokens = Lexer::get_tokens(number);
-            assert_eq!(Err(InvalidNumber { line: 0, column: 0 }), tokens);
+            assert_eq!(
+                Err(InvalidNumber {
+                    line: 0,
+                    column: *column,
+                    expected
+                }),
+                tokens
+            );
+        }
+    }
+
+    #[test]
+    fn test_fuzz_numbers() {
+        let fsm = number_fsm::build_number_recognizer();
+        let mut sampler = Sampler::new(&fsm, "0123456789.eE+-", 10, 1);
+        for _ in 0..500 {
+            let number = sampler.accepted().unwrap();
+            let tokens = Lexer::get_tokens(&number);
+            assert_eq!(Ok(vec![a_number(&number, 0).0]), tokens);
+
+            let input = sampler.rejected().unwrap();
+            let tokens = Lexer::get_tokens(&input);
+            assert_ne!(Ok(vec![a_number(&input, 0).0]), tokens);
         }
     }
 
@@ -665,10 +1002,10 @@ mod tests {
 
     #[test]
     fn test_error2() {
-        let tokens = Lexer::get_tokens("var = 3\npi=3.14.");
+        let tokens = Lexer::get_tokens("var = 3\npi=3.14@");
         assert_eq!(
             Err(UnrecognizedCharacter {
-                character: '.',
+                character: '@',
                 line: 1,
                 column: 7
             }),
@@ -676,9 +1013,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_error_after_multibyte_characters() {
+        let tokens = Lexer::get_tokens("Δt ÷ π & 2");
+        assert_eq!(
+            Err(UnrecognizedCharacter {
+                character: '&',
+                line: 0,
+                column: 7
+            }),
+            tokens
+        );
+    }
+
     #[test]
     fn test_error3() {
         let tokens = Lexer::get_tokens("var = 3\npi=3.14e+ - 8");
-        assert_eq!(Err(InvalidNumber { line: 1, column: 3 }), tokens);
+        assert_eq!(
+            Err(InvalidNumber {
+                line: 1,
+                column: 9,
+                expected: "digit after exponent sign"
+            }),
+            tokens
+        );
+    }
+
+    #[test]
+    fn test_invalid_number_message() {
+        let error = Lexer::get_tokens("x = 83e").unwrap_err();
+        assert_eq!(
+            "Invalid number at line 0 and column 7, expected digit after exponent marker.",
+            error.to_string()
+        );
     }
 }
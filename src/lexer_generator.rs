@@ -0,0 +1,409 @@
+use crate::fsm::FSM;
+use crate::lexer::{LexingError, TokenType};
+use crate::regex::{self, RegexError};
+use std::hash::Hash;
+
+/// Builds the error reported when a rule consumes part of the input
+/// but gets stuck outside of an accepting state.
+type RejectFn = Box<dyn Fn(&Rejection) -> LexingError>;
+
+/// Describes the input a rule stopped on without accepting it.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Rejection<'i> {
+    /// - input.  The input, starting at the rejected lexeme.
+    pub input: &'i str,
+
+    /// - size.   The number of bytes consumed
+    ///           by the rule before getting stuck.
+    pub size: usize,
+
+    /// - line.   The line number where
+    ///           the rejected lexeme starts.
+    pub line: usize,
+
+    /// - column. The column number where
+    ///           the rejected lexeme starts.
+    pub column: usize,
+}
+
+/// A machine recognizing one kind of lexeme.
+pub struct Rule {
+    machine: Box<dyn Machine>,
+
+    /// - ttype.    The type of the emitted token,
+    ///             'None' if the lexeme is skipped.
+    ttype: Option<TokenType>,
+
+    /// - priority. Breaks ties between rules matching
+    ///             lexemes of the same length. Highest wins.
+    priority: usize,
+
+    on_reject: Option<RejectFn>,
+}
+
+impl Rule {
+    /// Reports the error built by 'on_reject' instead of an unrecognized character
+    /// when this rule made the most progress on the input without accepting it,
+    /// or when the lexeme following its fallback ends where it got stuck.
+    pub fn on_reject<F>(&mut self, on_reject: F) -> &mut Self
+    where
+        F: Fn(&Rejection) -> LexingError + 'static,
+    {
+        self.on_reject = Some(Box::new(on_reject));
+        self
+    }
+}
+
+/// Type erased view of an 'FSM' so rules with different state types can be run together.
+trait Machine {
+    fn start(&self) -> Box<dyn Run + '_>;
+}
+
+trait Run {
+    /// Feeds 'character' to the machine. Returns 'false' when it got stuck.
+    fn step(&mut self, character: char) -> bool;
+
+    fn is_accepting(&self) -> bool;
+}
+
+struct FSMRun<'f, T: Eq + Hash> {
+    fsm: &'f FSM<T>,
+    state: T,
+}
+
+impl<T: Eq + Hash + Copy> Machine for FSM<T> {
+    fn start(&self) -> Box<dyn Run + '_> {
+        Box::new(FSMRun {
+            fsm: self,
+            state: self.initial_state,
+        })
+    }
+}
+
+impl<T: Eq + Hash + Copy> Run for FSMRun<'_, T> {
+    fn step(&mut self, character: char) -> bool {
+        match (self.fsm.next_state)(self.state, character) {
+            Some(next_state) => {
+                self.state = next_state;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn is_accepting(&self) -> bool {
+        self.fsm.accepting_states.contains(&self.state)
+    }
+}
+
+/// Progress of a single rule once every machine stopped.
+struct Outcome {
+    /// - size.     The number of bytes consumed before getting stuck.
+    size: usize,
+
+    /// - accepted. The size of the longest lexeme accepted on the way, if any.
+    accepted: Option<usize>,
+}
+
+/// A lexer built from a list of rules.
+///
+/// All the machines are run in parallel over the input. The longest accepted
+/// lexeme wins, ties are broken by priority and then by registration order.
+/// A machine stuck outside of an accepting state falls back to the last lexeme it accepted.
+#[derive(Default)]
+pub struct LexerGenerator {
+    rules: Vec<Rule>,
+}
+
+impl LexerGenerator {
+    pub fn new() -> Self {
+        LexerGenerator { rules: vec![] }
+    }
+
+    /// Registers a rule emitting 'ttype' for the lexemes matching 'pattern'.
+    pub fn add_regex(
+        &mut self,
+        pattern: &str,
+        ttype: TokenType,
+        priority: usize,
+    ) -> Result<&mut Rule, RegexError> {
+        let fsm = regex::compile(pattern)?;
+        Ok(self.add_rule(Box::new(fsm), Some(ttype), priority))
+    }
+
+    /// Registers a rule emitting 'ttype' for the lexemes accepted by 'fsm'.
+    pub fn add_fsm<T>(&mut self, fsm: FSM<T>, ttype: TokenType, priority: usize) -> &mut Rule
+    where
+        T: Eq + Hash + Copy + 'static,
+    {
+        self.add_rule(Box::new(fsm), Some(ttype), priority)
+    }
+
+    /// Registers a rule discarding the lexemes matching 'pattern', like whitespaces.
+    pub fn skip_regex(&mut self, pattern: &str) -> Result<&mut Rule, RegexError> {
+        let fsm = regex::compile(pattern)?;
+        Ok(self.add_rule(Box::new(fsm), None, 0))
+    }
+
+    fn add_rule(
+        &mut self,
+        machine: Box<dyn Machine>,
+        ttype: Option<TokenType>,
+        priority: usize,
+    ) -> &mut Rule {
+        self.rules.push(Rule {
+            machine,
+            ttype,
+            priority,
+            on_reject: None,
+        });
+
+        self.rules.last_mut().unwrap()
+    }
+
+    /// Recognizes the lexeme at the start of the non empty 'input', found at 'line' and 'column'.
+    /// Returns the token type, or 'None' for skipped lexemes, and the size of the lexeme in bytes.
+    pub fn recognize(
+        &self,
+        input: &str,
+        line: usize,
+        column: usize,
+    ) -> Result<(Option<TokenType>, usize), LexingError> {
+        let outcomes = self.run_all(input);
+
+        let accepted = self
+            .rules
+            .iter()
+            .zip(outcomes.iter())
+            .filter_map(|(rule, outcome)| Some((rule, outcome, outcome.accepted?)))
+            .rev()
+            .max_by_key(|(rule, _, size)| (*size, rule.priority));
+
+        let rejected = match accepted {
+            Some((rule, outcome, size)) if !self.rejects(rule, outcome, size, input) => {
+                return Ok((rule.ttype, size));
+            }
+            Some((rule, outcome, _)) => Some((rule, outcome)),
+            None => self
+                .rules
+                .iter()
+                .zip(outcomes.iter())
+                .filter(|(_, outcome)| outcome.accepted.is_none() && outcome.size > 0)
+                .rev()
+                .max_by_key(|(rule, outcome)| (outcome.size, rule.priority)),
+        };
+
+        match rejected {
+            Some((
+                Rule {
+                    on_reject: Some(on_reject),
+                    ..
+                },
+                outcome,
+            )) => Err(on_reject(&Rejection {
+                input,
+                size: outcome.size,
+                line,
+                column,
+            })),
+            _ => Err(LexingError::UnrecognizedCharacter {
+                character: input.chars().next().unwrap(),
+                line,
+                column,
+            }),
+        }
+    }
+
+    /// Whether 'rule' reports its rejection instead of falling back to the lexeme of 'size'
+    /// it accepted. It does when the following lexeme doesn't get past where it got stuck,
+    /// so '1.' is an invalid number while '1..5' starts with the number '1'.
+    fn rejects(&self, rule: &Rule, outcome: &Outcome, size: usize, input: &str) -> bool {
+        rule.on_reject.is_some()
+            && outcome.size > size
+            && self
+                .recognize(&input[size..], 0, 0)
+                .map_or(true, |(_, next)| size + next <= outcome.size)
+    }
+
+    /// Runs every rule in lockstep until all of them got stuck or the input ended.
+    fn run_all(&self, input: &str) -> Vec<Outcome> {
+        let mut runs: Vec<_> = self.rules.iter().map(|rule| rule.machine.start()).collect();
+        let mut outcomes: Vec<_> = self
+            .rules
+            .iter()
+            .map(|_| Outcome {
+                size: 0,
+                accepted: None,
+            })
+            .collect();
+        let mut stuck = vec![false; self.rules.len()];
+
+        for (position, character) in input.char_indices() {
+            let mut running = false;
+            for (index, run) in runs.iter_mut().enumerate() {
+                if stuck[index] {
+                    continue;
+                }
+
+                if run.step(character) {
+                    let outcome = &mut outcomes[index];
+                    outcome.size = position + character.len_utf8();
+                    if run.is_accepting() {
+                        outcome.accepted = Some(outcome.size);
+                    }
+                    running = true;
+                } else {
+                    stuck[index] = true;
+                }
+            }
+
+            if !running {
+                break;
+            }
+        }
+
+        outcomes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{Lexer, Token};
+    use std::rc::Rc;
+    use TokenType::*;
+
+    fn tokens(
+        generator: LexerGenerator,
+        input: &str,
+    ) -> Result<Vec<(TokenType, &str)>, LexingError> {
+        Lexer::with_generator(input, Rc::new(generator))
+            .all_tokens()
+            .map(|tokens| {
+                tokens
+                    .into_iter()
+                    .map(|Token { ttype, value, .. }| (ttype, value))
+                    .collect()
+            })
+    }
+
+    #[test]
+    fn test_longest_match_wins() {
+        let mut generator = LexerGenerator::new();
+        generator.add_regex(r"\*", Times, 0).unwrap();
+        generator.add_regex(r"\*\*", Div, 0).unwrap();
+        assert_eq!(
+            Ok(vec![(Div, "**"), (Times, "*")]),
+            tokens(generator, "***")
+        );
+    }
+
+    #[test]
+    fn test_priority_breaks_ties() {
+        let mut generator = LexerGenerator::new();
+        generator.add_regex("[a-z]+", Identifier, 0).unwrap();
+        generator.add_regex("pi", Number, 1).unwrap();
+        generator.skip_regex(" +").unwrap();
+        assert_eq!(
+            Ok(vec![(Number, "pi"), (Identifier, "pie")]),
+            tokens(generator, "pi pie")
+        );
+    }
+
+    #[test]
+    fn test_registration_order_breaks_ties() {
+        let mut generator = LexerGenerator::new();
+        generator.add_regex("[a-z]+", Identifier, 0).unwrap();
+        generator.add_regex("pi", Number, 0).unwrap();
+        assert_eq!(Ok(vec![(Identifier, "pi")]), tokens(generator, "pi"));
+    }
+
+    #[test]
+    fn test_extending_the_standard_generator() {
+        let mut generator = Lexer::standard_generator();
+        generator.add_regex("<>", NotEqual, 0).unwrap();
+        assert_eq!(
+            Ok(vec![(Identifier, "a"), (NotEqual, "<>"), (Number, "2")]),
+            tokens(generator, "a <> 2")
+        );
+    }
+
+    #[test]
+    fn test_rejection_error() {
+        let mut generator = LexerGenerator::new();
+        generator
+            .add_regex("ab", Identifier, 0)
+            .unwrap()
+            .on_reject(|rejection| LexingError::InvalidNumber {
+                line: rejection.line,
+                column: rejection.column + rejection.size,
+                expected: "b",
+            });
+        assert_eq!(
+            Err(LexingError::InvalidNumber {
+                line: 0,
+                column: 1,
+                expected: "b"
+            }),
+            tokens(generator, "ac")
+        );
+    }
+
+    #[test]
+    fn test_fallback_to_last_accepted() {
+        let mut generator = LexerGenerator::new();
+        generator.add_regex("a(bc)?", Identifier, 0).unwrap();
+        generator.add_regex("[bd]", Number, 0).unwrap();
+        assert_eq!(
+            Ok(vec![(Identifier, "a"), (Number, "b"), (Number, "d")]),
+            tokens(generator, "abd")
+        );
+    }
+
+    fn number_generator() -> LexerGenerator {
+        let mut generator = LexerGenerator::new();
+        generator
+            .add_regex(r"\d+(\.\d+)?", Number, 0)
+            .unwrap()
+            .on_reject(|rejection| LexingError::InvalidNumber {
+                line: rejection.line,
+                column: rejection.column + rejection.size,
+                expected: "digit",
+            });
+        generator.add_regex(r"\.", Dot, 0).unwrap();
+        generator.add_regex(r"\.\.", Range, 0).unwrap();
+        generator.add_regex("[a-z]", Identifier, 0).unwrap();
+        generator
+    }
+
+    #[test]
+    fn test_rejection_after_fallback() {
+        assert_eq!(
+            Err(LexingError::InvalidNumber {
+                line: 0,
+                column: 2,
+                expected: "digit"
+            }),
+            tokens(number_generator(), "1.x")
+        );
+        // The '..' gets past the '.' the number rule got stuck after.
+        assert_eq!(
+            Ok(vec![(Number, "1"), (Range, ".."), (Number, "2.5")]),
+            tokens(number_generator(), "1..2.5")
+        );
+    }
+
+    #[test]
+    fn test_unrecognized_character() {
+        let mut generator = LexerGenerator::new();
+        generator.add_regex("ab", Identifier, 0).unwrap();
+        assert_eq!(
+            Err(LexingError::UnrecognizedCharacter {
+                character: 'a',
+                line: 0,
+                column: 0
+            }),
+            tokens(generator, "ac")
+        );
+    }
+}
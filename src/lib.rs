@@ -1,14 +1,51 @@
+// The impls generated by failure_derive live inside an anonymous constant.
+#![allow(non_local_definitions)]
+// Field docs are aligned in columns, see 'Token'.
+#![allow(clippy::doc_overindented_list_items)]
+
 extern crate failure;
 #[macro_use]
 extern crate failure_derive;
+extern crate unicode_xid;
 
+mod builtins;
+mod compiled;
+mod compiler;
+mod complex;
+mod derivative;
+mod dual;
+mod equality;
 mod eval;
 mod fsm;
+mod identifier_fsm;
+mod interval;
 mod lexer;
+mod lexer_generator;
 mod number_fsm;
+mod operators;
 mod parser;
+mod printer;
+mod regex;
+mod sampler;
+mod simplifier;
+mod units;
+mod value;
+mod vm;
 
-pub use eval::EvalContext;
-pub use fsm::FSM;
-pub use lexer::Lexer;
-pub use parser::Parser;
+pub use builtins::Builtin;
+pub use compiled::{CompileError, CompiledExpr};
+pub use compiler::{Instruction, Program};
+pub use complex::Complex;
+pub use dual::Dual;
+pub use equality::EqualityMode;
+pub use eval::{EvalContext, EvalError, NumericPolicy};
+pub use fsm::{Trace, FSM};
+pub use interval::Interval;
+pub use lexer::{Keyword, Lexer, LexingError, Token, TokenType};
+pub use lexer_generator::{LexerGenerator, Rejection, Rule};
+pub use operators::{Associativity, Operator, OperatorKind, OperatorTable};
+pub use parser::{Expected, Location, NodeType, ParseNode, Parser, ParsingError};
+pub use regex::RegexError;
+pub use sampler::Sampler;
+pub use units::{Quantity, Unit};
+pub use value::{Function, Value};
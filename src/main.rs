@@ -7,14 +7,13 @@ fn main() {
 
     let result = Lexer::new(&input)
         .all_tokens()
-        .map(|tokens| {
+        .inspect(|tokens| {
             println!("Lexer result:");
             for token in tokens.iter() {
                 print!("{} ", token)
             }
 
             println!();
-            tokens
         })
         .map_err(|err| err.to_string())
         .and_then(|tokens| Parser::new(&tokens).parse().map_err(|err| err.to_string()))
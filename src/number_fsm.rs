@@ -10,6 +10,7 @@ pub enum NumberFSMState {
     BeginNumberWithExponent,
     BeginNumberWithSignedExponent,
     NumberWithExponent,
+    ImaginaryNumber,
 }
 
 impl NumberFSMState {
@@ -23,11 +24,25 @@ impl NumberFSMState {
             BeginNumberWithExponent,
             BeginNumberWithSignedExponent,
             NumberWithExponent,
+            ImaginaryNumber,
         ]
         .iter()
         .cloned()
         .collect()
     }
+
+    /// Describes what the recognizer needs to read to leave this state,
+    /// or 'None' if the state is accepting.
+    pub fn expected(self) -> Option<&'static str> {
+        use NumberFSMState::*;
+        match self {
+            Initial => Some("digit"),
+            BeginNumberWithFractionalPart => Some("digit after decimal point"),
+            BeginNumberWithExponent => Some("digit after exponent marker"),
+            BeginNumberWithSignedExponent => Some("digit after exponent sign"),
+            Integer | NumberWithFractionalPart | NumberWithExponent | ImaginaryNumber => None,
+        }
+    }
 }
 
 pub fn build_number_recognizer() -> FSM<NumberFSMState> {
@@ -35,43 +50,58 @@ pub fn build_number_recognizer() -> FSM<NumberFSMState> {
     FSM {
         states: NumberFSMState::get_all(),
         initial_state: Initial,
-        accepting_states: [Integer, NumberWithFractionalPart, NumberWithExponent]
-            .iter()
-            .cloned()
-            .collect(),
+        accepting_states: [
+            Integer,
+            NumberWithFractionalPart,
+            NumberWithExponent,
+            ImaginaryNumber,
+        ]
+        .iter()
+        .cloned()
+        .collect(),
         next_state: Box::new(|current_state, character| {
             match current_state {
                 Initial => {
-                    if character.is_digit(10) {
+                    if character.is_ascii_digit() {
                         return Some(Integer);
                     }
                 }
                 Integer => match character {
-                    character if character.is_digit(10) => return Some(Integer),
+                    character if character.is_ascii_digit() => return Some(Integer),
                     '.' => return Some(BeginNumberWithFractionalPart),
                     'e' | 'E' => return Some(BeginNumberWithExponent),
+                    'i' | 'j' => return Some(ImaginaryNumber),
                     _ => (),
                 },
                 BeginNumberWithFractionalPart => {
-                    if character.is_digit(10) {
+                    if character.is_ascii_digit() {
                         return Some(NumberWithFractionalPart);
                     }
                 }
                 NumberWithFractionalPart => match character {
-                    character if character.is_digit(10) => return Some(NumberWithFractionalPart),
+                    character if character.is_ascii_digit() => {
+                        return Some(NumberWithFractionalPart)
+                    }
                     'e' | 'E' => return Some(BeginNumberWithExponent),
+                    'i' | 'j' => return Some(ImaginaryNumber),
                     _ => (),
                 },
                 BeginNumberWithExponent => match character {
-                    character if character.is_digit(10) => return Some(NumberWithExponent),
+                    character if character.is_ascii_digit() => return Some(NumberWithExponent),
                     '+' | '-' => return Some(BeginNumberWithSignedExponent),
                     _ => (),
                 },
-                BeginNumberWithSignedExponent | NumberWithExponent => {
-                    if character.is_digit(10) {
+                BeginNumberWithSignedExponent => {
+                    if character.is_ascii_digit() {
                         return Some(NumberWithExponent);
                     }
                 }
+                NumberWithExponent => match character {
+                    character if character.is_ascii_digit() => return Some(NumberWithExponent),
+                    'i' | 'j' => return Some(ImaginaryNumber),
+                    _ => (),
+                },
+                ImaginaryNumber => (),
             }
 
             None
@@ -82,11 +112,73 @@ pub fn build_number_recognizer() -> FSM<NumberFSMState> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::sampler::Sampler;
+
+    const ALPHABET: &str = "0123456789.eE+-";
+
+    /// Forms 'f64::from_str' parses which the language rejects on purpose: the special
+    /// values and the signs are read as identifiers and operators, and a point without
+    /// digits on both sides would make ranges like '1..5' ambiguous.
+    const EXCLUDED_PREFIXES: [&str; 5] = ["inf", "nan", "+", "-", "."];
+    const EXCLUDED_INFIXES: [&str; 2] = [".e", ".E"];
+    const EXCLUDED_SUFFIXES: [&str; 1] = ["."];
+
+    fn excluded(input: &str) -> bool {
+        let lowercase = input.to_lowercase();
+        EXCLUDED_PREFIXES
+            .iter()
+            .any(|prefix| lowercase.starts_with(prefix))
+            || EXCLUDED_INFIXES.iter().any(|infix| input.contains(infix))
+            || EXCLUDED_SUFFIXES
+                .iter()
+                .any(|suffix| input.ends_with(suffix))
+    }
+
+    fn assert_agrees_with_from_str(fsm: &FSM<NumberFSMState>, input: &str) {
+        let expected = input.parse::<f64>().is_ok() && !excluded(input);
+        assert_eq!(
+            expected,
+            fsm.run(input) == Some(input),
+            "{} disagrees with from_str",
+            input
+        );
+    }
+
+    #[test]
+    fn test_excluded_forms() {
+        let fsm = build_number_recognizer();
+        for input in ["inf", "NaN", "+1", "-1", ".5", "1.", "1.e5"].iter() {
+            assert!(input.parse::<f64>().is_ok() && excluded(input));
+            assert_ne!(Some(*input), fsm.run(input));
+        }
+    }
+
+    #[test]
+    fn test_accepted_numbers_agree_with_from_str() {
+        let fsm = build_number_recognizer();
+        let mut sampler = Sampler::new(&fsm, ALPHABET, 12, 2020);
+        for _ in 0..1000 {
+            let number = sampler.accepted().unwrap();
+            assert_agrees_with_from_str(&fsm, &number);
+        }
+    }
+
+    #[test]
+    fn test_rejected_numbers_agree_with_from_str() {
+        let fsm = build_number_recognizer();
+        let mut sampler = Sampler::new(&fsm, ALPHABET, 12, 2021);
+        for _ in 0..1000 {
+            let input = sampler.rejected().unwrap();
+            assert_agrees_with_from_str(&fsm, &input);
+        }
+    }
 
     #[test]
     fn test_valid_numbers() {
         let fsm = build_number_recognizer();
-        let numbers = ["5", "2.37", "83e2", "4E57", "91.5e4", "2.83e+3", "3E+7"];
+        let numbers = [
+            "5", "2.37", "83e2", "4E57", "91.5e4", "2.83e+3", "3E+7", "3i", "2.5j", "1e-3i",
+        ];
         for number in numbers.iter() {
             assert_eq!(Some(*number), fsm.run(number));
         }
@@ -98,14 +190,31 @@ mod tests {
         let input = "3.1416*2";
         let number = "3.1416";
         assert_eq!(Some(number), fsm.run(input));
+        assert_eq!(Some("2i"), fsm.run("2in"));
     }
 
     #[test]
     fn test_invalid_numbers() {
         let fsm = build_number_recognizer();
-        let numbers = ["l5", "2.", "83e", "4E", "91.e4"];
+        let numbers = ["l5", "2.", "83e", "4E", "91.e4", "i", "2.i", "4ei"];
         for number in numbers.iter() {
             assert_eq!(None, fsm.run(number));
         }
     }
+
+    #[test]
+    fn test_expected_after_invalid_numbers() {
+        let fsm = build_number_recognizer();
+        let numbers = [
+            ("2.", "digit after decimal point"),
+            ("83e", "digit after exponent marker"),
+            ("2.5E-x", "digit after exponent sign"),
+            ("91.e4", "digit after decimal point"),
+            ("7e-j", "digit after exponent sign"),
+        ];
+        for (number, expected) in numbers.iter() {
+            let trace = fsm.run_traced(number);
+            assert_eq!(Some(*expected), trace.last_state().expected());
+        }
+    }
 }
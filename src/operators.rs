@@ -0,0 +1,186 @@
+use crate::lexer::{Token, TokenType};
+use crate::parser::{NodeType, ParseNode};
+
+type Child = Box<ParseNode>;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Associativity {
+    Left,
+    Right,
+    /// Chaining the operator, like 'a < b < c', is an error.
+    NonAssociative,
+}
+
+/// Position of an operator relative to its operands,
+/// with the function building the node of the operation.
+#[derive(Copy, Clone)]
+pub enum OperatorKind {
+    Prefix(fn(Child) -> NodeType),
+    Infix(Associativity, fn(Child, Child) -> NodeType),
+    Postfix(fn(Child) -> NodeType),
+}
+
+#[derive(Copy, Clone)]
+pub struct Operator {
+    /// - token.      The type of the token of the operator.
+    pub token: TokenType,
+
+    /// - lexeme.     Restricts the operator to the tokens with this value,
+    ///               used to tell apart the 'TokenType::Operator' tokens.
+    pub lexeme: Option<&'static str>,
+
+    /// - precedence. How tightly the operator binds its operands.
+    ///               Operators with higher precedence are applied first.
+    pub precedence: u8,
+
+    pub kind: OperatorKind,
+}
+
+impl Operator {
+    pub fn prefix(token: TokenType, precedence: u8, build: fn(Child) -> NodeType) -> Self {
+        Self::new(token, precedence, OperatorKind::Prefix(build))
+    }
+
+    pub fn infix(
+        token: TokenType,
+        precedence: u8,
+        associativity: Associativity,
+        build: fn(Child, Child) -> NodeType,
+    ) -> Self {
+        Self::new(token, precedence, OperatorKind::Infix(associativity, build))
+    }
+
+    pub fn postfix(token: TokenType, precedence: u8, build: fn(Child) -> NodeType) -> Self {
+        Self::new(token, precedence, OperatorKind::Postfix(build))
+    }
+
+    fn new(token: TokenType, precedence: u8, kind: OperatorKind) -> Self {
+        Operator {
+            token,
+            lexeme: None,
+            precedence,
+            kind,
+        }
+    }
+
+    pub fn with_lexeme(mut self, lexeme: &'static str) -> Self {
+        self.lexeme = Some(lexeme);
+        self
+    }
+
+    pub fn is_prefix(&self) -> bool {
+        matches!(self.kind, OperatorKind::Prefix(_))
+    }
+
+    fn matches(&self, token: &Token) -> bool {
+        self.token == token.ttype && self.lexeme.is_none_or(|lexeme| lexeme == token.value)
+    }
+}
+
+/// Precedences of the standard operators.
+/// The conversion 'x in km' isn't in the table, its right side being a unit.
+pub(crate) const CONVERSION: u8 = 3;
+pub(crate) const RANGE: u8 = 5;
+pub(crate) const COMPARISON: u8 = 10;
+pub(crate) const ADDITIVE: u8 = 20;
+pub(crate) const MULTIPLICATIVE: u8 = 30;
+pub(crate) const NEGATION: u8 = 40;
+pub(crate) const POWER: u8 = 50;
+
+/// The operators known by a 'Parser'.
+/// A token has at most one prefix operator and one infix or postfix operator.
+#[derive(Clone)]
+pub struct OperatorTable {
+    operators: Vec<Operator>,
+}
+
+impl OperatorTable {
+    pub fn empty() -> Self {
+        OperatorTable { operators: vec![] }
+    }
+
+    pub fn standard() -> Self {
+        use Associativity::*;
+        use TokenType::{
+            Div, Equal, GreaterThan, GreaterThanOrEqual, LessThan, LessThanOrEqual, Minus,
+            NotEqual, Plus, Power, Range, Times,
+        };
+
+        let mut table = Self::empty();
+        let operators = vec![
+            Operator::infix(Range, RANGE, NonAssociative, NodeType::Range),
+            Operator::infix(
+                GreaterThan,
+                COMPARISON,
+                NonAssociative,
+                NodeType::GreaterThan,
+            ),
+            Operator::infix(
+                GreaterThanOrEqual,
+                COMPARISON,
+                NonAssociative,
+                NodeType::GreaterThanOrEqual,
+            ),
+            Operator::infix(LessThan, COMPARISON, NonAssociative, NodeType::LessThan),
+            Operator::infix(
+                LessThanOrEqual,
+                COMPARISON,
+                NonAssociative,
+                NodeType::LessThanOrEqual,
+            ),
+            Operator::infix(Equal, COMPARISON, NonAssociative, NodeType::Equal),
+            Operator::infix(NotEqual, COMPARISON, NonAssociative, NodeType::NotEqual),
+            Operator::infix(Plus, ADDITIVE, Left, NodeType::Sum),
+            Operator::infix(Minus, ADDITIVE, Left, NodeType::Substraction),
+            Operator::infix(Times, MULTIPLICATIVE, Left, NodeType::Multiplication),
+            Operator::infix(Div, MULTIPLICATIVE, Left, NodeType::Division),
+            Operator::prefix(Minus, NEGATION, NodeType::Negation),
+            Operator::infix(Power, POWER, Right, NodeType::Power),
+        ];
+
+        for operator in operators {
+            table.register(operator);
+        }
+
+        table
+    }
+
+    /// Adds 'operator' to the table, replacing the operator
+    /// of the same token and position if there was one.
+    pub fn register(&mut self, operator: Operator) {
+        self.operators.retain(|other| {
+            other.token != operator.token
+                || other.lexeme != operator.lexeme
+                || other.is_prefix() != operator.is_prefix()
+        });
+        self.operators.push(operator);
+    }
+
+    /// Returns the prefix operator of 'token'.
+    pub fn prefix(&self, token: &Token) -> Option<&Operator> {
+        self.find(token, true)
+    }
+
+    /// Returns the infix or postfix operator of 'token'.
+    pub fn infix(&self, token: &Token) -> Option<&Operator> {
+        self.find(token, false)
+    }
+
+    pub fn prefix_operators(&self) -> impl Iterator<Item = &Operator> {
+        self.operators
+            .iter()
+            .filter(|operator| operator.is_prefix())
+    }
+
+    pub fn infix_operators(&self) -> impl Iterator<Item = &Operator> {
+        self.operators
+            .iter()
+            .filter(|operator| !operator.is_prefix())
+    }
+
+    fn find(&self, token: &Token, prefix: bool) -> Option<&Operator> {
+        self.operators
+            .iter()
+            .find(|operator| operator.is_prefix() == prefix && operator.matches(token))
+    }
+}
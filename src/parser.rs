@@ -1,4 +1,8 @@
-use crate::lexer::{Token, TokenType};
+use crate::builtins;
+use crate::lexer::{Keyword, Token, TokenType};
+use crate::operators::{Associativity, Operator, OperatorKind, OperatorTable, CONVERSION};
+use crate::units::Unit;
+use std::collections::HashSet;
 use std::fmt::{Display, Formatter};
 
 type Child = Box<ParseNode>;
@@ -8,12 +12,31 @@ pub enum NodeType {
     /// Identifiers and literals
     Identifier(String),
     Number(f64),
+    /// A number followed by its unit, like '9.81 m/s^2'
+    Quantity(f64, Unit),
+    /// A number followed by 'i' or 'j', like '2.5i'
+    Imaginary(f64),
+    List(Vec<ParseNode>),
+    Record(Vec<(String, ParseNode)>),
+
+    /// List operations
+    Index(Child, Child),
+    Range(Child, Child),
+
+    /// Record operations
+    Field(Child, String),
+
+    /// Functions
+    Function(String, Vec<String>, Child),
+    Call(String, Vec<ParseNode>),
 
     /// Arithmetic operations
     Sum(Child, Child),
     Substraction(Child, Child),
     Multiplication(Child, Child),
     Division(Child, Child),
+    Power(Child, Child),
+    Negation(Child),
 
     /// Comparison operations
     GreaterThan(Child, Child),
@@ -21,12 +44,32 @@ pub enum NodeType {
     LessThan(Child, Child),
     LessThanOrEqual(Child, Child),
     Equal(Child, Child),
+    NotEqual(Child, Child),
+
+    /// Conversion of a quantity to a unit of the same dimension, like 'x in km'
+    Conversion(Child, Unit),
+    /// Interval between two bounds, assigned by 'x in [9.8, 10.2]'
+    Bounds(Child, Child),
 
     /// Assignment operations
     Assignment(String, Child),
+    /// Binding local to the enclosing block
+    Let(String, Child),
+    Const(String, Child),
 
-    /// Special node
+    /// Expressions separated by newlines or ';', valued as the last one
+    Block(Vec<ParseNode>),
+
+    /// Loops, valued as their last iteration
+    While(Child, Child),
+    For(String, Child, Child),
+    Break,
+    Continue,
+
+    /// Special nodes
     Root(Vec<ParseNode>),
+    /// Placeholder of an expression that failed to parse
+    Error,
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -41,23 +84,69 @@ pub struct ParseNode {
 pub struct Parser<'a> {
     input: &'a [Token<'a>],
     position: usize,
+
+    /// - line.   The line of the last token consumed. Outside of parentheses,
+    ///           the tokens of the following lines belong to the next statement.
     line: usize,
+
+    operators: OperatorTable,
+
+    /// - brackets. The closing tokens of the parentheses and brackets
+    ///             enclosing the current token inside of the innermost block.
+    brackets: Vec<TokenType>,
+
+    /// - blocks. The number of blocks enclosing the current token.
+    blocks: usize,
+
+    /// - errors. The errors recovered from so far.
+    errors: Vec<ParsingError>,
+
+    /// - constants. The built-in constants and the ones declared so far in the enclosing
+    ///              blocks, checked to reject their reassignments before evaluation.
+    constants: HashSet<String>,
+}
+
+/// Something the parser would have accepted where it failed.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Expected {
+    Token(TokenType),
+    EndOfLine,
+    /// A unit of measurement, like 'km/h'
+    Unit,
 }
 
 #[derive(Debug, PartialEq, Fail)]
 pub enum ParsingError {
-    UnexpectedToken(String, Location),
-    UnexpectedEndOfLine(Location),
-    ExpectedCloseParen(String, Location),
+    UnexpectedToken {
+        expected: Vec<Expected>,
+        found: TokenType,
+        lexeme: String,
+        location: Location,
+    },
+    UnexpectedEndOfLine {
+        expected: Vec<Expected>,
+        location: Location,
+    },
+    ReservedWordAssignment(String, Location),
+    /// The node is kept in the tree, which can still be evaluated.
+    ConstantAssignment(String, Location),
+    /// A unit raised to a power whose exponents don't fit, like 'm^99999999999'.
+    InvalidUnitPower(String, f64, Location),
+    /// A record field named again, left out of the tree.
+    DuplicateField(String, Location),
+    /// A non associative operator applied again to its own result, like 'a < b < c'.
+    ChainedOperator(String, Location),
     MultipleErrors(Vec<ParsingError>),
 }
 
 type ParseResult = Result<ParseNode, ParsingError>;
 type OptParseResult = Option<ParseResult>;
+type OptUnitResult = Option<Result<Unit, ParsingError>>;
 type OptToken<'a> = Option<&'a Token<'a>>;
 type FmtResult = std::fmt::Result;
 
 impl ParseNode {
+    #[cfg(test)]
     fn empty_root() -> Self {
         ParseNode {
             ntype: NodeType::Root(vec![]),
@@ -76,13 +165,19 @@ impl ParseNode {
             write!(f, "{} [{}:{}]>", name, self.location.0, self.location.1)?;
             nodes
                 .iter()
-                .map(|&node| node.internal_fmt(f, depth + 1))
-                .collect()
+                .try_for_each(|&node| node.internal_fmt(f, depth + 1))
         };
 
         match &self.ntype {
             Root(nodes) => fmt_with_nodes("Root", &nodes.iter().collect::<Vec<_>>()),
+            Error => write!(f, "Error [{}:{}]", self.location.0, self.location.1),
             Number(num) => write!(f, "{} [{}:{}]", num, self.location.0, self.location.1),
+            Imaginary(num) => write!(f, "{}i [{}:{}]", num, self.location.0, self.location.1),
+            Quantity(num, unit) => write!(
+                f,
+                "{} {} [{}:{}]",
+                num, unit, self.location.0, self.location.1
+            ),
             Identifier(identifier) => write!(
                 f,
                 "{} [{}:{}]",
@@ -98,6 +193,8 @@ impl ParseNode {
             Division(left_child, right_child) => {
                 fmt_with_nodes("Division", &[left_child, right_child])
             }
+            Power(left_child, right_child) => fmt_with_nodes("Power", &[left_child, right_child]),
+            Negation(child) => fmt_with_nodes("Negation", &[child]),
             GreaterThan(left_child, right_child) => {
                 fmt_with_nodes("GreaterThan", &[left_child, right_child])
             }
@@ -111,11 +208,63 @@ impl ParseNode {
                 fmt_with_nodes("LessThanOrEqual", &[left_child, right_child])
             }
             Equal(left_child, right_child) => fmt_with_nodes("Equal", &[left_child, right_child]),
+            NotEqual(left_child, right_child) => {
+                fmt_with_nodes("NotEqual", &[left_child, right_child])
+            }
+            Conversion(child, unit) => fmt_with_nodes(&format!("Conversion in {}", unit), &[child]),
+            Bounds(lo, hi) => fmt_with_nodes("Bounds", &[lo, hi]),
             Assignment(identifier, right_child) => {
                 writeln!(f, "Assignment>")?;
                 write!(f, "{} {}", depth_str, identifier)?;
                 right_child.internal_fmt(f, depth + 1)
             }
+            Let(identifier, right_child) => {
+                writeln!(f, "Let>")?;
+                write!(f, "{} {}", depth_str, identifier)?;
+                right_child.internal_fmt(f, depth + 1)
+            }
+            Const(identifier, right_child) => {
+                writeln!(f, "Const>")?;
+                write!(f, "{} {}", depth_str, identifier)?;
+                right_child.internal_fmt(f, depth + 1)
+            }
+            Block(nodes) => fmt_with_nodes("Block", &nodes.iter().collect::<Vec<_>>()),
+            While(condition, body) => fmt_with_nodes("While", &[condition, body]),
+            For(variable, iterable, body) => {
+                writeln!(f, "For [{}:{}]>", self.location.0, self.location.1)?;
+                write!(f, "{} {}", depth_str, variable)?;
+                [iterable, body]
+                    .iter()
+                    .try_for_each(|node| node.internal_fmt(f, depth + 1))
+            }
+            List(nodes) => fmt_with_nodes("List", &nodes.iter().collect::<Vec<_>>()),
+            Index(list, index) => fmt_with_nodes("Index", &[list, index]),
+            Range(start, end) => fmt_with_nodes("Range", &[start, end]),
+            Record(fields) => {
+                write!(f, "Record [{}:{}]>", self.location.0, self.location.1)?;
+                fields.iter().try_for_each(|(name, node)| {
+                    write!(f, "\n{} {}:", depth_str, name)?;
+                    node.internal_fmt(f, depth + 1)
+                })
+            }
+            Field(record, name) => {
+                writeln!(f, "Field [{}:{}]>", self.location.0, self.location.1)?;
+                write!(f, "{} {}", depth_str, name)?;
+                record.internal_fmt(f, depth + 1)
+            }
+            Function(name, params, body) => {
+                writeln!(f, "Function [{}:{}]>", self.location.0, self.location.1)?;
+                write!(f, "{} {}({})", depth_str, name, params.join(", "))?;
+                body.internal_fmt(f, depth + 1)
+            }
+            Call(name, args) => {
+                writeln!(f, "Call [{}:{}]>", self.location.0, self.location.1)?;
+                write!(f, "{} {}", depth_str, name)?;
+                args.iter()
+                    .try_for_each(|node| node.internal_fmt(f, depth + 1))
+            }
+            Break => write!(f, "Break [{}:{}]", self.location.0, self.location.1),
+            Continue => write!(f, "Continue [{}:{}]", self.location.0, self.location.1),
         }
     }
 }
@@ -132,87 +281,124 @@ impl<'a> Parser<'a> {
             input,
             position: 0,
             line: 0,
+            operators: OperatorTable::standard(),
+            brackets: vec![],
+            blocks: 0,
+            errors: vec![],
+            constants: builtins::CONSTANTS
+                .iter()
+                .map(|(name, _)| name.to_string())
+                .collect(),
         }
     }
 
+    /// Adds 'operator' to the operators known by the parser, replacing
+    /// the standard operator of the same token and position if there was one.
+    pub fn register_operator(&mut self, operator: Operator) {
+        self.operators.register(operator);
+    }
+
     pub fn parse(&mut self) -> ParseResult {
-        if self.input.is_empty() {
-            return Ok(ParseNode::empty_root());
+        let (root, errors) = self.parse_partial();
+        if errors.is_empty() {
+            Ok(root)
+        } else {
+            Err(ParsingError::MultipleErrors(errors))
         }
+    }
 
-        let lines = self.input.last().map(|token| token.line).unwrap();
-
-        let mut results = Vec::with_capacity(lines);
+    /// Parses the whole input, recovering from the errors. Returns the tree,
+    /// where 'NodeType::Error' nodes replace the invalid expressions,
+    /// along with every error found.
+    pub fn parse_partial(&mut self) -> (ParseNode, Vec<ParsingError>) {
+        let mut nodes = vec![];
+        while let Some(token) = self.input.get(self.position) {
+            if token.ttype == TokenType::Semicolon {
+                self.position += 1;
+            } else {
+                nodes.push(self.parse_statement());
+            }
+        }
 
-        loop {
-            let result = self.parse_line();
-            results.push(result);
-            if self.position >= self.input.len() {
-                break;
+        let root = ParseNode {
+            ntype: NodeType::Root(nodes),
+            location: Location(0, 0),
+        };
+        (root, std::mem::take(&mut self.errors))
+    }
+
+    /// Parses the statement starting at the current token, which ends at a ';',
+    /// at the end of the line outside of parentheses or at the end of the block.
+    fn parse_statement(&mut self) -> ParseNode {
+        self.line = self.input[self.position].line;
+        let start = self.position;
+        let node = self
+            .parse_expr()
+            .unwrap_or_else(|error| self.recover(error));
+        if self.current().is_some() {
+            let mut expected = self.operand_followers();
+            if self.position == start + 1 && matches!(node.ntype, NodeType::Identifier(_)) {
+                expected.push(Expected::Token(TokenType::Assign));
             }
+
+            let error = self.create_unexpected_error(expected);
+            self.report(error);
         }
 
-        results
-            .into_iter()
-            .fold(Ok(ParseNode::empty_root()), |result, line_result| {
-                match (result, line_result) {
-                    (
-                        Ok(ParseNode {
-                            ntype: NodeType::Root(mut nodes),
-                            location,
-                        }),
-                        Ok(node),
-                    ) => {
-                        nodes.push(node);
-                        Ok(ParseNode {
-                            ntype: NodeType::Root(nodes),
-                            location,
-                        })
-                    }
-                    (Ok(_), Err(err)) => Err(ParsingError::MultipleErrors(vec![err])),
-                    (Err(error), Ok(_)) => Err(error),
-                    (Err(ParsingError::MultipleErrors(mut errors)), Err(err)) => {
-                        errors.push(err);
-                        Err(ParsingError::MultipleErrors(errors))
-                    }
-                    (result, line_result) => {
-                        panic!("Unexpected tuple {:#?} and {:#?}", result, line_result)
-                    }
-                }
-            })
+        node
     }
 
-    fn parse_line(&mut self) -> ParseResult {
-        self.parse_expr()
-            .and_then(|node| {
-                if self.current().is_none() {
-                    self.line += 1;
-                    Ok(node)
-                } else {
-                    Err(self.create_unexpected_error())
-                }
-            })
-            .map_err(|err| {
-                self.line += 1;
-                self.move_to_next_line();
-                err
-            })
+    /// Records 'error' and skips the tokens up to the next synchronisation point.
+    /// Returns the location of the error.
+    fn report(&mut self, error: ParsingError) -> Location {
+        let location = error.location().unwrap_or(Location(self.line, 0));
+        self.errors.push(error);
+        self.synchronize();
+        location
     }
 
-    fn move_to_next_line(&mut self) {
-        while let Some(next) = self.input.get(self.position) {
-            if next.line == self.line {
-                break;
-            } else {
-                self.position += 1;
+    /// Like 'report', returning the placeholder of the invalid expression.
+    fn recover(&mut self, error: ParsingError) -> ParseNode {
+        ParseNode {
+            ntype: NodeType::Error,
+            location: self.report(error),
+        }
+    }
+
+    /// Skips the tokens up to the end of the statement, or up to the ')', ']' or ','
+    /// closing or separating the innermost enclosing parentheses or brackets.
+    fn synchronize(&mut self) {
+        use TokenType::*;
+        let mut nesting = 0;
+        while let Some(token) = self.current() {
+            match token.ttype {
+                RightParenthesis | RightBracket | Comma
+                    if nesting == 0 && !self.brackets.is_empty() =>
+                {
+                    break
+                }
+                RightBrace
+                    if nesting == 0
+                        && (self.blocks > 0 || self.brackets.last() == Some(&RightBrace)) =>
+                {
+                    break
+                }
+                LeftParenthesis | LeftBracket | LeftBrace => nesting += 1,
+                RightParenthesis | RightBracket | RightBrace if nesting > 0 => nesting -= 1,
+                _ => {}
             }
+            self.advance();
         }
     }
 
     fn look_ahead(&self, count: usize) -> OptToken<'a> {
         self.input
             .get(self.position + count)
-            .filter(|token| token.line == self.line)
+            .filter(|token| match token.ttype {
+                TokenType::Semicolon => false,
+                TokenType::RightBrace if self.brackets.is_empty() && self.blocks > 0 => false,
+                _ => !self.brackets.is_empty() || token.line == self.line,
+            })
     }
 
     fn current(&self) -> OptToken<'a> {
@@ -225,6 +411,7 @@ impl<'a> Parser<'a> {
 
     fn move_forward(&mut self, count: usize) {
         self.position += count;
+        self.line = self.last_token().line;
     }
 
     fn advance(&mut self) {
@@ -261,7 +448,10 @@ impl<'a> Parser<'a> {
         let value = *value;
         let ntype = match ttype {
             TokenType::Identifier => NodeType::Identifier(value.to_string()),
-            TokenType::Number => NodeType::Number(value.parse().unwrap()),
+            TokenType::Number => match value.strip_suffix(['i', 'j']) {
+                Some(imaginary) => NodeType::Imaginary(imaginary.parse().unwrap()),
+                None => NodeType::Number(value.parse().unwrap()),
+            },
             _ => panic!(
                 "Token of type {:?} and value '{}' passed to token_to_node",
                 ttype, value
@@ -274,34 +464,7 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn token_to_bin_op_node(
-        Token {
-            ttype,
-            value,
-            line,
-            column,
-        }: &Token<'_>,
-        left_child: ParseNode,
-        right_child: ParseNode,
-    ) -> ParseNode {
-        let left_child = Box::new(left_child);
-        let right_child = Box::new(right_child);
-        let ntype = match ttype {
-            TokenType::Plus => NodeType::Sum(left_child, right_child),
-            TokenType::Minus => NodeType::Substraction(left_child, right_child),
-            TokenType::Times => NodeType::Multiplication(left_child, right_child),
-            TokenType::Div => NodeType::Division(left_child, right_child),
-            TokenType::GreaterThan => NodeType::GreaterThan(left_child, right_child),
-            TokenType::GreaterThanOrEqual => NodeType::GreaterThanOrEqual(left_child, right_child),
-            TokenType::LessThan => NodeType::LessThan(left_child, right_child),
-            TokenType::LessThanOrEqual => NodeType::LessThanOrEqual(left_child, right_child),
-            TokenType::Equal => NodeType::Equal(left_child, right_child),
-            _ => panic!(
-                "Token of type {:?} and value '{}' passed to token_to_bin_op_node",
-                ttype, value
-            ),
-        };
-
+    fn operator_to_node(Token { line, column, .. }: &Token<'_>, ntype: NodeType) -> ParseNode {
         ParseNode {
             ntype,
             location: Location(*line, *column),
@@ -318,24 +481,45 @@ impl<'a> Parser<'a> {
         left_child: ParseNode,
         right_child: ParseNode,
     ) -> ParseNode {
+        let location = Location(*line, *column);
         let right_child = Box::new(right_child);
-        let ntype = match (ttype, left_child.ntype) {
-            (TokenType::Assign, NodeType::Identifier(value)) => {
-                NodeType::Assignment(value, right_child)
-            }
-            (TokenType::Assign, ntype) => panic!(
+        let identifier = match left_child.ntype {
+            NodeType::Identifier(identifier) => identifier,
+            ntype => panic!(
                 "Left node of type {:?} passed to token_to_assignment_node",
                 ntype
             ),
+        };
+
+        // 'x += 1' is read as 'x = x + 1'.
+        let build: fn(Child, Child) -> NodeType = match ttype {
+            TokenType::Assign => {
+                return ParseNode {
+                    ntype: NodeType::Assignment(identifier, right_child),
+                    location,
+                }
+            }
+            TokenType::PlusAssign => NodeType::Sum,
+            TokenType::MinusAssign => NodeType::Substraction,
+            TokenType::TimesAssign => NodeType::Multiplication,
+            TokenType::DivAssign => NodeType::Division,
             _ => panic!(
                 "Token of type {:?} and value '{}' passed to token_to_assignment_node",
                 ttype, value
             ),
         };
+        let target = ParseNode {
+            ntype: NodeType::Identifier(identifier.clone()),
+            location: left_child.location,
+        };
+        let operation = ParseNode {
+            ntype: build(Box::new(target), right_child),
+            location,
+        };
 
         ParseNode {
-            ntype,
-            location: Location(*line, *column),
+            ntype: NodeType::Assignment(identifier, Box::new(operation)),
+            location,
         }
     }
 
@@ -351,268 +535,999 @@ impl<'a> Parser<'a> {
             .map(Result::Ok)
     }
 
-    fn check_open_paren(&mut self, advance: bool) -> OptToken {
+    fn check_open_paren(&mut self, advance: bool) -> OptToken<'a> {
         self.check_current(TokenType::LeftParenthesis, advance)
     }
 
-    fn expect_close_paren(&mut self, node: ParseNode) -> ParseResult {
-        self.check_current(TokenType::RightParenthesis, true)
-            .map(|_| node)
-            .ok_or_else(|| self.create_close_paren_error())
-    }
+    /// Consumes the token closing the innermost brackets,
+    /// skipping what comes before it if it isn't the current token.
+    fn expect_closer(&mut self) {
+        let closer = *self.brackets.last().unwrap();
+        if self.check_current(closer, true).is_some() || self.reported_end_of_line() {
+            return;
+        }
 
-    fn parse_expr_in_parens(&mut self, advance: bool) -> OptParseResult {
-        match self.check_open_paren(advance) {
-            Some(_) => Some(
-                self.parse_right_expr()
-                    .and_then(|node| self.expect_close_paren(node)),
-            ),
-            None => None,
+        let error = self.create_unexpected_error(self.operand_followers());
+        self.report(error);
+        // Nothing is separated by commas inside these brackets.
+        while self.check_current(TokenType::Comma, true).is_some() {
+            self.synchronize();
         }
+        self.check_current(closer, true);
     }
 
-    fn check_current_in_list(&mut self, token_types: &[TokenType], advance: bool) -> OptToken<'a> {
-        token_types
-            .iter()
-            .find_map(|ttype| self.check_current(*ttype, advance))
+    fn parse_expr_in_parens(&mut self, advance: bool) -> OptParseResult {
+        self.check_open_paren(advance).map(|_| {
+            self.brackets.push(TokenType::RightParenthesis);
+            let node = self
+                .parse_right_expr()
+                .unwrap_or_else(|error| self.recover(error));
+            self.expect_closer();
+            self.brackets.pop();
+            Ok(node)
+        })
     }
 
-    fn check_assignment_op(&self) -> OptToken {
-        self.check_ahead(TokenType::Assign, 1)
+    /// Parses the comma separated items following an opening bracket, up to 'closer'.
+    fn parse_delimited<T>(
+        &mut self,
+        closer: TokenType,
+        mut parse_item: impl FnMut(&mut Self) -> T,
+    ) -> Vec<T> {
+        self.brackets.push(closer);
+        let mut nodes = vec![];
+        if self.check_current(closer, true).is_none() {
+            loop {
+                nodes.push(parse_item(self));
+                if self.check_current(TokenType::Comma, true).is_some() {
+                    continue;
+                }
+                if self.check_current(closer, true).is_some() || self.reported_end_of_line() {
+                    break;
+                }
+
+                let mut expected = self.operand_followers();
+                expected.insert(expected.len() - 1, Expected::Token(TokenType::Comma));
+                let error = self.create_unexpected_error(expected);
+                self.report(error);
+                if self.check_current(TokenType::Comma, true).is_none() {
+                    self.check_current(closer, true);
+                    break;
+                }
+            }
+        }
+
+        self.brackets.pop();
+        nodes
     }
 
-    fn parse_factor(&mut self) -> ParseResult {
-        self.parse_number(true)
-            .or_else(|| self.parse_identifier(true))
-            .or_else(|| self.parse_expr_in_parens(true))
-            .unwrap_or_else(|| Err(self.create_unexpected_error()))
+    fn parse_recovering_expr(&mut self) -> ParseNode {
+        self.parse_right_expr()
+            .unwrap_or_else(|error| self.recover(error))
     }
 
-    fn parse_term(&mut self) -> ParseResult {
-        let mut node = self.parse_factor()?;
-        loop {
-            let left_child = &node;
-            let res = self
-                .check_current_in_list(&[TokenType::Times, TokenType::Div], true)
-                .and_then(|token| {
-                    let res = self.parse_factor().map(|right_child| {
-                        Self::token_to_bin_op_node(token, left_child.clone(), right_child)
-                    });
-                    Some(res)
-                });
+    fn parse_list(&mut self) -> OptParseResult {
+        self.check_current(TokenType::LeftBracket, true)
+            .map(|open_bracket| {
+                let nodes =
+                    self.parse_delimited(TokenType::RightBracket, Self::parse_recovering_expr);
+                Ok(ParseNode {
+                    ntype: NodeType::List(nodes),
+                    location: Location(open_bracket.line, open_bracket.column),
+                })
+            })
+    }
 
-            match res {
-                Some(new_node) => node = new_node?,
-                None => break,
-            }
+    fn parse_call(&mut self) -> OptParseResult {
+        let name = self.check_current(TokenType::Identifier, false)?;
+        self.check_ahead(TokenType::LeftParenthesis, 1)?;
+        self.move_forward(2);
+        let args = self.parse_delimited(TokenType::RightParenthesis, Self::parse_recovering_expr);
+        Some(Ok(ParseNode {
+            ntype: NodeType::Call(name.value.to_string(), args),
+            location: Location(name.line, name.column),
+        }))
+    }
+
+    /// Parses a record like '{ qty: 3, price: 2.5 }',
+    /// told apart from a block by the ':' after its first identifier.
+    fn parse_record(&mut self) -> OptParseResult {
+        self.check_current(TokenType::LeftBrace, false)?;
+        let is_field = |offset: usize, ttype: TokenType| {
+            self.input
+                .get(self.position + offset)
+                .is_some_and(|token| token.ttype == ttype)
+        };
+        if !is_field(1, TokenType::Identifier) || !is_field(2, TokenType::Colon) {
+            return None;
         }
 
-        Ok(node)
+        let open_brace = self.check_current(TokenType::LeftBrace, true)?;
+        let mut names = HashSet::new();
+        let fields =
+            self.parse_delimited(TokenType::RightBrace, |parser| match parser.parse_field() {
+                Ok((name, _)) if !names.insert(name.value) => {
+                    let location = Location(name.line, name.column);
+                    parser.errors.push(ParsingError::DuplicateField(
+                        name.value.to_string(),
+                        location,
+                    ));
+                    None
+                }
+                Ok((name, node)) => Some((name.value.to_string(), node)),
+                Err(error) => {
+                    parser.report(error);
+                    None
+                }
+            });
+        Some(Ok(ParseNode {
+            ntype: NodeType::Record(fields.into_iter().flatten().collect()),
+            location: Location(open_brace.line, open_brace.column),
+        }))
+    }
+
+    fn parse_field(&mut self) -> Result<(&'a Token<'a>, ParseNode), ParsingError> {
+        let name = self.expect_token(TokenType::Identifier)?;
+        self.expect_token(TokenType::Colon)?;
+        let node = self.parse_right_expr()?;
+        Ok((name, node))
     }
 
-    fn parse_comp_term(&mut self) -> ParseResult {
-        let mut node = self.parse_term()?;
+    /// Parses the indexes and field accesses following 'node', like in 'orders[0].qty'.
+    fn parse_postfix(&mut self, mut node: ParseNode) -> ParseNode {
         loop {
-            let left_child = &node;
-            let res = self
-                .check_current_in_list(&[TokenType::Plus, TokenType::Minus], true)
-                .and_then(|token| {
-                    let res = self.parse_term().map(|right_child| {
-                        Self::token_to_bin_op_node(token, left_child.clone(), right_child)
-                    });
-                    Some(res)
-                });
+            if let Some(dot) = self.check_current(TokenType::Dot, true) {
+                let name = match self.expect_token(TokenType::Identifier) {
+                    Ok(name) => name.value.to_string(),
+                    Err(error) => return self.recover(error),
+                };
+                node = ParseNode {
+                    ntype: NodeType::Field(Box::new(node), name),
+                    location: Location(dot.line, dot.column),
+                };
+                continue;
+            }
 
-            match res {
-                Some(new_node) => node = new_node?,
+            let open_bracket = match self.check_current(TokenType::LeftBracket, true) {
+                Some(open_bracket) => open_bracket,
                 None => break,
-            }
+            };
+            self.brackets.push(TokenType::RightBracket);
+            let index = self.parse_recovering_expr();
+            self.expect_closer();
+            self.brackets.pop();
+            node = ParseNode {
+                ntype: NodeType::Index(Box::new(node), Box::new(index)),
+                location: Location(open_bracket.line, open_bracket.column),
+            };
         }
 
-        Ok(node)
+        node
     }
 
-    fn parse_right_expr(&mut self) -> ParseResult {
-        let node = self.parse_comp_term()?;
-        let left_child = &node;
-        self.check_current_in_list(
-            &[
-                TokenType::GreaterThan,
-                TokenType::GreaterThanOrEqual,
-                TokenType::LessThan,
-                TokenType::LessThanOrEqual,
-                TokenType::Equal,
-            ],
-            true,
-        )
-        .and_then(|token| {
-            let res = self.parse_comp_term().map(|right_child| {
-                Self::token_to_bin_op_node(token, left_child.clone(), right_child)
-            });
-            Some(res)
+    fn check_assignment_op(&self) -> OptToken<'a> {
+        use TokenType::*;
+        self.look_ahead(1).filter(|token| {
+            matches!(
+                token.ttype,
+                Assign | PlusAssign | MinusAssign | TimesAssign | DivAssign
+            )
         })
-        .unwrap_or_else(|| Ok(node))
     }
 
-    fn parse_expr(&mut self) -> ParseResult {
-        self.parse_identifier(false)
-            .and_then(Result::ok)
-            .and_then(|id_node| {
-                self.check_assignment_op().map(|assign_token| {
-                    // We have to return an empty node as right expression because `assign_token`
-                    // contains a reference to `self` and it would result in two functions
-                    // referencing `self`.
-                    Self::token_to_assignment_node(assign_token, id_node, ParseNode::empty_root())
-                })
-            })
-            .map(|node| match node {
+    fn check_reserved_word_assignment(&self) -> OptToken<'a> {
+        match self.current() {
+            Some(
+                token @ Token {
+                    ttype: TokenType::Keyword(_),
+                    ..
+                },
+            ) => self.check_assignment_op().map(|_| token),
+            _ => None,
+        }
+    }
+
+    fn parse_block(&mut self, advance: bool) -> OptParseResult {
+        self.check_current(TokenType::LeftBrace, advance)
+            .map(|open_brace| {
+                // Newlines separate the statements of the block even inside parentheses.
+                let brackets = std::mem::take(&mut self.brackets);
+                // The constants declared in the block are only bound inside it.
+                let constants = self.constants.clone();
+                self.blocks += 1;
+
+                let mut nodes = vec![];
+                loop {
+                    match self.input.get(self.position).map(|token| token.ttype) {
+                        Some(TokenType::Semicolon) => self.advance(),
+                        Some(TokenType::RightBrace) => {
+                            self.advance();
+                            break;
+                        }
+                        Some(_) => nodes.push(self.parse_statement()),
+                        None => {
+                            let error = self.create_unexpected_error(vec![Expected::Token(
+                                TokenType::RightBrace,
+                            )]);
+                            self.errors.push(error);
+                            break;
+                        }
+                    }
+                }
+
+                self.blocks -= 1;
+                self.brackets = brackets;
+                self.constants = constants;
                 ParseNode {
-                    ntype: NodeType::Assignment(id, _),
-                    location,
-                } => {
-                    self.move_forward(2);
-                    let right_expr = self.parse_right_expr()?;
-                    Ok(ParseNode {
-                        ntype: NodeType::Assignment(id, Box::new(right_expr)),
-                        location,
-                    })
+                    ntype: NodeType::Block(nodes),
+                    location: Location(open_brace.line, open_brace.column),
                 }
-                node => panic!("Expected assignment node. Got {:#?}", node),
             })
-            .unwrap_or_else(|| self.parse_right_expr())
+            .map(Result::Ok)
     }
 
-    fn create_unexpected_error(&self) -> ParsingError {
-        match self.current() {
-            Some(Token {
-                value,
-                line,
-                column,
-                ..
-            }) => ParsingError::UnexpectedToken((*value).to_string(), Location(*line, *column)),
-            None => {
-                let last_token = self.last_token();
-                ParsingError::UnexpectedEndOfLine(Location(
-                    last_token.line,
-                    last_token.column + last_token.value.len() - 1,
-                ))
+    /// Parses a unit like 'kg*m/s^2', made of the units of the table raised to integer
+    /// powers. Its operators are written without spaces, so 'x * 2 m * h' multiplies
+    /// by the variable 'h' instead of hours.
+    fn parse_unit(&mut self) -> OptUnitResult {
+        let mut unit = match self.parse_unit_power()? {
+            Ok(unit) => unit,
+            Err(error) => return Some(Err(error)),
+        };
+        loop {
+            let sign = match self.current() {
+                Some(token) if self.is_attached(token, 0) => match token.ttype {
+                    TokenType::Times => 1,
+                    TokenType::Div => -1,
+                    _ => break,
+                },
+                _ => break,
+            };
+            match self.look_ahead(1) {
+                Some(next) if self.is_attached(next, 1) => {}
+                _ => break,
             }
-        }
-    }
 
-    fn create_close_paren_error(&self) -> ParsingError {
-        match self.current() {
-            Some(Token {
-                value,
-                line,
-                column,
-                ..
-            }) => ParsingError::ExpectedCloseParen((*value).to_string(), Location(*line, *column)),
-            None => {
-                let last_token = self.last_token();
-                ParsingError::ExpectedCloseParen(
-                    String::from("EOL"),
-                    Location(
-                        last_token.line,
-                        last_token.column + last_token.value.len() - 1,
-                    ),
-                )
+            let position = self.position;
+            let operator = &self.input[position];
+            self.advance();
+            match self.parse_unit_power() {
+                Some(Ok(other)) => match unit.multiply(&other, sign) {
+                    Ok(product) => unit = product,
+                    Err((name, exponent)) => {
+                        let location = Location(operator.line, operator.column);
+                        let error = ParsingError::InvalidUnitPower(
+                            name.to_string(),
+                            exponent as f64,
+                            location,
+                        );
+                        return Some(Err(error));
+                    }
+                },
+                Some(Err(error)) => return Some(Err(error)),
+                None => {
+                    self.position = position;
+                    break;
+                }
             }
         }
+
+        Some(Ok(unit))
     }
-}
 
-impl std::fmt::Display for ParsingError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        use ParsingError::*;
-        match self {
-            UnexpectedToken(token, location) => {
-                write!(f, "Unexpected token '{}' at {:?}", token, location)
-            }
-            UnexpectedEndOfLine(location) => write!(f, "Unexpected end of line: {:?}", location),
-            ExpectedCloseParen(token, location) => write!(
-                f,
-                "Expected close parenthesis at '{:?}' got {}",
-                location, token
-            ),
-            MultipleErrors(errors) => {
-                for error in errors {
-                    writeln!(f, "{}", error)?;
-                }
+    /// Parses a unit of the table with its exponent, like in 's^2' or 's^-1'.
+    /// An integer exponent that the unit can't be raised to is an error.
+    fn parse_unit_power(&mut self) -> OptUnitResult {
+        let name = self.check_current(TokenType::Identifier, false)?;
+        let unit = Unit::named(name.value)?;
+        self.advance();
 
-                Ok(())
+        let attached = |parser: &Self, count| {
+            parser
+                .look_ahead(count)
+                .filter(|token| parser.is_attached(token, count))
+        };
+        let exponent = match attached(self, 0).map(|token| token.ttype) {
+            Some(TokenType::Power) => match attached(self, 1) {
+                Some(Token {
+                    ttype: TokenType::Minus,
+                    ..
+                }) => attached(self, 2)
+                    .filter(|token| token.ttype == TokenType::Number)
+                    .and_then(|token| token.value.parse::<f64>().ok())
+                    .map(|exponent| (-exponent, 3)),
+                // An imaginary exponent isn't a float, and ends the unit.
+                Some(Token {
+                    ttype: TokenType::Number,
+                    value,
+                    ..
+                }) => value.parse::<f64>().ok().map(|exponent| (exponent, 2)),
+                _ => None,
+            },
+            _ => None,
+        };
+        Some(match exponent {
+            Some((exponent, count)) if exponent.fract() == 0.0 => {
+                let location = Location(name.line, name.column);
+                let res = unit.power(exponent).ok_or_else(|| {
+                    ParsingError::InvalidUnitPower(name.value.to_string(), exponent, location)
+                });
+                self.move_forward(count);
+                res
             }
-        }
+            _ => Ok(unit),
+        })
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::lexer::Lexer;
+    /// Whether 'token', 'count' tokens ahead, directly follows the token before it.
+    fn is_attached(&self, token: &Token<'_>, count: usize) -> bool {
+        let previous = &self.input[self.position + count - 1];
+        previous.line == token.line
+            && previous.column + previous.value.chars().count() == token.column
+    }
 
-    fn wrap(node: ParseNode) -> ParseNode {
-        ParseNode {
-            ntype: NodeType::Root(vec![node]),
-            location: Location(0, 0),
+    /// Parses the unit of a whole input, for 'Unit::parse'.
+    pub(crate) fn parse_whole_unit(&mut self) -> Option<Unit> {
+        if self.input.is_empty() {
+            return None;
+        }
+        self.line = self.input[0].line;
+        let unit = self.parse_first_unit()?.ok()?;
+        if self.position == self.input.len() {
+            Some(unit)
+        } else {
+            None
         }
     }
 
-    fn wrap2(node1: ParseNode, node2: ParseNode) -> ParseNode {
-        ParseNode {
-            ntype: NodeType::Root(vec![node1, node2]),
-            location: Location(0, 0),
-        }
+    /// Parses a unit not preceded by an attached token, like after a number or 'in'.
+    fn parse_first_unit(&mut self) -> OptUnitResult {
+        let name = self.check_current(TokenType::Identifier, false)?;
+        Unit::named(name.value)?;
+        self.parse_unit()
     }
 
-    fn number_node(num: f64, (line, column): (usize, usize)) -> ParseNode {
-        ParseNode {
-            ntype: NodeType::Number(num),
-            location: Location(line, column),
-        }
+    /// Parses a number along with the unit that follows it, if there is one.
+    /// Imaginary numbers don't take a unit.
+    fn parse_quantity(&mut self) -> OptParseResult {
+        let number = self.parse_number(true)?;
+        Some(number.and_then(|node| match node.ntype {
+            NodeType::Number(num) => match self.parse_first_unit() {
+                Some(unit) => Ok(ParseNode {
+                    ntype: NodeType::Quantity(num, unit?),
+                    location: node.location,
+                }),
+                None => Ok(node),
+            },
+            _ => Ok(node),
+        }))
     }
 
-    fn identifier_node(value: &str, (line, column): (usize, usize)) -> ParseNode {
-        ParseNode {
-            ntype: NodeType::Identifier(String::from(value)),
-            location: Location(line, column),
+    fn parse_factor(&mut self) -> ParseResult {
+        self.parse_interval_assignment()
+            .or_else(|| self.parse_quantity())
+            .or_else(|| self.parse_call())
+            .or_else(|| self.parse_identifier(true))
+            .or_else(|| self.parse_expr_in_parens(true))
+            .or_else(|| self.parse_record())
+            .or_else(|| self.parse_block(true))
+            .or_else(|| self.parse_list())
+            .unwrap_or_else(|| Err(self.create_unexpected_error(self.operand_starters())))
+            .map(|node| self.parse_postfix(node))
+    }
+
+    /// The tokens that can start an operand.
+    fn operand_starters(&self) -> Vec<Expected> {
+        let mut expected = vec![
+            Expected::Token(TokenType::Number),
+            Expected::Token(TokenType::Identifier),
+            Expected::Token(TokenType::LeftParenthesis),
+            Expected::Token(TokenType::LeftBrace),
+            Expected::Token(TokenType::LeftBracket),
+        ];
+        for operator in self.operators.prefix_operators() {
+            let token = Expected::Token(operator.token);
+            if !expected.contains(&token) {
+                expected.push(token);
+            }
         }
+
+        expected
     }
 
-    fn multiplication_node(
-        left_child: ParseNode,
-        right_child: ParseNode,
-        (line, column): (usize, usize),
-    ) -> ParseNode {
-        let left_child = Box::new(left_child);
-        let right_child = Box::new(right_child);
-        ParseNode {
-            ntype: NodeType::Multiplication(left_child, right_child),
-            location: Location(line, column),
+    /// What can follow a complete operand: an index, a field, an operator or a conversion,
+    /// and then what ends the expression.
+    fn operand_followers(&self) -> Vec<Expected> {
+        let mut expected = vec![
+            Expected::Token(TokenType::Dot),
+            Expected::Token(TokenType::LeftBracket),
+        ];
+        for operator in self.operators.infix_operators() {
+            let token = Expected::Token(operator.token);
+            if !expected.contains(&token) {
+                expected.push(token);
+            }
+        }
+        expected.push(Expected::Token(TokenType::Keyword(Keyword::In)));
+        if let Some(&closer) = self.brackets.last() {
+            expected.push(Expected::Token(closer));
+        } else {
+            expected.push(Expected::EndOfLine);
+            if self.blocks > 0 {
+                expected.push(Expected::Token(TokenType::RightBrace));
+            }
         }
+
+        expected
     }
 
-    fn division_node(
-        left_child: ParseNode,
-        right_child: ParseNode,
-        (line, column): (usize, usize),
-    ) -> ParseNode {
-        let left_child = Box::new(left_child);
-        let right_child = Box::new(right_child);
-        ParseNode {
-            ntype: NodeType::Division(left_child, right_child),
-            location: Location(line, column),
-        }
+    fn check_prefix_op(&self) -> Option<(&'a Token<'a>, Operator)> {
+        self.current().and_then(|token| {
+            self.operators
+                .prefix(token)
+                .map(|operator| (token, *operator))
+        })
     }
 
-    fn sum_node(
-        left_child: ParseNode,
-        right_child: ParseNode,
-        (line, column): (usize, usize),
-    ) -> ParseNode {
-        let left_child = Box::new(left_child);
-        let right_child = Box::new(right_child);
-        ParseNode {
+    fn check_infix_op(&self) -> Option<(&'a Token<'a>, Operator)> {
+        self.current().and_then(|token| {
+            self.operators
+                .infix(token)
+                .map(|operator| (token, *operator))
+        })
+    }
+
+    /// Parses the operations whose operators bind tighter than 'min_precedence'.
+    fn parse_operation(&mut self, min_precedence: u8) -> ParseResult {
+        let mut node = match self.check_prefix_op() {
+            Some((token, operator)) => {
+                self.advance();
+                let child = self.parse_operation(operator.precedence)?;
+                match operator.kind {
+                    OperatorKind::Prefix(build) => {
+                        Self::operator_to_node(token, build(Box::new(child)))
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            None => self
+                .parse_factor()
+                .unwrap_or_else(|error| self.recover(error)),
+        };
+
+        // Precedence of the last non associative operator applied to 'node'.
+        let mut non_associative = None;
+        loop {
+            if CONVERSION > min_precedence {
+                if let Some(in_token) = self.check_current(TokenType::Keyword(Keyword::In), true) {
+                    let unit = self
+                        .parse_first_unit()
+                        .ok_or_else(|| self.create_unexpected_error(vec![Expected::Unit]))??;
+                    let ntype = NodeType::Conversion(Box::new(node), unit);
+                    node = Self::operator_to_node(in_token, ntype);
+                    continue;
+                }
+            }
+            let (token, operator) = match self.check_infix_op() {
+                Some(infix) => infix,
+                None => break,
+            };
+            let chained = non_associative == Some(operator.precedence)
+                && matches!(
+                    operator.kind,
+                    OperatorKind::Infix(Associativity::NonAssociative, _)
+                );
+            if operator.precedence <= min_precedence {
+                break;
+            }
+            if chained {
+                return Err(ParsingError::ChainedOperator(
+                    token.value.to_string(),
+                    Location(token.line, token.column),
+                ));
+            }
+
+            self.advance();
+            non_associative = None;
+            let ntype = match operator.kind {
+                OperatorKind::Infix(associativity, build) => {
+                    let right_precedence = match associativity {
+                        Associativity::Right => operator.precedence.saturating_sub(1),
+                        Associativity::Left => operator.precedence,
+                        Associativity::NonAssociative => {
+                            non_associative = Some(operator.precedence);
+                            operator.precedence
+                        }
+                    };
+                    let right_child = self.parse_operation(right_precedence)?;
+                    build(Box::new(node), Box::new(right_child))
+                }
+                OperatorKind::Postfix(build) => build(Box::new(node)),
+                OperatorKind::Prefix(_) => unreachable!(),
+            };
+            node = Self::operator_to_node(token, ntype);
+        }
+
+        Ok(node)
+    }
+
+    fn parse_right_expr(&mut self) -> ParseResult {
+        self.parse_operation(0)
+    }
+
+    /// Parses 'let x = 1' or 'const X = 1'.
+    fn parse_declaration(&mut self) -> OptParseResult {
+        self.check_current(TokenType::Keyword(Keyword::Let), true)
+            .or_else(|| self.check_current(TokenType::Keyword(Keyword::Const), true))
+            .map(|keyword| {
+                if let Some(token) = self.check_reserved_word_assignment() {
+                    return Err(ParsingError::ReservedWordAssignment(
+                        token.value.to_string(),
+                        Location(token.line, token.column),
+                    ));
+                }
+
+                let identifier = self.expect_token(TokenType::Identifier)?;
+                let assign_token = self.expect_token(TokenType::Assign)?;
+
+                let right_expr = self.parse_right_expr()?;
+                let name = identifier.value.to_string();
+                let location = Location(assign_token.line, assign_token.column);
+                self.check_constant(&name, location);
+                let ntype = if keyword.ttype == TokenType::Keyword(Keyword::Const) {
+                    self.constants.insert(name.clone());
+                    NodeType::Const(name, Box::new(right_expr))
+                } else {
+                    NodeType::Let(name, Box::new(right_expr))
+                };
+                Ok(ParseNode { ntype, location })
+            })
+    }
+
+    /// Reports the reassignment of a constant, keeping the assignment in the tree.
+    fn check_constant(&mut self, name: &str, location: Location) {
+        if self.constants.contains(name) {
+            self.errors
+                .push(ParsingError::ConstantAssignment(name.to_string(), location));
+        }
+    }
+
+    fn expect_block(&mut self) -> ParseResult {
+        self.parse_block(true).unwrap_or_else(|| {
+            Err(self.create_unexpected_error(vec![Expected::Token(TokenType::LeftBrace)]))
+        })
+    }
+
+    fn expect_token(&mut self, token_type: TokenType) -> Result<&'a Token<'a>, ParsingError> {
+        self.check_current(token_type, true)
+            .ok_or_else(|| self.create_unexpected_error(vec![Expected::Token(token_type)]))
+    }
+
+    fn parse_while(&mut self) -> OptParseResult {
+        self.check_current(TokenType::Keyword(Keyword::While), true)
+            .map(|while_token| {
+                let condition = self.parse_right_expr()?;
+                let body = self.expect_block()?;
+                Ok(ParseNode {
+                    ntype: NodeType::While(Box::new(condition), Box::new(body)),
+                    location: Location(while_token.line, while_token.column),
+                })
+            })
+    }
+
+    fn parse_for(&mut self) -> OptParseResult {
+        self.check_current(TokenType::Keyword(Keyword::For), true)
+            .map(|for_token| {
+                let variable = self.expect_token(TokenType::Identifier)?;
+                self.expect_token(TokenType::Keyword(Keyword::In))?;
+                let iterable = self.parse_right_expr()?;
+                let body = self.expect_block()?;
+                Ok(ParseNode {
+                    ntype: NodeType::For(
+                        variable.value.to_string(),
+                        Box::new(iterable),
+                        Box::new(body),
+                    ),
+                    location: Location(for_token.line, for_token.column),
+                })
+            })
+    }
+
+    fn parse_function(&mut self) -> OptParseResult {
+        self.check_current(TokenType::Keyword(Keyword::Fn), true)
+            .map(|fn_token| {
+                let name = self.expect_token(TokenType::Identifier)?;
+                self.expect_token(TokenType::LeftParenthesis)?;
+                let mut params = vec![];
+                if self
+                    .check_current(TokenType::RightParenthesis, true)
+                    .is_none()
+                {
+                    loop {
+                        params.push(self.expect_token(TokenType::Identifier)?.value.to_string());
+                        if self.check_current(TokenType::Comma, true).is_none() {
+                            break;
+                        }
+                    }
+                    self.check_current(TokenType::RightParenthesis, true)
+                        .ok_or_else(|| {
+                            self.create_unexpected_error(vec![
+                                Expected::Token(TokenType::Comma),
+                                Expected::Token(TokenType::RightParenthesis),
+                            ])
+                        })?;
+                }
+
+                let body = self.expect_block()?;
+                let location = Location(fn_token.line, fn_token.column);
+                self.check_constant(name.value, location);
+                Ok(ParseNode {
+                    ntype: NodeType::Function(name.value.to_string(), params, Box::new(body)),
+                    location,
+                })
+            })
+    }
+
+    fn parse_loop_control(&mut self) -> OptParseResult {
+        [Keyword::Break, Keyword::Continue]
+            .iter()
+            .find_map(|&keyword| self.check_current(TokenType::Keyword(keyword), true))
+            .map(|token| {
+                let ntype = match token.ttype {
+                    TokenType::Keyword(Keyword::Break) => NodeType::Break,
+                    _ => NodeType::Continue,
+                };
+                Ok(ParseNode {
+                    ntype,
+                    location: Location(token.line, token.column),
+                })
+            })
+    }
+
+    fn parse_expr(&mut self) -> ParseResult {
+        if let Some(token) = self.check_reserved_word_assignment() {
+            return Err(ParsingError::ReservedWordAssignment(
+                token.value.to_string(),
+                Location(token.line, token.column),
+            ));
+        }
+
+        if let Some(result) = self
+            .parse_declaration()
+            .or_else(|| self.parse_while())
+            .or_else(|| self.parse_for())
+            .or_else(|| self.parse_function())
+            .or_else(|| self.parse_loop_control())
+        {
+            return result;
+        }
+
+        self.parse_identifier(false)
+            .and_then(Result::ok)
+            .and_then(|id_node| {
+                self.check_assignment_op()
+                    .map(|assign_token| (id_node, assign_token))
+            })
+            .map(|(id_node, assign_token)| {
+                self.move_forward(2);
+                let right_expr = self.parse_right_expr()?;
+                let node = Self::token_to_assignment_node(assign_token, id_node, right_expr);
+                if let NodeType::Assignment(name, _) = &node.ntype {
+                    self.check_constant(name, node.location);
+                }
+                Ok(node)
+            })
+            .unwrap_or_else(|| self.parse_right_expr())
+    }
+
+    /// Parses 'x in [9.8, 10.2]', read as the assignment of the interval between the bounds.
+    /// It is an operand valued as the interval, as in '(x in [1, 2]) * 2'.
+    fn parse_interval_assignment(&mut self) -> OptParseResult {
+        let identifier = self.check_current(TokenType::Identifier, false)?;
+        let in_token = self.check_ahead(TokenType::Keyword(Keyword::In), 1)?;
+        let open_bracket = self.check_ahead(TokenType::LeftBracket, 2)?;
+        self.move_forward(3);
+
+        self.brackets.push(TokenType::RightBracket);
+        let bounds = self.parse_bounds();
+        self.brackets.pop();
+        Some(bounds.map(|(lo, hi)| {
+            let name = identifier.value.to_string();
+            let location = Location(in_token.line, in_token.column);
+            self.check_constant(&name, location);
+            let bounds = ParseNode {
+                ntype: NodeType::Bounds(Box::new(lo), Box::new(hi)),
+                location: Location(open_bracket.line, open_bracket.column),
+            };
+            ParseNode {
+                ntype: NodeType::Assignment(name, Box::new(bounds)),
+                location,
+            }
+        }))
+    }
+
+    fn parse_bounds(&mut self) -> Result<(ParseNode, ParseNode), ParsingError> {
+        let lo = self.parse_right_expr()?;
+        self.expect_token(TokenType::Comma)?;
+        let hi = self.parse_right_expr()?;
+        self.expect_token(TokenType::RightBracket)?;
+        Ok((lo, hi))
+    }
+
+    fn create_unexpected_error(&self, expected: Vec<Expected>) -> ParsingError {
+        match self.current() {
+            Some(Token {
+                ttype,
+                value,
+                line,
+                column,
+            }) => ParsingError::UnexpectedToken {
+                expected,
+                found: *ttype,
+                lexeme: (*value).to_string(),
+                location: Location(*line, *column),
+            },
+            None => ParsingError::UnexpectedEndOfLine {
+                expected,
+                location: self.end_of_line(),
+            },
+        }
+    }
+
+    /// The location just past the last token of the statement.
+    fn end_of_line(&self) -> Location {
+        let last_token = self.last_token();
+        Location(
+            last_token.line,
+            last_token.column + last_token.value.chars().count(),
+        )
+    }
+
+    /// Whether the end of the statement was already reported, which then
+    /// doesn't need to be reported again for each bracket left open.
+    fn reported_end_of_line(&self) -> bool {
+        self.current().is_none()
+            && matches!(
+                self.errors.last(),
+                Some(ParsingError::UnexpectedEndOfLine { location, .. })
+                    if *location == self.end_of_line()
+            )
+    }
+}
+
+impl ParsingError {
+    pub fn location(&self) -> Option<Location> {
+        use ParsingError::*;
+        match self {
+            UnexpectedToken { location, .. }
+            | UnexpectedEndOfLine { location, .. }
+            | ReservedWordAssignment(_, location)
+            | ConstantAssignment(_, location)
+            | InvalidUnitPower(_, _, location)
+            | DuplicateField(_, location)
+            | ChainedOperator(_, location) => Some(*location),
+            MultipleErrors(_) => None,
+        }
+    }
+}
+
+impl Display for Expected {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Expected::Token(ttype) => write!(f, "{}", ttype),
+            Expected::EndOfLine => write!(f, "end of line"),
+            Expected::Unit => write!(f, "unit"),
+        }
+    }
+}
+
+/// Formats a list of expectations as "a, b or c".
+struct ExpectedList<'e>(&'e [Expected]);
+
+impl Display for ExpectedList<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let count = self.0.len();
+        for (index, expected) in self.0.iter().enumerate() {
+            if index > 0 {
+                write!(f, "{}", if index + 1 == count { " or " } else { ", " })?;
+            }
+            write!(f, "{}", expected)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for ParsingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use ParsingError::*;
+        match self {
+            UnexpectedToken {
+                expected,
+                lexeme,
+                location,
+                ..
+            } => write!(
+                f,
+                "Expected {} but found '{}' at {:?}",
+                ExpectedList(expected),
+                lexeme,
+                location
+            ),
+            UnexpectedEndOfLine { expected, location } => write!(
+                f,
+                "Expected {} but found end of line at {:?}",
+                ExpectedList(expected),
+                location
+            ),
+            ReservedWordAssignment(word, location) => write!(
+                f,
+                "Cannot assign to reserved word '{}' at {:?}",
+                word, location
+            ),
+            ConstantAssignment(name, location) => {
+                write!(f, "Cannot assign to constant '{}' at {:?}", name, location)
+            }
+            InvalidUnitPower(unit, exponent, location) => write!(
+                f,
+                "Cannot raise '{}' to the power {} at {:?}",
+                unit, exponent, location
+            ),
+            DuplicateField(name, location) => {
+                write!(f, "Duplicate field '{}' at {:?}", name, location)
+            }
+            ChainedOperator(operator, location) => write!(
+                f,
+                "Operator '{}' cannot be chained at {:?}",
+                operator, location
+            ),
+            MultipleErrors(errors) => {
+                for error in errors {
+                    writeln!(f, "{}", error)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::approx_constant)]
+mod tests {
+    use super::*;
+    use crate::lexer::{Keyword, Lexer};
+
+    fn starters() -> Vec<Expected> {
+        vec![
+            Expected::Token(TokenType::Number),
+            Expected::Token(TokenType::Identifier),
+            Expected::Token(TokenType::LeftParenthesis),
+            Expected::Token(TokenType::LeftBrace),
+            Expected::Token(TokenType::LeftBracket),
+            Expected::Token(TokenType::Minus),
+        ]
+    }
+
+    /// The tokens that can follow an operand with the standard operators.
+    fn operators() -> Vec<Expected> {
+        use TokenType::*;
+        vec![
+            Dot,
+            LeftBracket,
+            Range,
+            GreaterThan,
+            GreaterThanOrEqual,
+            LessThan,
+            LessThanOrEqual,
+            Equal,
+            NotEqual,
+            Plus,
+            Minus,
+            Times,
+            Div,
+            Power,
+            Keyword(self::Keyword::In),
+        ]
+        .into_iter()
+        .map(Expected::Token)
+        .collect()
+    }
+
+    fn followers(in_parens: bool) -> Vec<Expected> {
+        let closing = if in_parens {
+            Expected::Token(TokenType::RightParenthesis)
+        } else {
+            Expected::EndOfLine
+        };
+        let mut expected = operators();
+        expected.push(closing);
+        expected
+    }
+
+    fn unexpected_token(
+        lexeme: &str,
+        found: TokenType,
+        expected: Vec<Expected>,
+        (line, column): (usize, usize),
+    ) -> ParsingError {
+        ParsingError::UnexpectedToken {
+            expected,
+            found,
+            lexeme: String::from(lexeme),
+            location: Location(line, column),
+        }
+    }
+
+    fn unexpected_eol(expected: Vec<Expected>, (line, column): (usize, usize)) -> ParsingError {
+        ParsingError::UnexpectedEndOfLine {
+            expected,
+            location: Location(line, column),
+        }
+    }
+
+    fn wrap(node: ParseNode) -> ParseNode {
+        ParseNode {
+            ntype: NodeType::Root(vec![node]),
+            location: Location(0, 0),
+        }
+    }
+
+    fn wrap2(node1: ParseNode, node2: ParseNode) -> ParseNode {
+        ParseNode {
+            ntype: NodeType::Root(vec![node1, node2]),
+            location: Location(0, 0),
+        }
+    }
+
+    fn number_node(num: f64, (line, column): (usize, usize)) -> ParseNode {
+        ParseNode {
+            ntype: NodeType::Number(num),
+            location: Location(line, column),
+        }
+    }
+
+    fn identifier_node(value: &str, (line, column): (usize, usize)) -> ParseNode {
+        ParseNode {
+            ntype: NodeType::Identifier(String::from(value)),
+            location: Location(line, column),
+        }
+    }
+
+    fn multiplication_node(
+        left_child: ParseNode,
+        right_child: ParseNode,
+        (line, column): (usize, usize),
+    ) -> ParseNode {
+        let left_child = Box::new(left_child);
+        let right_child = Box::new(right_child);
+        ParseNode {
+            ntype: NodeType::Multiplication(left_child, right_child),
+            location: Location(line, column),
+        }
+    }
+
+    fn division_node(
+        left_child: ParseNode,
+        right_child: ParseNode,
+        (line, column): (usize, usize),
+    ) -> ParseNode {
+        let left_child = Box::new(left_child);
+        let right_child = Box::new(right_child);
+        ParseNode {
+            ntype: NodeType::Division(left_child, right_child),
+            location: Location(line, column),
+        }
+    }
+
+    fn sum_node(
+        left_child: ParseNode,
+        right_child: ParseNode,
+        (line, column): (usize, usize),
+    ) -> ParseNode {
+        let left_child = Box::new(left_child);
+        let right_child = Box::new(right_child);
+        ParseNode {
             ntype: NodeType::Sum(left_child, right_child),
             location: Location(line, column),
         }
@@ -696,6 +1611,39 @@ mod tests {
         }
     }
 
+    fn not_equal_node(
+        left_child: ParseNode,
+        right_child: ParseNode,
+        (line, column): (usize, usize),
+    ) -> ParseNode {
+        let left_child = Box::new(left_child);
+        let right_child = Box::new(right_child);
+        ParseNode {
+            ntype: NodeType::NotEqual(left_child, right_child),
+            location: Location(line, column),
+        }
+    }
+
+    fn power_node(
+        left_child: ParseNode,
+        right_child: ParseNode,
+        (line, column): (usize, usize),
+    ) -> ParseNode {
+        let left_child = Box::new(left_child);
+        let right_child = Box::new(right_child);
+        ParseNode {
+            ntype: NodeType::Power(left_child, right_child),
+            location: Location(line, column),
+        }
+    }
+
+    fn negation_node(child: ParseNode, (line, column): (usize, usize)) -> ParseNode {
+        ParseNode {
+            ntype: NodeType::Negation(Box::new(child)),
+            location: Location(line, column),
+        }
+    }
+
     fn assignment_node(
         identifier: String,
         right_child: ParseNode,
@@ -757,7 +1705,7 @@ mod tests {
         assert_eq!(Ok(number_node(3.14f64, (0, 0))), parser.parse_factor());
         assert_eq!(Ok(identifier_node("hello", (0, 5))), parser.parse_factor());
         assert_eq!(
-            Err(ParsingError::UnexpectedEndOfLine(Location(0, 9))),
+            Err(unexpected_eol(starters(), (0, 10))),
             parser.parse_factor()
         );
         assert_eq!(parser.position, 2);
@@ -769,10 +1717,7 @@ mod tests {
         let mut parser = Parser::new(&tokens);
         assert_eq!(Ok(identifier_node("hello", (0, 0))), parser.parse_factor());
         assert_eq!(
-            Err(ParsingError::UnexpectedToken(
-                String::from("+"),
-                Location(0, 6)
-            )),
+            Err(unexpected_token("+", TokenType::Plus, starters(), (0, 6))),
             parser.parse_factor()
         );
         assert_eq!(parser.position, 1);
@@ -798,28 +1743,44 @@ mod tests {
     fn test_fn_expr_in_unclosed_paren() {
         let tokens = Lexer::get_tokens("(hello").unwrap();
         let mut parser = Parser::new(&tokens);
-        assert_eq!(
-            Err(ParsingError::ExpectedCloseParen(
-                String::from("EOL"),
-                Location(0, 5)
-            )),
-            parser.parse_expr()
-        );
+        assert_eq!(Ok(identifier_node("hello", (0, 1))), parser.parse_expr());
+        assert_eq!(vec![unexpected_eol(followers(true), (0, 6))], parser.errors);
         assert_eq!(parser.position, 2);
     }
 
     #[test]
-    fn test_fn_expr_in_unclosed_paren2() {
-        let tokens = Lexer::get_tokens("(hello j").unwrap();
+    fn test_unclosed_brackets_report_the_end_of_line_once() {
+        let tokens = Lexer::get_tokens("(").unwrap();
         let mut parser = Parser::new(&tokens);
-        assert_eq!(
-            Err(ParsingError::ExpectedCloseParen(
-                String::from("j"),
-                Location(0, 7)
-            )),
-            parser.parse_expr()
+        parser.parse_expr().unwrap();
+        assert_eq!(vec![unexpected_eol(starters(), (0, 1))], parser.errors);
+
+        let tokens = Lexer::get_tokens("f(1,").unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse_expr().unwrap();
+        assert_eq!(vec![unexpected_eol(starters(), (0, 4))], parser.errors);
+
+        let tokens = Lexer::get_tokens("[(1").unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse_expr().unwrap();
+        assert_eq!(vec![unexpected_eol(followers(true), (0, 3))], parser.errors);
+    }
+
+    #[test]
+    fn test_fn_expr_in_unclosed_paren2() {
+        let tokens = Lexer::get_tokens("(hello j").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(Ok(identifier_node("hello", (0, 1))), parser.parse_expr());
+        assert_eq!(
+            vec![unexpected_token(
+                "j",
+                TokenType::Identifier,
+                followers(true),
+                (0, 7)
+            )],
+            parser.errors
         );
-        assert_eq!(parser.position, 2);
+        assert_eq!(parser.position, 3);
     }
 
     #[test]
@@ -1037,6 +1998,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_not_equal() {
+        let tokens = Lexer::get_tokens("3.14 ≠ hello").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Ok(wrap(not_equal_node(
+                number_node(3.14f64, (0, 0)),
+                identifier_node("hello", (0, 7)),
+                (0, 5)
+            ))),
+            parser.parse()
+        );
+    }
+
     #[test]
     fn test_parse_less_than_sum() {
         let tokens = Lexer::get_tokens("3.14 < hello + world").unwrap();
@@ -1095,6 +2070,127 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_power_right_associative() {
+        let tokens = Lexer::get_tokens("2 ^ 3 ** 2").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Ok(wrap(power_node(
+                number_node(2.0, (0, 0)),
+                power_node(number_node(3.0, (0, 4)), number_node(2.0, (0, 9)), (0, 6)),
+                (0, 2)
+            ))),
+            parser.parse()
+        );
+    }
+
+    #[test]
+    fn test_parse_negation() {
+        let tokens = Lexer::get_tokens("-x ^ 2 * 3").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Ok(wrap(multiplication_node(
+                negation_node(
+                    power_node(
+                        identifier_node("x", (0, 1)),
+                        number_node(2.0, (0, 5)),
+                        (0, 3)
+                    ),
+                    (0, 0)
+                ),
+                number_node(3.0, (0, 9)),
+                (0, 7)
+            ))),
+            parser.parse()
+        );
+    }
+
+    #[test]
+    fn test_parse_chained_comparison() {
+        let tokens = Lexer::get_tokens("1 < 2 < 3").unwrap();
+        let mut parser = Parser::new(&tokens);
+        let error = parser.parse().unwrap_err();
+        assert_eq!(
+            wrap_err(ParsingError::ChainedOperator(
+                String::from("<"),
+                Location(0, 6)
+            )),
+            error
+        );
+        assert_eq!(
+            "Operator '<' cannot be chained at Location(0, 6)\n",
+            error.to_string()
+        );
+
+        let tokens = Lexer::get_tokens("1 .. 2 .. 3").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Err(wrap_err(ParsingError::ChainedOperator(
+                String::from(".."),
+                Location(0, 7)
+            ))),
+            parser.parse()
+        );
+        // Operators at another precedence still apply.
+        let tokens = Lexer::get_tokens("0 .. 1 < 2").unwrap();
+        assert!(Parser::new(&tokens).parse().is_ok());
+    }
+
+    #[test]
+    fn test_parse_custom_operator() {
+        use std::rc::Rc;
+
+        let mut generator = Lexer::standard_generator();
+        generator.add_regex("%", TokenType::Operator, 0).unwrap();
+        let tokens = Lexer::with_generator("2 * 50%", Rc::new(generator))
+            .all_tokens()
+            .unwrap();
+
+        let mut parser = Parser::new(&tokens);
+        parser.register_operator(
+            Operator::postfix(TokenType::Operator, 60, |child| {
+                let hundred = Box::new(ParseNode {
+                    ntype: NodeType::Number(100.0),
+                    location: child.location,
+                });
+                NodeType::Division(child, hundred)
+            })
+            .with_lexeme("%"),
+        );
+        assert_eq!(
+            Ok(wrap(multiplication_node(
+                number_node(2.0, (0, 0)),
+                division_node(
+                    number_node(50.0, (0, 4)),
+                    number_node(100.0, (0, 4)),
+                    (0, 6)
+                ),
+                (0, 2)
+            ))),
+            parser.parse()
+        );
+    }
+
+    #[test]
+    fn test_parse_replaced_operator() {
+        let tokens = Lexer::get_tokens("2 ^ 3 ^ 2").unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.register_operator(Operator::infix(
+            TokenType::Power,
+            50,
+            Associativity::Left,
+            NodeType::Power,
+        ));
+        assert_eq!(
+            Ok(wrap(power_node(
+                power_node(number_node(2.0, (0, 0)), number_node(3.0, (0, 4)), (0, 2)),
+                number_node(2.0, (0, 8)),
+                (0, 6)
+            ))),
+            parser.parse()
+        );
+    }
+
     #[test]
     fn test_parse_assignment() {
         let tokens = Lexer::get_tokens("pi = 3.14").unwrap();
@@ -1165,9 +2261,11 @@ mod tests {
         let tokens = Lexer::get_tokens("3.14 hello").unwrap();
         let mut parser = Parser::new(&tokens);
         assert_eq!(
-            Err(wrap_err(ParsingError::UnexpectedToken(
-                String::from("hello"),
-                Location(0, 5)
+            Err(wrap_err(unexpected_token(
+                "hello",
+                TokenType::Identifier,
+                followers(false),
+                (0, 5)
             ))),
             parser.parse()
         );
@@ -1179,18 +2277,57 @@ mod tests {
         let tokens = Lexer::get_tokens("hello =").unwrap();
         let mut parser = Parser::new(&tokens);
         assert_eq!(
-            Err(wrap_err(ParsingError::UnexpectedEndOfLine(Location(0, 6)))),
+            Err(wrap_err(unexpected_eol(starters(), (0, 7)))),
             parser.parse()
         );
         assert_eq!(parser.position, 2);
     }
 
+    #[test]
+    fn test_parse_invalid_unicode_assignment() {
+        let tokens = Lexer::get_tokens("größe = Δt ×").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Err(wrap_err(unexpected_eol(starters(), (0, 12)))),
+            parser.parse()
+        );
+    }
+
+    #[test]
+    fn test_parse_reserved_word_assignment() {
+        let tokens = Lexer::get_tokens("x = 2\nlet = 3\nx + 1").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Err(wrap_err(ParsingError::ReservedWordAssignment(
+                String::from("let"),
+                Location(1, 0)
+            ))),
+            parser.parse()
+        );
+        assert_eq!(parser.position, tokens.len());
+    }
+
+    #[test]
+    fn test_parse_keyword_in_expression() {
+        let tokens = Lexer::get_tokens("2 * while").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Err(wrap_err(unexpected_token(
+                "while",
+                TokenType::Keyword(Keyword::While),
+                starters(),
+                (0, 4)
+            ))),
+            parser.parse()
+        );
+    }
+
     #[test]
     fn test_parse_invalid_multiple_lines() {
         let tokens = Lexer::get_tokens("hello =\n2").unwrap();
         let mut parser = Parser::new(&tokens);
         assert_eq!(
-            Err(wrap_err(ParsingError::UnexpectedEndOfLine(Location(0, 6)))),
+            Err(wrap_err(unexpected_eol(starters(), (0, 7)))),
             parser.parse()
         );
         assert_eq!(parser.position, 3);
@@ -1201,7 +2338,7 @@ mod tests {
         let tokens = Lexer::get_tokens("2\nhello =").unwrap();
         let mut parser = Parser::new(&tokens);
         assert_eq!(
-            Err(wrap_err(ParsingError::UnexpectedEndOfLine(Location(1, 6)))),
+            Err(wrap_err(unexpected_eol(starters(), (1, 7)))),
             parser.parse()
         );
         assert_eq!(parser.position, 3);
@@ -1213,11 +2350,804 @@ mod tests {
         let mut parser = Parser::new(&tokens);
         assert_eq!(
             Err(wrap_err2(
-                ParsingError::UnexpectedEndOfLine(Location(0, 6)),
-                ParsingError::UnexpectedToken(String::from("="), Location(1, 0))
+                unexpected_eol(starters(), (0, 7)),
+                unexpected_token("=", TokenType::Assign, starters(), (1, 0))
             )),
             parser.parse()
         );
         assert_eq!(parser.position, 3);
     }
+
+    fn error_node((line, column): (usize, usize)) -> ParseNode {
+        ParseNode {
+            ntype: NodeType::Error,
+            location: Location(line, column),
+        }
+    }
+
+    #[test]
+    fn test_parse_partial_recovers_in_parens() {
+        let tokens = Lexer::get_tokens("(1 * ) + (2 / ) - 3").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            (
+                wrap(substraction_node(
+                    sum_node(
+                        multiplication_node(number_node(1.0, (0, 1)), error_node((0, 5)), (0, 3)),
+                        division_node(number_node(2.0, (0, 10)), error_node((0, 14)), (0, 12)),
+                        (0, 7)
+                    ),
+                    number_node(3.0, (0, 18)),
+                    (0, 16)
+                )),
+                vec![
+                    unexpected_token(")", TokenType::RightParenthesis, starters(), (0, 5)),
+                    unexpected_token(")", TokenType::RightParenthesis, starters(), (0, 14)),
+                ]
+            ),
+            parser.parse_partial()
+        );
+    }
+
+    #[test]
+    fn test_parse_partial_skips_to_close_paren() {
+        let tokens = Lexer::get_tokens("(1 2 (3) 4) + *").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            (
+                wrap(sum_node(
+                    number_node(1.0, (0, 1)),
+                    error_node((0, 14)),
+                    (0, 12)
+                )),
+                vec![
+                    unexpected_token("2", TokenType::Number, followers(true), (0, 3)),
+                    unexpected_token("*", TokenType::Times, starters(), (0, 14)),
+                ]
+            ),
+            parser.parse_partial()
+        );
+    }
+
+    #[test]
+    fn test_parse_partial_keeps_the_valid_lines() {
+        let tokens = Lexer::get_tokens(
+            "x = 1 +
+)
+x * 2",
+        )
+        .unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            (
+                ParseNode {
+                    ntype: NodeType::Root(vec![
+                        assignment_node(
+                            String::from("x"),
+                            sum_node(number_node(1.0, (0, 4)), error_node((0, 7)), (0, 6)),
+                            (0, 2)
+                        ),
+                        error_node((1, 0)),
+                        multiplication_node(
+                            identifier_node("x", (2, 0)),
+                            number_node(2.0, (2, 4)),
+                            (2, 2)
+                        ),
+                    ]),
+                    location: Location(0, 0),
+                },
+                vec![
+                    unexpected_eol(starters(), (0, 7)),
+                    unexpected_token(")", TokenType::RightParenthesis, starters(), (1, 0)),
+                ]
+            ),
+            parser.parse_partial()
+        );
+    }
+
+    #[test]
+    fn test_parse_blank_lines() {
+        let tokens = Lexer::get_tokens("1\n\n2").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Ok(wrap2(number_node(1.0, (0, 0)), number_node(2.0, (2, 0)))),
+            parser.parse()
+        );
+    }
+
+    #[test]
+    fn test_error_message_lists_expected_tokens() {
+        let tokens = Lexer::get_tokens("2 + * 3").unwrap();
+        let (_, errors) = Parser::new(&tokens).parse_partial();
+        assert_eq!(
+            "Expected number, identifier, '(', '{', '[' or '-' but found '*' at Location(0, 4)",
+            errors[0].to_string()
+        );
+    }
+
+    #[test]
+    fn test_error_message_after_identifier() {
+        let tokens = Lexer::get_tokens("x 3").unwrap();
+        let (_, errors) = Parser::new(&tokens).parse_partial();
+        assert_eq!(
+            vec![unexpected_token(
+                "3",
+                TokenType::Number,
+                [
+                    operators(),
+                    vec![Expected::EndOfLine, Expected::Token(TokenType::Assign)]
+                ]
+                .concat(),
+                (0, 2)
+            )],
+            errors
+        );
+        assert_eq!(
+            "Expected '.', '[', '..', '>', '>=', '<', '<=', '==', '!=', '+', '-', '*', '/', '^', \
+             'in', end of line or '=' but found '3' at Location(0, 2)",
+            errors[0].to_string()
+        );
+    }
+
+    #[test]
+    fn test_expected_custom_prefix_operator() {
+        let tokens = Lexer::get_tokens("(2 *)").unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.register_operator(Operator::prefix(TokenType::Plus, 40, |child| child.ntype));
+        let (_, errors) = parser.parse_partial();
+        assert_eq!(
+            "Expected number, identifier, '(', '{', '[', '-' or '+' but found ')' at Location(0, 4)",
+            errors[0].to_string()
+        );
+    }
+
+    #[test]
+    fn test_parse_semicolons() {
+        let tokens = Lexer::get_tokens("a = 1; b = 2;\n;a").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Ok(ParseNode {
+                ntype: NodeType::Root(vec![
+                    assignment_node(String::from("a"), number_node(1.0, (0, 4)), (0, 2)),
+                    assignment_node(String::from("b"), number_node(2.0, (0, 11)), (0, 9)),
+                    identifier_node("a", (1, 1)),
+                ]),
+                location: Location(0, 0),
+            }),
+            parser.parse()
+        );
+    }
+
+    #[test]
+    fn test_parse_expression_across_lines() {
+        let tokens = Lexer::get_tokens("x = (1 +\n  2) * 3\nx").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Ok(wrap2(
+                assignment_node(
+                    String::from("x"),
+                    multiplication_node(
+                        sum_node(number_node(1.0, (0, 5)), number_node(2.0, (1, 2)), (0, 7)),
+                        number_node(3.0, (1, 7)),
+                        (1, 5)
+                    ),
+                    (0, 2)
+                ),
+                identifier_node("x", (2, 0))
+            )),
+            parser.parse()
+        );
+    }
+
+    #[test]
+    fn test_parse_newline_ends_statement_outside_parens() {
+        let tokens = Lexer::get_tokens("1 +\n2").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Err(wrap_err(unexpected_eol(starters(), (0, 3)))),
+            parser.parse()
+        );
+    }
+
+    #[test]
+    fn test_parse_semicolon_inside_parens() {
+        let tokens = Lexer::get_tokens("(1; 2").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            (
+                wrap2(number_node(1.0, (0, 1)), number_node(2.0, (0, 4))),
+                vec![unexpected_eol(followers(true), (0, 2))]
+            ),
+            parser.parse_partial()
+        );
+    }
+
+    fn let_node(
+        identifier: &str,
+        right_child: ParseNode,
+        (line, column): (usize, usize),
+    ) -> ParseNode {
+        ParseNode {
+            ntype: NodeType::Let(String::from(identifier), Box::new(right_child)),
+            location: Location(line, column),
+        }
+    }
+
+    fn block_node(nodes: Vec<ParseNode>, (line, column): (usize, usize)) -> ParseNode {
+        ParseNode {
+            ntype: NodeType::Block(nodes),
+            location: Location(line, column),
+        }
+    }
+
+    #[test]
+    fn test_parse_block() {
+        let tokens = Lexer::get_tokens("y = {\n  let x = 2; x\n} * 3").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Ok(wrap(assignment_node(
+                String::from("y"),
+                multiplication_node(
+                    block_node(
+                        vec![
+                            let_node("x", number_node(2.0, (1, 10)), (1, 8)),
+                            identifier_node("x", (1, 13))
+                        ],
+                        (0, 4)
+                    ),
+                    number_node(3.0, (2, 4)),
+                    (2, 2)
+                ),
+                (0, 2)
+            ))),
+            parser.parse()
+        );
+    }
+
+    #[test]
+    fn test_parse_block_inside_parens() {
+        let tokens = Lexer::get_tokens("({ 1\n 2 })").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Ok(wrap(block_node(
+                vec![number_node(1.0, (0, 3)), number_node(2.0, (1, 1))],
+                (0, 1)
+            ))),
+            parser.parse()
+        );
+    }
+
+    #[test]
+    fn test_parse_block_errors() {
+        let tokens = Lexer::get_tokens("{ 1 2 }\n{ let 3 }\n{ 4").unwrap();
+        let mut parser = Parser::new(&tokens);
+        let mut followers = followers(false);
+        followers.push(Expected::Token(TokenType::RightBrace));
+        assert_eq!(
+            (
+                ParseNode {
+                    ntype: NodeType::Root(vec![
+                        block_node(vec![number_node(1.0, (0, 2))], (0, 0)),
+                        block_node(vec![error_node((1, 6))], (1, 0)),
+                        block_node(vec![number_node(4.0, (2, 2))], (2, 0)),
+                    ]),
+                    location: Location(0, 0),
+                },
+                vec![
+                    unexpected_token("2", TokenType::Number, followers, (0, 4)),
+                    unexpected_token(
+                        "3",
+                        TokenType::Number,
+                        vec![Expected::Token(TokenType::Identifier)],
+                        (1, 6)
+                    ),
+                    unexpected_eol(vec![Expected::Token(TokenType::RightBrace)], (2, 3)),
+                ]
+            ),
+            parser.parse_partial()
+        );
+    }
+
+    #[test]
+    fn test_parse_stray_brace() {
+        let tokens = Lexer::get_tokens("1 }\n2").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            (
+                wrap2(number_node(1.0, (0, 0)), number_node(2.0, (1, 0))),
+                vec![unexpected_token(
+                    "}",
+                    TokenType::RightBrace,
+                    followers(false),
+                    (0, 2)
+                )]
+            ),
+            parser.parse_partial()
+        );
+    }
+
+    #[test]
+    fn test_parse_while() {
+        let tokens = Lexer::get_tokens("while x < 3 { x = x + 1 }").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Ok(wrap(ParseNode {
+                ntype: NodeType::While(
+                    Box::new(less_than_node(
+                        identifier_node("x", (0, 6)),
+                        number_node(3.0, (0, 10)),
+                        (0, 8)
+                    )),
+                    Box::new(block_node(
+                        vec![assignment_node(
+                            String::from("x"),
+                            sum_node(
+                                identifier_node("x", (0, 18)),
+                                number_node(1.0, (0, 22)),
+                                (0, 20)
+                            ),
+                            (0, 16)
+                        )],
+                        (0, 12)
+                    ))
+                ),
+                location: Location(0, 0),
+            })),
+            parser.parse()
+        );
+    }
+
+    #[test]
+    fn test_parse_for() {
+        let tokens = Lexer::get_tokens("for i in 0..n {\n  break\n  continue\n}").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Ok(wrap(ParseNode {
+                ntype: NodeType::For(
+                    String::from("i"),
+                    Box::new(ParseNode {
+                        ntype: NodeType::Range(
+                            Box::new(number_node(0.0, (0, 9))),
+                            Box::new(identifier_node("n", (0, 12))),
+                        ),
+                        location: Location(0, 10),
+                    }),
+                    Box::new(block_node(
+                        vec![
+                            ParseNode {
+                                ntype: NodeType::Break,
+                                location: Location(1, 2),
+                            },
+                            ParseNode {
+                                ntype: NodeType::Continue,
+                                location: Location(2, 2),
+                            },
+                        ],
+                        (0, 14)
+                    ))
+                ),
+                location: Location(0, 0),
+            })),
+            parser.parse()
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_loops() {
+        let tokens = Lexer::get_tokens("for i 0..3 {}\nwhile 1 2").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Err(wrap_err2(
+                unexpected_token(
+                    "0",
+                    TokenType::Number,
+                    vec![Expected::Token(TokenType::Keyword(Keyword::In))],
+                    (0, 6)
+                ),
+                unexpected_token(
+                    "2",
+                    TokenType::Number,
+                    vec![Expected::Token(TokenType::LeftBrace)],
+                    (1, 8)
+                )
+            )),
+            parser.parse()
+        );
+    }
+
+    fn list_node(nodes: Vec<ParseNode>, (line, column): (usize, usize)) -> ParseNode {
+        ParseNode {
+            ntype: NodeType::List(nodes),
+            location: Location(line, column),
+        }
+    }
+
+    fn call_node(name: &str, args: Vec<ParseNode>, (line, column): (usize, usize)) -> ParseNode {
+        ParseNode {
+            ntype: NodeType::Call(String::from(name), args),
+            location: Location(line, column),
+        }
+    }
+
+    #[test]
+    fn test_parse_list_and_index() {
+        let tokens = Lexer::get_tokens("[1, x][-1]").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Ok(wrap(ParseNode {
+                ntype: NodeType::Index(
+                    Box::new(list_node(
+                        vec![number_node(1.0, (0, 1)), identifier_node("x", (0, 4))],
+                        (0, 0)
+                    )),
+                    Box::new(negation_node(number_node(1.0, (0, 8)), (0, 7))),
+                ),
+                location: Location(0, 6),
+            })),
+            parser.parse()
+        );
+    }
+
+    #[test]
+    fn test_parse_range() {
+        let tokens = Lexer::get_tokens("0..n + 1").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Ok(wrap(ParseNode {
+                ntype: NodeType::Range(
+                    Box::new(number_node(0.0, (0, 0))),
+                    Box::new(sum_node(
+                        identifier_node("n", (0, 3)),
+                        number_node(1.0, (0, 7)),
+                        (0, 5)
+                    )),
+                ),
+                location: Location(0, 1),
+            })),
+            parser.parse()
+        );
+    }
+
+    #[test]
+    fn test_parse_calls() {
+        let tokens = Lexer::get_tokens("max(1, 2 3)\nlen([\n])").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            (
+                wrap2(
+                    call_node(
+                        "max",
+                        vec![number_node(1.0, (0, 4)), number_node(2.0, (0, 7))],
+                        (0, 0)
+                    ),
+                    call_node("len", vec![list_node(vec![], (1, 4))], (1, 0))
+                ),
+                vec![unexpected_token(
+                    "3",
+                    TokenType::Number,
+                    [
+                        operators(),
+                        vec![
+                            Expected::Token(TokenType::Comma),
+                            Expected::Token(TokenType::RightParenthesis)
+                        ]
+                    ]
+                    .concat(),
+                    (0, 9)
+                )]
+            ),
+            parser.parse_partial()
+        );
+    }
+
+    #[test]
+    fn test_parse_function() {
+        let tokens = Lexer::get_tokens("fn add(a, b) { a + b }").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Ok(wrap(ParseNode {
+                ntype: NodeType::Function(
+                    String::from("add"),
+                    vec![String::from("a"), String::from("b")],
+                    Box::new(block_node(
+                        vec![sum_node(
+                            identifier_node("a", (0, 15)),
+                            identifier_node("b", (0, 19)),
+                            (0, 17)
+                        )],
+                        (0, 13)
+                    )),
+                ),
+                location: Location(0, 0),
+            })),
+            parser.parse()
+        );
+    }
+
+    #[test]
+    fn test_parse_record_and_field() {
+        let tokens = Lexer::get_tokens("{\n  qty: 3,\n  price: p\n}.qty").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Ok(wrap(ParseNode {
+                ntype: NodeType::Field(
+                    Box::new(ParseNode {
+                        ntype: NodeType::Record(vec![
+                            (String::from("qty"), number_node(3.0, (1, 7))),
+                            (String::from("price"), identifier_node("p", (2, 9))),
+                        ]),
+                        location: Location(0, 0),
+                    }),
+                    String::from("qty"),
+                ),
+                location: Location(3, 1),
+            })),
+            parser.parse()
+        );
+    }
+
+    #[test]
+    fn test_parse_record_error() {
+        let tokens = Lexer::get_tokens("{ a: 1 2 }\nr.").unwrap();
+        let (_, errors) = Parser::new(&tokens).parse_partial();
+        assert_eq!(
+            vec![
+                unexpected_token(
+                    "2",
+                    TokenType::Number,
+                    [
+                        operators(),
+                        vec![
+                            Expected::Token(TokenType::Comma),
+                            Expected::Token(TokenType::RightBrace)
+                        ]
+                    ]
+                    .concat(),
+                    (0, 7)
+                ),
+                unexpected_eol(vec![Expected::Token(TokenType::Identifier)], (1, 2)),
+            ],
+            errors
+        );
+
+        // The fields without a name or named again are left out.
+        let tokens = Lexer::get_tokens("{ a: 1, : 2, b: 3, a: 4 }").unwrap();
+        let (root, errors) = Parser::new(&tokens).parse_partial();
+        assert_eq!(
+            vec![
+                unexpected_token(
+                    ":",
+                    TokenType::Colon,
+                    vec![Expected::Token(TokenType::Identifier)],
+                    (0, 8)
+                ),
+                ParsingError::DuplicateField(String::from("a"), Location(0, 19)),
+            ],
+            errors
+        );
+        assert_eq!(
+            wrap(ParseNode {
+                ntype: NodeType::Record(vec![
+                    (String::from("a"), number_node(1.0, (0, 5))),
+                    (String::from("b"), number_node(3.0, (0, 16))),
+                ]),
+                location: Location(0, 0),
+            }),
+            root
+        );
+    }
+
+    #[test]
+    fn test_parse_compound_assignment() {
+        let tokens = Lexer::get_tokens("total += x * 2").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Ok(wrap(assignment_node(
+                String::from("total"),
+                sum_node(
+                    identifier_node("total", (0, 0)),
+                    multiplication_node(
+                        identifier_node("x", (0, 9)),
+                        number_node(2.0, (0, 13)),
+                        (0, 11)
+                    ),
+                    (0, 6)
+                ),
+                (0, 6)
+            ))),
+            parser.parse()
+        );
+    }
+
+    #[test]
+    fn test_parse_constant_assignment() {
+        let tokens = Lexer::get_tokens("PI = 3\nconst RATE = 2\nRATE *= 2\nfn E() { 1 }").unwrap();
+        let (root, errors) = Parser::new(&tokens).parse_partial();
+        assert_eq!(
+            vec![
+                ParsingError::ConstantAssignment(String::from("PI"), Location(0, 3)),
+                ParsingError::ConstantAssignment(String::from("RATE"), Location(2, 5)),
+                ParsingError::ConstantAssignment(String::from("E"), Location(3, 0)),
+            ],
+            errors
+        );
+        // The assignments are kept in the tree.
+        assert!(matches!(&root.ntype, NodeType::Root(nodes) if nodes.len() == 4));
+        assert_eq!(
+            "Cannot assign to constant 'PI' at Location(0, 3)",
+            errors[0].to_string()
+        );
+
+        // A constant declared in a block is only bound inside it.
+        let tokens = Lexer::get_tokens("{ const K = 1; K = 2 }\nK = 2").unwrap();
+        let (_, errors) = Parser::new(&tokens).parse_partial();
+        assert_eq!(
+            vec![ParsingError::ConstantAssignment(
+                String::from("K"),
+                Location(0, 17)
+            )],
+            errors
+        );
+    }
+
+    #[test]
+    fn test_parse_const() {
+        let tokens = Lexer::get_tokens("const RATE = 2").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Ok(wrap(ParseNode {
+                ntype: NodeType::Const(String::from("RATE"), Box::new(number_node(2.0, (0, 13)))),
+                location: Location(0, 11),
+            })),
+            parser.parse()
+        );
+    }
+
+    fn quantity_node(num: f64, unit: &str, (line, column): (usize, usize)) -> ParseNode {
+        ParseNode {
+            ntype: NodeType::Quantity(num, Unit::parse(unit).unwrap()),
+            location: Location(line, column),
+        }
+    }
+
+    #[test]
+    fn test_parse_quantity() {
+        // The unit ends at the first space.
+        let tokens = Lexer::get_tokens("9.81 m/s^2 * s").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Ok(wrap(multiplication_node(
+                quantity_node(9.81, "m/s^2", (0, 0)),
+                identifier_node("s", (0, 13)),
+                (0, 11)
+            ))),
+            parser.parse()
+        );
+
+        let tokens = Lexer::get_tokens("3.2 kg*m*s^-2").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Ok(wrap(quantity_node(3.2, "kg*m/s^2", (0, 0)))),
+            parser.parse()
+        );
+
+        // The exponents of the unit, or of its dimension, don't fit.
+        let invalid_power = |unit: &str, exponent, location: (usize, usize)| {
+            Err(wrap_err(ParsingError::InvalidUnitPower(
+                unit.to_string(),
+                exponent,
+                Location(location.0, location.1),
+            )))
+        };
+        for (input, unit, exponent, location) in [
+            ("1 m^99999999999", "m", 99999999999.0, (0, 2)),
+            ("1 J^1500000000", "J", 1.5e9, (0, 2)),
+            ("1 m^2000000000*m^2000000000", "m", 4e9, (0, 14)),
+        ] {
+            let tokens = Lexer::get_tokens(input).unwrap();
+            let mut parser = Parser::new(&tokens);
+            assert_eq!(invalid_power(unit, exponent, location), parser.parse());
+        }
+    }
+
+    #[test]
+    fn test_parse_imaginary() {
+        let imaginary_node = |num, (line, column)| ParseNode {
+            ntype: NodeType::Imaginary(num),
+            location: Location(line, column),
+        };
+        let tokens = Lexer::get_tokens("3 + 2.5j").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Ok(wrap(sum_node(
+                number_node(3.0, (0, 0)),
+                imaginary_node(2.5, (0, 4)),
+                (0, 2)
+            ))),
+            parser.parse()
+        );
+
+        // The imaginary exponent isn't part of the unit.
+        let tokens = Lexer::get_tokens("1 m^2i").unwrap();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(
+            Ok(wrap(power_node(
+                quantity_node(1.0, "m", (0, 0)),
+                imaginary_node(2.0, (0, 4)),
+                (0, 3)
+            ))),
+            parser.parse()
+        );
+    }
+
+    #[test]
+    fn test_parse_conversion() {
+        let tokens = Lexer::get_tokens("x + 1 km in m").unwrap();
+        let mut parser = Parser::new(&tokens);
+        let sum = ParseNode {
+            ntype: NodeType::Sum(
+                Box::new(identifier_node("x", (0, 0))),
+                Box::new(quantity_node(1.0, "km", (0, 4))),
+            ),
+            location: Location(0, 2),
+        };
+        assert_eq!(
+            Ok(wrap(ParseNode {
+                ntype: NodeType::Conversion(Box::new(sum), Unit::parse("m").unwrap()),
+                location: Location(0, 9),
+            })),
+            parser.parse()
+        );
+
+        let tokens = Lexer::get_tokens("x in parsec").unwrap();
+        let (_, errors) = Parser::new(&tokens).parse_partial();
+        assert_eq!(
+            vec![unexpected_token(
+                "parsec",
+                TokenType::Identifier,
+                vec![Expected::Unit],
+                (0, 5)
+            )],
+            errors
+        );
+    }
+
+    #[test]
+    fn test_parse_interval_assignment() {
+        let tokens = Lexer::get_tokens("x in [9.8, 10 + 0.2]").unwrap();
+        let mut parser = Parser::new(&tokens);
+        let bounds = ParseNode {
+            ntype: NodeType::Bounds(
+                Box::new(number_node(9.8, (0, 6))),
+                Box::new(sum_node(
+                    number_node(10.0, (0, 11)),
+                    number_node(0.2, (0, 16)),
+                    (0, 14),
+                )),
+            ),
+            location: Location(0, 5),
+        };
+        assert_eq!(
+            Ok(wrap(ParseNode {
+                ntype: NodeType::Assignment(String::from("x"), Box::new(bounds)),
+                location: Location(0, 2),
+            })),
+            parser.parse()
+        );
+
+        let tokens = Lexer::get_tokens("(x in [1, 2]) * 2").unwrap();
+        assert!(Parser::new(&tokens).parse().is_ok());
+
+        let tokens = Lexer::get_tokens("x in [9.8 10.2]").unwrap();
+        let (_, errors) = Parser::new(&tokens).parse_partial();
+        assert_eq!(
+            vec![unexpected_token(
+                "10.2",
+                TokenType::Number,
+                vec![Expected::Token(TokenType::Comma)],
+                (0, 10)
+            )],
+            errors
+        );
+    }
 }
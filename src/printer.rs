@@ -0,0 +1,275 @@
+use crate::operators::{ADDITIVE, COMPARISON, CONVERSION, MULTIPLICATIVE, NEGATION, POWER, RANGE};
+use crate::parser::{NodeType, ParseNode};
+use std::fmt::Write;
+
+/// Precedence of the statements, looser than any operator.
+const STATEMENT: u8 = 0;
+/// Precedence of indexing and field access, tighter than any operator.
+const POSTFIX: u8 = 60;
+/// Precedence of literals, identifiers, calls and bracketed expressions.
+const ATOM: u8 = u8::MAX;
+
+impl ParseNode {
+    /// Prints the node back as source code, with only the parentheses the standard
+    /// precedences require. The statements of the root go on separate lines,
+    /// the ones of a block are separated by ';'.
+    pub fn to_source(&self) -> String {
+        let mut source = String::new();
+        write_node(&mut source, self);
+        source
+    }
+}
+
+fn precedence(node: &ParseNode) -> u8 {
+    use NodeType::*;
+    match &node.ntype {
+        Number(num) if num.is_sign_negative() && !num.is_nan() => NEGATION,
+        Conversion(..) => CONVERSION,
+        Range(..) => RANGE,
+        GreaterThan(..)
+        | GreaterThanOrEqual(..)
+        | LessThan(..)
+        | LessThanOrEqual(..)
+        | Equal(..)
+        | NotEqual(..) => COMPARISON,
+        Sum(..) | Substraction(..) => ADDITIVE,
+        Multiplication(..) | Division(..) => MULTIPLICATIVE,
+        Negation(..) => NEGATION,
+        Power(..) => POWER,
+        Index(..) | Field(..) => POSTFIX,
+        Assignment(..) | Let(..) | Const(..) | Function(..) | While(..) | For(..) | Break
+        | Continue | Root(..) => STATEMENT,
+        Identifier(_) | Number(_) | Quantity(..) | Imaginary(_) | List(_) | Record(_)
+        | Call(..) | Bounds(..) | Block(_) | Error => ATOM,
+    }
+}
+
+/// The operator of a binary node, with its precedence and whether it
+/// associates to the left, to the right or not at all.
+fn binary(node: &NodeType) -> Option<(&'static str, u8, Option<bool>)> {
+    use NodeType::*;
+    Some(match node {
+        Range(..) => ("..", RANGE, None),
+        GreaterThan(..) => (" > ", COMPARISON, None),
+        GreaterThanOrEqual(..) => (" >= ", COMPARISON, None),
+        LessThan(..) => (" < ", COMPARISON, None),
+        LessThanOrEqual(..) => (" <= ", COMPARISON, None),
+        Equal(..) => (" == ", COMPARISON, None),
+        NotEqual(..) => (" != ", COMPARISON, None),
+        Sum(..) => (" + ", ADDITIVE, Some(true)),
+        Substraction(..) => (" - ", ADDITIVE, Some(true)),
+        Multiplication(..) => (" * ", MULTIPLICATIVE, Some(true)),
+        Division(..) => (" / ", MULTIPLICATIVE, Some(true)),
+        Power(..) => (" ^ ", POWER, Some(false)),
+        _ => return None,
+    })
+}
+
+/// Writes 'node', in parentheses if it binds looser than 'min_precedence'.
+fn write_child(source: &mut String, node: &ParseNode, min_precedence: u8) {
+    if precedence(node) < min_precedence {
+        source.push('(');
+        write_node(source, node);
+        source.push(')');
+    } else {
+        write_node(source, node);
+    }
+}
+
+fn write_list(source: &mut String, nodes: &[ParseNode], separator: &str) {
+    for (index, node) in nodes.iter().enumerate() {
+        if index > 0 {
+            source.push_str(separator);
+        }
+        write_child(source, node, STATEMENT + 1);
+    }
+}
+
+fn write_statements(source: &mut String, nodes: &[ParseNode], separator: &str) {
+    for (index, node) in nodes.iter().enumerate() {
+        if index > 0 {
+            source.push_str(separator);
+        }
+        write_node(source, node);
+    }
+}
+
+fn write_node(source: &mut String, node: &ParseNode) {
+    use NodeType::*;
+    match &node.ntype {
+        Sum(left, right)
+        | Substraction(left, right)
+        | Multiplication(left, right)
+        | Division(left, right)
+        | Power(left, right)
+        | Range(left, right)
+        | GreaterThan(left, right)
+        | GreaterThanOrEqual(left, right)
+        | LessThan(left, right)
+        | LessThanOrEqual(left, right)
+        | Equal(left, right)
+        | NotEqual(left, right) => {
+            let (operator, precedence, left_associative) = binary(&node.ntype).unwrap();
+            let tighter = precedence + 1;
+            let (left_min, right_min) = match left_associative {
+                Some(true) => (precedence, tighter),
+                Some(false) => (tighter, precedence),
+                None => (tighter, tighter),
+            };
+            write_child(source, left, left_min);
+            source.push_str(operator);
+            write_child(source, right, right_min);
+        }
+        Identifier(identifier) => source.push_str(identifier),
+        Number(num) if num.is_nan() => source.push_str("NAN"),
+        Number(num) if num.is_infinite() => {
+            source.push_str(if *num > 0.0 { "INF" } else { "-INF" })
+        }
+        Number(num) => {
+            let _ = write!(source, "{}", num);
+        }
+        Quantity(num, unit) => {
+            let _ = write!(source, "{} {}", num, unit);
+        }
+        Imaginary(num) => {
+            let _ = write!(source, "{}i", num);
+        }
+        Conversion(child, unit) => {
+            write_child(source, child, CONVERSION);
+            let _ = write!(source, " in {}", unit);
+        }
+        Negation(child) => {
+            source.push('-');
+            write_child(source, child, NEGATION + 1);
+        }
+        List(nodes) => {
+            source.push('[');
+            write_list(source, nodes, ", ");
+            source.push(']');
+        }
+        Record(fields) => {
+            source.push_str("{ ");
+            for (index, (name, node)) in fields.iter().enumerate() {
+                if index > 0 {
+                    source.push_str(", ");
+                }
+                let _ = write!(source, "{}: ", name);
+                write_child(source, node, STATEMENT + 1);
+            }
+            source.push_str(" }");
+        }
+        Index(list, index) => {
+            write_child(source, list, POSTFIX);
+            source.push('[');
+            write_node(source, index);
+            source.push(']');
+        }
+        Field(record, name) => {
+            write_child(source, record, POSTFIX);
+            let _ = write!(source, ".{}", name);
+        }
+        Call(name, args) => {
+            let _ = write!(source, "{}(", name);
+            write_list(source, args, ", ");
+            source.push(')');
+        }
+        Function(name, params, body) => {
+            let _ = write!(source, "fn {}({}) ", name, params.join(", "));
+            write_node(source, body);
+        }
+        Assignment(identifier, value) if matches!(value.ntype, Bounds(..)) => {
+            let _ = write!(source, "{} in ", identifier);
+            write_node(source, value);
+        }
+        Assignment(identifier, value) => {
+            let _ = write!(source, "{} = ", identifier);
+            write_node(source, value);
+        }
+        Bounds(lo, hi) => {
+            source.push('[');
+            write_child(source, lo, STATEMENT + 1);
+            source.push_str(", ");
+            write_child(source, hi, STATEMENT + 1);
+            source.push(']');
+        }
+        Let(identifier, value) => {
+            let _ = write!(source, "let {} = ", identifier);
+            write_node(source, value);
+        }
+        Const(identifier, value) => {
+            let _ = write!(source, "const {} = ", identifier);
+            write_node(source, value);
+        }
+        Block(nodes) if nodes.is_empty() => source.push_str("{}"),
+        Block(nodes) => {
+            source.push_str("{ ");
+            write_statements(source, nodes, "; ");
+            source.push_str(" }");
+        }
+        While(condition, body) => {
+            source.push_str("while ");
+            write_child(source, condition, STATEMENT + 1);
+            source.push(' ');
+            write_node(source, body);
+        }
+        For(variable, iterable, body) => {
+            let _ = write!(source, "for {} in ", variable);
+            write_child(source, iterable, STATEMENT + 1);
+            source.push(' ');
+            write_node(source, body);
+        }
+        Break => source.push_str("break"),
+        Continue => source.push_str("continue"),
+        Root(nodes) => write_statements(source, nodes, "\n"),
+        Error => source.push_str("<error>"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn assert_source(input: &str, expected: &str) {
+        let tokens = Lexer::get_tokens(input).unwrap();
+        let root = Parser::new(&tokens).parse().unwrap();
+        let source = root.to_source();
+        assert_eq!(expected, source, "{}", input);
+
+        // The printed source parses back to the same source.
+        let tokens = Lexer::get_tokens(&source).unwrap();
+        let reparsed = Parser::new(&tokens).parse().unwrap();
+        assert_eq!(source, reparsed.to_source(), "{}", input);
+    }
+
+    #[test]
+    fn test_precedences() {
+        assert_source("1+2*3", "1 + 2 * 3");
+        assert_source("(1 + 2) * 3", "(1 + 2) * 3");
+        assert_source("1 - (2 - 3) - 4", "1 - (2 - 3) - 4");
+        assert_source("(2 ^ 3) ^ 2 + 2 ^ (3 ^ 2)", "(2 ^ 3) ^ 2 + 2 ^ 3 ^ 2");
+        assert_source("-2 ** 2 + (-2) ^ 2", "-2 ^ 2 + (-2) ^ 2");
+        assert_source("-(-x)", "-(-x)");
+        assert_source("(a < b) == (1 .. 2)", "(a < b) == (1..2)");
+        assert_source("(x + 1)[0] + r.a.b", "(x + 1)[0] + r.a.b");
+        assert_source("(72 km*m/h/m in m/s) > 1 m/s", "(72 km/h in m/s) > 1 m/s");
+    }
+
+    #[test]
+    fn test_statements() {
+        assert_source(
+            "let y = 1\nconst k = 2; fn f(a, b) { a * b }\nf(y, k)",
+            "let y = 1\nconst k = 2\nfn f(a, b) { a * b }\nf(y, k)",
+        );
+        assert_source(
+            "for i in 0..3 { if_ = i\n break }\nwhile 0 {}",
+            "for i in 0..3 { if_ = i; break }\nwhile 0 {}",
+        );
+        assert_source(
+            "xs = [1, { a: 2 }]; xs += 1",
+            "xs = [1, { a: 2 }]\nxs = xs + 1",
+        );
+        assert_source("x in [ -0.2, 1/5 ]\nx", "x in [-0.2, 1 / 5]\nx");
+        assert_source("(x in [1, 2])*2", "(x in [1, 2]) * 2");
+    }
+}
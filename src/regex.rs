@@ -0,0 +1,472 @@
+use crate::fsm::FSM;
+use std::collections::{BTreeSet, HashMap};
+
+/// Largest scalar value a 'char' can hold.
+const MAX_CHAR: u32 = 0x10_FFFF;
+
+#[derive(Debug, PartialEq, Fail)]
+pub enum RegexError {
+    #[fail(display = "Unexpected end of pattern '{}'.", _0)]
+    UnexpectedEnd(String),
+    #[fail(display = "Unexpected character {} at {} in pattern '{}'.", _1, _2, _0)]
+    UnexpectedCharacter(String, char, usize),
+    #[fail(display = "Invalid range {}-{} in pattern '{}'.", _1, _2, _0)]
+    InvalidRange(String, char, char),
+}
+
+/// Abstract syntax of a regular expression.
+/// Character classes are kept as sorted, non overlapping ranges of scalar values.
+#[derive(Clone, PartialEq, Debug)]
+enum Regex {
+    Class(Vec<(u32, u32)>),
+    Concat(Vec<Regex>),
+    Alternation(Vec<Regex>),
+    ZeroOrMore(Box<Regex>),
+    OneOrMore(Box<Regex>),
+    Optional(Box<Regex>),
+}
+
+/// States of the Thompson NFA built from a 'Regex'.
+enum NfaState {
+    Class(Vec<(u32, u32)>, usize),
+    Split(Vec<usize>),
+    Match,
+}
+
+/// Compiles 'pattern' into a deterministic 'FSM'.
+///
+/// The supported syntax is literals, '.', classes ('[a-z_]', '[^0-9]'),
+/// the escapes '\d', '\w', '\s' (and their negations), groups, '|', '*', '+' and '?'.
+pub fn compile(pattern: &str) -> Result<FSM<usize>, RegexError> {
+    let regex = RegexParser::new(pattern).parse()?;
+    let mut nfa = vec![NfaState::Match];
+    let start = build_nfa(&regex, 0, &mut nfa);
+    Ok(build_dfa(&nfa, start))
+}
+
+/// Escapes the special characters of 'literal' so it only matches itself.
+pub fn escape(literal: &str) -> String {
+    literal
+        .chars()
+        .flat_map(|character| {
+            let special = "\\.[]()|*+?^-".contains(character);
+            let escape = if special { Some('\\') } else { None };
+            escape.into_iter().chain(std::iter::once(character))
+        })
+        .collect()
+}
+
+struct RegexParser<'a> {
+    pattern: &'a str,
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+}
+
+impl<'a> RegexParser<'a> {
+    fn new(pattern: &'a str) -> Self {
+        RegexParser {
+            pattern,
+            chars: pattern.char_indices().peekable(),
+        }
+    }
+
+    fn parse(mut self) -> Result<Regex, RegexError> {
+        let regex = self.parse_alternation()?;
+        match self.chars.next() {
+            Some((index, character)) => Err(self.unexpected(character, index)),
+            None => Ok(regex),
+        }
+    }
+
+    fn parse_alternation(&mut self) -> Result<Regex, RegexError> {
+        let mut branches = vec![self.parse_concat()?];
+        while let Some((_, '|')) = self.chars.peek() {
+            self.chars.next();
+            branches.push(self.parse_concat()?);
+        }
+
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            Regex::Alternation(branches)
+        })
+    }
+
+    fn parse_concat(&mut self) -> Result<Regex, RegexError> {
+        let mut items = vec![];
+        while let Some(&(_, character)) = self.chars.peek() {
+            if character == '|' || character == ')' {
+                break;
+            }
+
+            items.push(self.parse_repetition()?);
+        }
+
+        Ok(Regex::Concat(items))
+    }
+
+    fn parse_repetition(&mut self) -> Result<Regex, RegexError> {
+        let mut regex = self.parse_atom()?;
+        while let Some(&(_, character)) = self.chars.peek() {
+            regex = match character {
+                '*' => Regex::ZeroOrMore(Box::new(regex)),
+                '+' => Regex::OneOrMore(Box::new(regex)),
+                '?' => Regex::Optional(Box::new(regex)),
+                _ => break,
+            };
+            self.chars.next();
+        }
+
+        Ok(regex)
+    }
+
+    fn parse_atom(&mut self) -> Result<Regex, RegexError> {
+        match self.next()? {
+            (_, '(') => {
+                let regex = self.parse_alternation()?;
+                match self.next()? {
+                    (_, ')') => Ok(regex),
+                    (index, character) => Err(self.unexpected(character, index)),
+                }
+            }
+            (_, '[') => self.parse_class(),
+            (_, '.') => Ok(Regex::Class(negate(&[('\n' as u32, '\n' as u32)]))),
+            (_, '\\') => self.parse_escape().map(Regex::Class),
+            (index, character @ '*') | (index, character @ '+') | (index, character @ '?') => {
+                Err(self.unexpected(character, index))
+            }
+            (_, character) => Ok(Regex::Class(single(character))),
+        }
+    }
+
+    fn parse_class(&mut self) -> Result<Regex, RegexError> {
+        let negated = if let Some((_, '^')) = self.chars.peek() {
+            self.chars.next();
+            true
+        } else {
+            false
+        };
+
+        let mut ranges = vec![];
+        loop {
+            let start = match self.next()? {
+                (_, ']') => break,
+                (_, '\\') => {
+                    ranges.extend(self.parse_escape()?);
+                    continue;
+                }
+                (_, character) => character,
+            };
+
+            let is_range = match self.chars.peek() {
+                Some(&(_, '-')) => {
+                    let mut lookahead = self.chars.clone();
+                    lookahead.next();
+                    lookahead.peek().is_some_and(|&(_, next)| next != ']')
+                }
+                _ => false,
+            };
+
+            if is_range {
+                self.chars.next();
+                let (_, end) = self.next()?;
+                if end < start {
+                    return Err(RegexError::InvalidRange(self.pattern.into(), start, end));
+                }
+
+                ranges.push((start as u32, end as u32));
+            } else {
+                ranges.push((start as u32, start as u32));
+            }
+        }
+
+        let ranges = normalize(ranges);
+        Ok(Regex::Class(if negated { negate(&ranges) } else { ranges }))
+    }
+
+    fn parse_escape(&mut self) -> Result<Vec<(u32, u32)>, RegexError> {
+        let digits = || vec![('0' as u32, '9' as u32)];
+        let word = || {
+            normalize(vec![
+                ('0' as u32, '9' as u32),
+                ('A' as u32, 'Z' as u32),
+                ('_' as u32, '_' as u32),
+                ('a' as u32, 'z' as u32),
+            ])
+        };
+        let spaces = || {
+            normalize(
+                [' ', '\t', '\n', '\r', '\x0C']
+                    .iter()
+                    .map(|&c| (c as u32, c as u32))
+                    .collect(),
+            )
+        };
+
+        Ok(match self.next()? {
+            (_, 'd') => digits(),
+            (_, 'D') => negate(&digits()),
+            (_, 'w') => word(),
+            (_, 'W') => negate(&word()),
+            (_, 's') => spaces(),
+            (_, 'S') => negate(&spaces()),
+            (_, 'n') => single('\n'),
+            (_, 't') => single('\t'),
+            (_, 'r') => single('\r'),
+            (_, character) => single(character),
+        })
+    }
+
+    fn next(&mut self) -> Result<(usize, char), RegexError> {
+        self.chars
+            .next()
+            .ok_or_else(|| RegexError::UnexpectedEnd(self.pattern.into()))
+    }
+
+    fn unexpected(&self, character: char, index: usize) -> RegexError {
+        RegexError::UnexpectedCharacter(self.pattern.into(), character, index)
+    }
+}
+
+fn single(character: char) -> Vec<(u32, u32)> {
+    vec![(character as u32, character as u32)]
+}
+
+/// Sorts 'ranges' and merges the ones that overlap or touch.
+fn normalize(mut ranges: Vec<(u32, u32)>) -> Vec<(u32, u32)> {
+    ranges.sort_unstable();
+    let mut merged: Vec<(u32, u32)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    merged
+}
+
+/// Complement of normalized 'ranges' over all scalar values.
+fn negate(ranges: &[(u32, u32)]) -> Vec<(u32, u32)> {
+    let mut negated = vec![];
+    let mut next = 0;
+    for &(start, end) in ranges {
+        if start > next {
+            negated.push((next, start - 1));
+        }
+
+        next = end + 1;
+    }
+
+    if next <= MAX_CHAR {
+        negated.push((next, MAX_CHAR));
+    }
+
+    negated
+}
+
+/// Adds the states recognizing 'regex' to 'nfa', continuing to 'next'
+/// once it matches. Returns the entry state.
+fn build_nfa(regex: &Regex, next: usize, nfa: &mut Vec<NfaState>) -> usize {
+    fn push(nfa: &mut Vec<NfaState>, state: NfaState) -> usize {
+        nfa.push(state);
+        nfa.len() - 1
+    }
+
+    match regex {
+        Regex::Class(ranges) => push(nfa, NfaState::Class(ranges.clone(), next)),
+        Regex::Concat(items) => items
+            .iter()
+            .rev()
+            .fold(next, |next, item| build_nfa(item, next, nfa)),
+        Regex::Alternation(branches) => {
+            let starts = branches
+                .iter()
+                .map(|branch| build_nfa(branch, next, nfa))
+                .collect();
+            push(nfa, NfaState::Split(starts))
+        }
+        Regex::ZeroOrMore(inner) => {
+            let split = push(nfa, NfaState::Split(vec![]));
+            let start = build_nfa(inner, split, nfa);
+            nfa[split] = NfaState::Split(vec![start, next]);
+            split
+        }
+        Regex::OneOrMore(inner) => {
+            let split = push(nfa, NfaState::Split(vec![]));
+            let start = build_nfa(inner, split, nfa);
+            nfa[split] = NfaState::Split(vec![start, next]);
+            start
+        }
+        Regex::Optional(inner) => {
+            let start = build_nfa(inner, next, nfa);
+            push(nfa, NfaState::Split(vec![start, next]))
+        }
+    }
+}
+
+fn epsilon_closure(nfa: &[NfaState], states: impl IntoIterator<Item = usize>) -> BTreeSet<usize> {
+    let mut closure = BTreeSet::new();
+    let mut pending: Vec<usize> = states.into_iter().collect();
+    while let Some(state) = pending.pop() {
+        if closure.insert(state) {
+            if let NfaState::Split(targets) = &nfa[state] {
+                pending.extend(targets);
+            }
+        }
+    }
+
+    closure
+}
+
+/// Subset construction. The alphabet is split into the intervals delimited
+/// by the bounds of every class in the NFA, so each interval behaves as a
+/// single symbol.
+fn build_dfa(nfa: &[NfaState], start: usize) -> FSM<usize> {
+    let mut bounds = BTreeSet::new();
+    for state in nfa {
+        if let NfaState::Class(ranges, _) = state {
+            for &(start, end) in ranges {
+                bounds.insert(start);
+                bounds.insert(end + 1);
+            }
+        }
+    }
+
+    let bounds: Vec<u32> = bounds.into_iter().collect();
+    let intervals: Vec<(u32, u32)> = bounds.windows(2).map(|w| (w[0], w[1] - 1)).collect();
+
+    let mut ids: HashMap<BTreeSet<usize>, usize> = HashMap::new();
+    let mut subsets = vec![epsilon_closure(nfa, vec![start])];
+    let mut transitions: Vec<Vec<Option<usize>>> = vec![];
+    ids.insert(subsets[0].clone(), 0);
+
+    while transitions.len() < subsets.len() {
+        let subset = subsets[transitions.len()].clone();
+        let row = intervals
+            .iter()
+            .map(|&(symbol, _)| {
+                let targets = subset.iter().filter_map(|&state| match &nfa[state] {
+                    NfaState::Class(ranges, next)
+                        if ranges.iter().any(|&(s, e)| s <= symbol && symbol <= e) =>
+                    {
+                        Some(*next)
+                    }
+                    _ => None,
+                });
+                let target = epsilon_closure(nfa, targets);
+                if target.is_empty() {
+                    return None;
+                }
+
+                Some(*ids.entry(target.clone()).or_insert_with(|| {
+                    subsets.push(target);
+                    subsets.len() - 1
+                }))
+            })
+            .collect();
+        transitions.push(row);
+    }
+
+    let accepting_states = subsets
+        .iter()
+        .enumerate()
+        .filter(|(_, subset)| subset.iter().any(|&s| matches!(nfa[s], NfaState::Match)))
+        .map(|(id, _)| id)
+        .collect();
+
+    FSM {
+        states: (0..subsets.len()).collect(),
+        initial_state: 0,
+        accepting_states,
+        next_state: Box::new(move |current_state, character| {
+            let code = character as u32;
+            let symbol = intervals
+                .binary_search_by(|&(start, end)| {
+                    if end < code {
+                        std::cmp::Ordering::Less
+                    } else if start > code {
+                        std::cmp::Ordering::Greater
+                    } else {
+                        std::cmp::Ordering::Equal
+                    }
+                })
+                .ok()?;
+
+            transitions[current_state][symbol]
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, input: &str) -> Option<String> {
+        compile(pattern).unwrap().run(input).map(String::from)
+    }
+
+    #[test]
+    fn test_literal() {
+        assert_eq!(Some("abc".into()), matches("abc", "abc"));
+        assert_eq!(None, matches("abc", "abd"));
+    }
+
+    #[test]
+    fn test_alternation() {
+        assert_eq!(Some("<=".into()), matches("<|<=", "<="));
+        assert_eq!(Some("<".into()), matches("<|<=", "<"));
+        assert_eq!(None, matches("a|b", "c"));
+    }
+
+    #[test]
+    fn test_repetition() {
+        assert_eq!(Some("aaab".into()), matches("a*b", "aaab"));
+        assert_eq!(Some("b".into()), matches("a*b", "b"));
+        assert_eq!(None, matches("a+b", "b"));
+        assert_eq!(Some("ab".into()), matches("a?b", "ab"));
+        assert_eq!(Some("abab".into()), matches("(ab)+", "abab"));
+    }
+
+    #[test]
+    fn test_classes() {
+        let identifier = "[a-zA-Z_][a-zA-Z0-9_]*";
+        assert_eq!(
+            Some("snake_case1".into()),
+            matches(identifier, "snake_case1 + 2")
+        );
+        assert_eq!(None, matches(identifier, "1abc"));
+        assert_eq!(Some("x".into()), matches("[^0-9]", "x"));
+        assert_eq!(None, matches("[^0-9]", "7"));
+        assert_eq!(Some("a-".into()), matches("[a-]+", "a-"));
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert_eq!(Some("12.5".into()), matches(r"\d+\.\d+", "12.5"));
+        assert_eq!(None, matches(r"\d+\.\d+", "12x5"));
+        assert_eq!(Some(" \t\n".into()), matches(r"\s+", " \t\nx"));
+        assert_eq!(Some("**".into()), matches(r"\*\*", "**"));
+    }
+
+    #[test]
+    fn test_escape() {
+        let literal = "(a*b)+[c]";
+        assert_eq!(Some(literal.into()), matches(&escape(literal), literal));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Err(RegexError::UnexpectedEnd("(ab".into())),
+            compile("(ab").map(|_| ())
+        );
+        assert_eq!(
+            Err(RegexError::UnexpectedCharacter("a)".into(), ')', 1)),
+            compile("a)").map(|_| ())
+        );
+        assert_eq!(
+            Err(RegexError::InvalidRange("[z-a]".into(), 'z', 'a')),
+            compile("[z-a]").map(|_| ())
+        );
+    }
+}
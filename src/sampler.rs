@@ -0,0 +1,211 @@
+use crate::fsm::FSM;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+/// Attempts made to mutate an accepted string into a rejected one.
+const MAX_MUTATIONS: usize = 256;
+
+/// Xorshift64* generator, deterministic for a given seed.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // Zero is a fixed point of xorshift.
+        XorShift(seed | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Returns a number in '0..bound'.
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+/// Generates random strings by walking the transitions of an 'FSM'.
+pub struct Sampler<'f, T: Eq + Hash> {
+    fsm: &'f FSM<T>,
+
+    /// - alphabet.    The characters tried on every state.
+    alphabet: Vec<char>,
+
+    /// - transitions. The transitions of every
+    ///                state over the alphabet.
+    transitions: HashMap<T, Vec<(char, T)>>,
+
+    /// - distances.   The fewest characters needed to reach an accepting
+    ///                state. Missing for states that can't reach one.
+    distances: HashMap<T, usize>,
+
+    /// - max_len.     The maximum number of characters
+    ///                in the accepted strings.
+    max_len: usize,
+
+    rng: XorShift,
+}
+
+impl<'f, T: Eq + Hash + Copy> Sampler<'f, T> {
+    pub fn new(fsm: &'f FSM<T>, alphabet: &str, max_len: usize, seed: u64) -> Self {
+        let alphabet: Vec<char> = alphabet.chars().collect();
+        let transitions: HashMap<T, Vec<(char, T)>> = fsm
+            .states
+            .iter()
+            .map(|&state| {
+                let targets = alphabet
+                    .iter()
+                    .filter_map(|&character| {
+                        (fsm.next_state)(state, character).map(|next_state| (character, next_state))
+                    })
+                    .collect();
+                (state, targets)
+            })
+            .collect();
+
+        // Breadth first search from the accepting states over the reversed transitions.
+        let mut distances: HashMap<T, usize> = fsm
+            .accepting_states
+            .iter()
+            .map(|&state| (state, 0))
+            .collect();
+        let mut pending: VecDeque<T> = fsm.accepting_states.iter().cloned().collect();
+        while let Some(state) = pending.pop_front() {
+            let distance = distances[&state] + 1;
+            for (&source, targets) in transitions.iter() {
+                let reaches_state = targets.iter().any(|&(_, target)| target == state);
+                if reaches_state && !distances.contains_key(&source) {
+                    distances.insert(source, distance);
+                    pending.push_back(source);
+                }
+            }
+        }
+
+        Sampler {
+            fsm,
+            alphabet,
+            transitions,
+            distances,
+            max_len,
+            rng: XorShift::new(seed),
+        }
+    }
+
+    /// Returns a random string of at most 'max_len' characters that the FSM accepts
+    /// entirely, or 'None' if there is no such string.
+    pub fn accepted(&mut self) -> Option<String> {
+        let mut state = self.fsm.initial_state;
+        if self.distance(state)? > self.max_len {
+            return None;
+        }
+
+        let mut string = String::new();
+        let mut len = 0;
+        loop {
+            let remaining = self.max_len - len;
+            let moves: Vec<(char, T)> = self.transitions[&state]
+                .iter()
+                .filter(|&&(_, next_state)| {
+                    self.distance(next_state)
+                        .is_some_and(|distance| distance < remaining)
+                })
+                .cloned()
+                .collect();
+
+            // The walk only enters states that can still reach an accepting one,
+            // so it can stop whenever there is nowhere else to go.
+            let stops = self.fsm.accepting_states.contains(&state)
+                && (moves.is_empty() || self.rng.below(moves.len() + 1) == 0);
+            if stops {
+                return Some(string);
+            }
+
+            let (character, next_state) = moves[self.rng.below(moves.len())];
+            string.push(character);
+            len += 1;
+            state = next_state;
+        }
+    }
+
+    /// Returns a random non empty string that the FSM doesn't accept entirely,
+    /// built by truncating an accepted string or replacing, inserting or deleting
+    /// one of its characters. Returns 'None' if no such string was found.
+    pub fn rejected(&mut self) -> Option<String> {
+        for _ in 0..MAX_MUTATIONS {
+            let mut characters: Vec<char> = self.accepted()?.chars().collect();
+            let len = characters.len();
+            let character = self.alphabet[self.rng.below(self.alphabet.len())];
+
+            match self.rng.below(4) {
+                0 if len > 1 => characters.truncate(1 + self.rng.below(len - 1)),
+                1 if len > 0 => characters[self.rng.below(len)] = character,
+                2 => characters.insert(self.rng.below(len + 1), character),
+                3 if len > 1 => {
+                    characters.remove(self.rng.below(len));
+                }
+                _ => continue,
+            }
+
+            let string: String = characters.into_iter().collect();
+            if self.fsm.run(&string) != Some(string.as_str()) {
+                return Some(string);
+            }
+        }
+
+        None
+    }
+
+    fn distance(&self, state: T) -> Option<usize> {
+        self.distances.get(&state).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex;
+
+    #[test]
+    fn test_accepted_strings() {
+        let fsm = regex::compile("a(bc)*d?").unwrap();
+        let mut sampler = Sampler::new(&fsm, "abcdx", 6, 7);
+        for _ in 0..100 {
+            let string = sampler.accepted().unwrap();
+            assert!(string.chars().count() <= 6);
+            assert_eq!(Some(string.as_str()), fsm.run(&string));
+        }
+    }
+
+    #[test]
+    fn test_rejected_strings() {
+        let fsm = regex::compile("a(bc)*d?").unwrap();
+        let mut sampler = Sampler::new(&fsm, "abcdx", 6, 7);
+        for _ in 0..100 {
+            let string = sampler.rejected().unwrap();
+            assert!(!string.is_empty());
+            assert_ne!(Some(string.as_str()), fsm.run(&string));
+        }
+    }
+
+    #[test]
+    fn test_same_seed_same_strings() {
+        let fsm = regex::compile("[a-z]+[0-9]*").unwrap();
+        let mut first = Sampler::new(&fsm, "az09", 8, 42);
+        let mut second = Sampler::new(&fsm, "az09", 8, 42);
+        for _ in 0..10 {
+            assert_eq!(first.accepted(), second.accepted());
+            assert_eq!(first.rejected(), second.rejected());
+        }
+    }
+
+    #[test]
+    fn test_too_short_for_any_string() {
+        let fsm = regex::compile("abc").unwrap();
+        let mut sampler = Sampler::new(&fsm, "abc", 2, 1);
+        assert_eq!(None, sampler.accepted());
+        assert_eq!(None, sampler.rejected());
+    }
+}
@@ -0,0 +1,493 @@
+use crate::builtins::Builtin;
+use crate::eval::{EvalContext, NumericPolicy};
+use crate::lexer::Keyword;
+use crate::parser::{Location, NodeType, ParseNode};
+use crate::value::Value;
+use crate::vm::Machine;
+use std::collections::HashSet;
+
+/// Simplifies 'node' for the constants and the settings of 'ctx', keeping the locations
+/// of the original nodes:
+///  - Operations on numbers and constants, and the math built-ins called with a number,
+///    are computed, unless they fail under the numeric policy, which is then left for
+///    the evaluation to report.
+///  - 'x * 1', '1 * x', 'x / 1', 'x ^ 1', 'x - 0' and 'x + -0' become 'x'. 'x + 0' and
+///    'x - x' are kept, as they differ from 'x' and 0 for '-0', infinities and NaN.
+///  - The assignments of a number that is never read are dropped.
+///
+/// The simplified node evaluates to the same value and leaves the same variables,
+/// assuming the variables used in operations hold numbers. Only when its evaluation
+/// fails may some of the dropped assignments be missing from the context.
+pub(crate) fn simplify(ctx: &EvalContext, node: &ParseNode) -> ParseNode {
+    use NodeType::*;
+    let mut defined = HashSet::new();
+    any_node(node, &mut |node| {
+        match &node.ntype {
+            Assignment(name, _) | Let(name, _) | Const(name, _) | For(name, _, _) => {
+                defined.insert(name.clone());
+            }
+            Function(name, params, _) => {
+                defined.insert(name.clone());
+                defined.extend(params.iter().cloned());
+            }
+            _ => {}
+        }
+        false
+    });
+
+    Simplifier {
+        ctx,
+        machine: ctx.machine(),
+        defined,
+    }
+    .simplify(node)
+}
+
+struct Simplifier<'c> {
+    ctx: &'c EvalContext,
+    machine: Machine,
+
+    /// - defined. The names given a value by the node, which may hide a built-in.
+    defined: HashSet<String>,
+}
+
+impl Simplifier<'_> {
+    fn simplify(&self, node: &ParseNode) -> ParseNode {
+        use NodeType::*;
+        let child = |node: &ParseNode| Box::new(self.simplify(node));
+        let nodes = |nodes: &[ParseNode]| nodes.iter().map(|node| self.simplify(node)).collect();
+        let ntype = match &node.ntype {
+            Identifier(identifier) => match self.ctx.constant(identifier) {
+                Some(Value::Number(num))
+                    if self
+                        .machine
+                        .numeric_policy
+                        .check(*num, node.location)
+                        .is_ok() =>
+                {
+                    Number(*num)
+                }
+                _ => Identifier(identifier.clone()),
+            },
+            Number(num) => Number(*num),
+            Quantity(num, unit) => Quantity(*num, unit.clone()),
+            Imaginary(num) => Imaginary(*num),
+            Conversion(value, unit) => Conversion(child(value), unit.clone()),
+            Bounds(lo, hi) => Bounds(child(lo), child(hi)),
+            List(values) => List(nodes(values)),
+            Record(fields) => Record(
+                fields
+                    .iter()
+                    .map(|(name, node)| (name.clone(), self.simplify(node)))
+                    .collect(),
+            ),
+            Index(list, index) => Index(child(list), child(index)),
+            Range(start, end) => Range(child(start), child(end)),
+            Field(record, name) => Field(child(record), name.clone()),
+            Function(name, params, body) => Function(name.clone(), params.clone(), child(body)),
+            Call(name, args) => Call(name.clone(), nodes(args)),
+            Sum(left, right) => Sum(child(left), child(right)),
+            Substraction(left, right) => Substraction(child(left), child(right)),
+            Multiplication(left, right) => Multiplication(child(left), child(right)),
+            Division(left, right) => Division(child(left), child(right)),
+            Power(left, right) => Power(child(left), child(right)),
+            Negation(value) => Negation(child(value)),
+            GreaterThan(left, right) => GreaterThan(child(left), child(right)),
+            GreaterThanOrEqual(left, right) => GreaterThanOrEqual(child(left), child(right)),
+            LessThan(left, right) => LessThan(child(left), child(right)),
+            LessThanOrEqual(left, right) => LessThanOrEqual(child(left), child(right)),
+            Equal(left, right) => Equal(child(left), child(right)),
+            NotEqual(left, right) => NotEqual(child(left), child(right)),
+            Assignment(identifier, value) => Assignment(identifier.clone(), child(value)),
+            Let(identifier, value) => Let(identifier.clone(), child(value)),
+            Const(identifier, value) => Const(identifier.clone(), child(value)),
+            Block(statements) => Block(self.drop_dead_assignments(nodes(statements), true)),
+            While(condition, body) => While(child(condition), child(body)),
+            For(variable, iterable, body) => For(variable.clone(), child(iterable), child(body)),
+            Root(statements) => Root(self.drop_dead_assignments(nodes(statements), false)),
+            Break => Break,
+            Continue => Continue,
+            Error => Error,
+        };
+
+        self.fold(ParseNode {
+            ntype,
+            location: node.location,
+        })
+    }
+
+    /// Folds a node whose children are already simplified.
+    fn fold(&self, node: ParseNode) -> ParseNode {
+        use NodeType::*;
+        let location = node.location;
+        let number = |num| {
+            Some(ParseNode {
+                ntype: Number(num),
+                location,
+            })
+        };
+        let folded = match &node.ntype {
+            Negation(value) => match value.ntype {
+                Number(num) => self
+                    .machine
+                    .numeric_policy
+                    .check(-num, location)
+                    .ok()
+                    .and_then(number),
+                _ => None,
+            },
+            Sum(left, right)
+            | Substraction(left, right)
+            | Multiplication(left, right)
+            | Division(left, right)
+            | Power(left, right)
+            | GreaterThan(left, right)
+            | GreaterThanOrEqual(left, right)
+            | LessThan(left, right)
+            | LessThanOrEqual(left, right)
+            | Equal(left, right)
+            | NotEqual(left, right) => match (&left.ntype, &right.ntype) {
+                (Number(left), Number(right)) => self
+                    .compute(&node.ntype, *left, *right, location)
+                    .and_then(number),
+                _ => self.identity(&node.ntype, left, right).cloned(),
+            },
+            Call(name, args) => match (self.math(name), args.as_slice()) {
+                (Some(function), [arg]) => match arg.ntype {
+                    Number(num) => self
+                        .machine
+                        .numeric_policy
+                        .check(function(num), location)
+                        .ok()
+                        .and_then(number),
+                    _ => None,
+                },
+                _ => None,
+            },
+            // The loop runs no iteration.
+            While(condition, _) if is_number(condition, 0.0) => number(0.0),
+            _ => None,
+        };
+
+        folded.unwrap_or(node)
+    }
+
+    fn compute(&self, ntype: &NodeType, left: f64, right: f64, location: Location) -> Option<f64> {
+        use NodeType::*;
+        let policy = self.machine.numeric_policy;
+        let truth = |res: bool| Ok(if res { 1.0 } else { 0.0 });
+        let res = match ntype {
+            Sum(..) => policy.check(left + right, location),
+            Substraction(..) => policy.check(left - right, location),
+            Multiplication(..) => policy.check(left * right, location),
+            Division(..) => policy.divide(left, right, location),
+            Power(..) => policy.check(left.powf(right), location),
+            GreaterThan(..) => truth(left > right),
+            GreaterThanOrEqual(..) => truth(left >= right),
+            LessThan(..) => truth(left < right),
+            LessThanOrEqual(..) => truth(left <= right),
+            Equal(..) => truth(self.machine.equality.equals(left, right)),
+            NotEqual(..) => truth(!self.machine.equality.equals(left, right)),
+            _ => panic!("{:?} is not a binary operation", ntype),
+        };
+
+        res.ok()
+    }
+
+    /// The operand an operation is equal to, for every number it may hold.
+    fn identity<'n>(
+        &self,
+        ntype: &NodeType,
+        left: &'n ParseNode,
+        right: &'n ParseNode,
+    ) -> Option<&'n ParseNode> {
+        use NodeType::*;
+        // 'x * 1' fails for an infinite 'x' when 'x' alone doesn't.
+        if self.machine.numeric_policy == NumericPolicy::RejectNonFinite {
+            return None;
+        }
+
+        let operand = match ntype {
+            Multiplication(..) if is_number(right, 1.0) => left,
+            Multiplication(..) if is_number(left, 1.0) => right,
+            Division(..) | Power(..) if is_number(right, 1.0) => left,
+            Substraction(..) if is_number(right, 0.0) => left,
+            Sum(..) if is_number(right, -0.0) => left,
+            Sum(..) if is_number(left, -0.0) => right,
+            _ => return None,
+        };
+
+        Some(operand).filter(|operand| self.is_numeric(operand))
+    }
+
+    /// The function of the math built-in 'name', unless a variable hides it.
+    fn math(&self, name: &str) -> Option<fn(f64) -> f64> {
+        if self.defined.contains(name) || self.ctx.global(name).is_some() {
+            return None;
+        }
+
+        Builtin::from_name(name).and_then(Builtin::math)
+    }
+
+    /// Whether 'node' is valued as a number, assuming the variables hold numbers.
+    fn is_numeric(&self, node: &ParseNode) -> bool {
+        use NodeType::*;
+        match &node.ntype {
+            Call(name, _) => self.math(name).is_some(),
+            Identifier(_)
+            | Number(_)
+            | Sum(..)
+            | Substraction(..)
+            | Multiplication(..)
+            | Division(..)
+            | Power(..)
+            | Negation(_)
+            | GreaterThan(..)
+            | GreaterThanOrEqual(..)
+            | LessThan(..)
+            | LessThanOrEqual(..)
+            | Equal(..)
+            | NotEqual(..) => true,
+            _ => false,
+        }
+    }
+
+    /// Drops the statements assigning a number to a variable that is assigned again
+    /// before being read, or bound by 'let' in a 'block' that ends first.
+    fn drop_dead_assignments(&self, statements: Vec<ParseNode>, block: bool) -> Vec<ParseNode> {
+        let last = statements.len().saturating_sub(1);
+        let dead: Vec<bool> = (0..statements.len())
+            .map(|index| index < last && self.is_dead(&statements[index..], block))
+            .collect();
+
+        statements
+            .into_iter()
+            .zip(dead)
+            .filter(|(_, dead)| !dead)
+            .map(|(statement, _)| statement)
+            .collect()
+    }
+
+    /// Whether the first of 'statements' is a dead assignment.
+    fn is_dead(&self, statements: &[ParseNode], block: bool) -> bool {
+        use NodeType::*;
+        let (identifier, declared) = match &statements[0].ntype {
+            Assignment(identifier, value) if matches!(value.ntype, Number(_)) => {
+                (identifier, false)
+            }
+            Let(identifier, value) if matches!(value.ntype, Number(_)) => (identifier, true),
+            _ => return false,
+        };
+        let local = declared && block;
+        // Assigning a reserved word or a constant is an error.
+        if Keyword::from_word(identifier).is_some() || self.ctx.constant(identifier).is_some() {
+            return false;
+        }
+
+        for statement in &statements[1..] {
+            // In a block, 'let' binds in the block while '=' updates the closest variable,
+            // so only a statement of the same kind binds the same variable again.
+            match &statement.ntype {
+                Assignment(target, value) | Let(target, value)
+                    if target == identifier
+                        && !mentions(value, identifier)
+                        && (!block || matches!(statement.ntype, Let(..)) == declared) =>
+                {
+                    return true
+                }
+                _ if mentions(statement, identifier) => return false,
+                // A call may read a global, and a jump skip the next assignments.
+                _ if !local
+                    && any_node(statement, &mut |node| {
+                        matches!(node.ntype, Call(..) | Break | Continue)
+                    }) =>
+                {
+                    return false
+                }
+                _ => {}
+            }
+        }
+
+        local
+    }
+}
+
+fn is_number(node: &ParseNode, value: f64) -> bool {
+    matches!(node.ntype, NodeType::Number(num) if num.to_bits() == value.to_bits())
+}
+
+/// Whether 'identifier' appears anywhere in 'node', read, assigned or declared.
+fn mentions(node: &ParseNode, identifier: &str) -> bool {
+    use NodeType::*;
+    any_node(node, &mut |node| match &node.ntype {
+        Identifier(name)
+        | Assignment(name, _)
+        | Let(name, _)
+        | Const(name, _)
+        | For(name, _, _)
+        | Call(name, _) => name == identifier,
+        Function(name, params, _) => name == identifier || params.iter().any(|p| p == identifier),
+        _ => false,
+    })
+}
+
+fn any_node(node: &ParseNode, predicate: &mut dyn FnMut(&ParseNode) -> bool) -> bool {
+    use NodeType::*;
+    if predicate(node) {
+        return true;
+    }
+
+    match &node.ntype {
+        List(nodes) | Call(_, nodes) | Block(nodes) | Root(nodes) => {
+            nodes.iter().any(|node| any_node(node, predicate))
+        }
+        Record(fields) => fields.iter().any(|(_, node)| any_node(node, predicate)),
+        Index(left, right)
+        | Range(left, right)
+        | Bounds(left, right)
+        | Sum(left, right)
+        | Substraction(left, right)
+        | Multiplication(left, right)
+        | Division(left, right)
+        | Power(left, right)
+        | GreaterThan(left, right)
+        | GreaterThanOrEqual(left, right)
+        | LessThan(left, right)
+        | LessThanOrEqual(left, right)
+        | Equal(left, right)
+        | NotEqual(left, right)
+        | While(left, right)
+        | For(_, left, right) => any_node(left, predicate) || any_node(right, predicate),
+        Field(child, _)
+        | Function(_, _, child)
+        | Negation(child)
+        | Conversion(child, _)
+        | Assignment(_, child)
+        | Let(_, child)
+        | Const(_, child) => any_node(child, predicate),
+        Identifier(_) | Number(_) | Quantity(..) | Imaginary(_) | Break | Continue | Error => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> ParseNode {
+        let tokens = Lexer::get_tokens(input).unwrap();
+        let (root, _) = Parser::new(&tokens).parse_partial();
+        root
+    }
+
+    fn assert_simplified(input: &str, expected: &str) {
+        let ctx = EvalContext::populated();
+        assert_eq!(
+            expected,
+            simplify(&ctx, &parse(input)).to_source(),
+            "{}",
+            input
+        );
+    }
+
+    #[test]
+    fn test_folding() {
+        assert_simplified("2 * PI", &(2.0 * std::f64::consts::PI).to_string());
+        assert_simplified("x * (1 + 2) ^ 2", "x * 9");
+        assert_simplified("2 * PI * r * 1", "6.283185307179586 * r");
+        assert_simplified("-(3 - 1) < 0", "1");
+        assert_simplified("0.1 + 0.2 == 0.3", "1");
+        assert_simplified("1 / 0", "INF");
+        assert_simplified("[1 + 1, { a: 2 * 3 }.a][x - 1]", "[2, { a: 6 }.a][x - 1]");
+        assert_simplified("fn f(a) { a * (2 + 2) }", "fn f(a) { a * 4 }");
+        assert_simplified("while 1 < 0 { x = 1 }", "0");
+    }
+
+    #[test]
+    fn test_identities() {
+        assert_simplified("x * 1 + 1 * y - z / 1", "x + y - z");
+        assert_simplified("(x + y) ^ (2 - 1) - 0", "x + y");
+        assert_simplified("x + 0", "x + 0");
+        assert_simplified("x - x", "x - x");
+        assert_simplified("x * 0", "x * 0");
+        assert_simplified("[x] * 1", "[x] * 1");
+        assert_simplified("len(xs) * 1", "len(xs) * 1");
+        assert_simplified("sqrt(x) * 1 + exp(0)", "sqrt(x) + 1");
+        assert_simplified("fn exp(x) { x }\nexp(0) * 1", "fn exp(x) { x }\nexp(0) * 1");
+    }
+
+    #[test]
+    fn test_policy() {
+        let mut ctx = EvalContext::populated();
+        ctx.set_numeric_policy(NumericPolicy::RejectNonFinite);
+        let simplified = simplify(&ctx, &parse("1 / 0 + x * 1 + 2 * 3"));
+        assert_eq!("1 / 0 + x * 1 + 6", simplified.to_source());
+
+        // 'INF' and its negation are left for the evaluation to reject.
+        let simplified = simplify(&ctx, &parse("-INF"));
+        match simplified.ntype {
+            NodeType::Root(nodes) => assert!(matches!(nodes[0].ntype, NodeType::Negation(_))),
+            ntype => panic!("Expected a root, got {:?}", ntype),
+        }
+    }
+
+    #[test]
+    fn test_dead_assignments() {
+        assert_simplified("x = 1\nx = 2\nx", "x = 2\nx");
+        assert_simplified("x = 1\nx = x + 1", "x = 1\nx = x + 1");
+        assert_simplified("x = 1\nx", "x = 1\nx");
+        assert_simplified("x = 1\nf()\nx = 2", "x = 1\nf()\nx = 2");
+        assert_simplified("x = y\nx = 2", "x = y\nx = 2");
+        assert_simplified("PI = 1\nPI = 2", "PI = 1\nPI = 2");
+        assert_simplified("{ let t = 2; x = 1; x = 3; t }", "{ let t = 2; x = 3; t }");
+        assert_simplified("{ let t = 2; let u = 1; 0 }", "{ 0 }");
+        assert_simplified("{ x = 1; let x = 2; x }", "{ x = 1; let x = 2; x }");
+        assert_simplified("{ let x = 1; let x = 2; x }", "{ let x = 2; x }");
+        assert_simplified(
+            "x = 5\n{ let x = 1; x = 2; x }\nx",
+            "x = 5\n{ let x = 1; x = 2; x }\nx",
+        );
+        assert_simplified(
+            "for i in 0..3 { y = 1; break; y = 2 }",
+            "for i in 0..3 { y = 1; break; y = 2 }",
+        );
+    }
+
+    #[test]
+    fn test_locations() {
+        let ctx = EvalContext::populated();
+        let simplified = simplify(&ctx, &parse("y * 1 + 2 * PI"));
+        let (left, right) = match simplified.ntype {
+            NodeType::Root(mut nodes) => match nodes.remove(0).ntype {
+                NodeType::Sum(left, right) => (left, right),
+                ntype => panic!("Expected a sum, got {:?}", ntype),
+            },
+            ntype => panic!("Expected a root, got {:?}", ntype),
+        };
+        assert_eq!(Location(0, 0), left.location);
+        assert_eq!(Location(0, 10), right.location);
+    }
+
+    #[test]
+    fn test_same_results() {
+        let inputs = [
+            "x = 3\ny = x * 1 + 2 ^ 3\ny",
+            "total = 0\ntotal = 1\nfor i in 0..4 { total += i * 1 }\ntotal",
+            "{ let a = 1; let a = 2; a * PI }",
+            "-(2 ^ 2) + (0 - 0) / 1",
+            "x = 5\n{ let x = 1; x = 2; x }\nx",
+        ];
+        for input in &inputs {
+            let root = parse(input);
+            let mut original = EvalContext::populated();
+            let mut simplified = EvalContext::populated();
+            let expected = original.eval(&root);
+            let res = simplified.eval(&simplify(&simplified, &root));
+            assert_eq!(expected, res, "{}", input);
+            for name in &["x", "y", "total"] {
+                assert_eq!(original.global(name), simplified.global(name), "{}", input);
+            }
+        }
+    }
+}
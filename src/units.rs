@@ -0,0 +1,442 @@
+use crate::eval::EvalError;
+use crate::lexer::Lexer;
+use crate::parser::{Location, Parser};
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
+
+/// The exponents of the base dimensions: length, mass, time, electric current,
+/// temperature, amount of substance and luminous intensity.
+type Dimension = [i32; 7];
+
+const LENGTH: Dimension = [1, 0, 0, 0, 0, 0, 0];
+const MASS: Dimension = [0, 1, 0, 0, 0, 0, 0];
+const TIME: Dimension = [0, 0, 1, 0, 0, 0, 0];
+const CURRENT: Dimension = [0, 0, 0, 1, 0, 0, 0];
+const TEMPERATURE: Dimension = [0, 0, 0, 0, 1, 0, 0];
+const AMOUNT: Dimension = [0, 0, 0, 0, 0, 1, 0];
+const LUMINOSITY: Dimension = [0, 0, 0, 0, 0, 0, 1];
+const AREA: Dimension = [2, 0, 0, 0, 0, 0, 0];
+const VOLUME: Dimension = [3, 0, 0, 0, 0, 0, 0];
+const SPEED: Dimension = [1, 0, -1, 0, 0, 0, 0];
+const FREQUENCY: Dimension = [0, 0, -1, 0, 0, 0, 0];
+const FORCE: Dimension = [1, 1, -2, 0, 0, 0, 0];
+const PRESSURE: Dimension = [-1, 1, -2, 0, 0, 0, 0];
+const ENERGY: Dimension = [2, 1, -2, 0, 0, 0, 0];
+const POWER: Dimension = [2, 1, -3, 0, 0, 0, 0];
+const CHARGE: Dimension = [0, 0, 1, 1, 0, 0, 0];
+const VOLTAGE: Dimension = [2, 1, -3, -1, 0, 0, 0];
+const RESISTANCE: Dimension = [2, 1, -3, -2, 0, 0, 0];
+
+/// The units known in formulas, with their value in SI base units.
+/// Temperatures are only in kelvins, whose zero is absolute.
+const UNITS: &[(&str, f64, Dimension)] = &[
+    ("m", 1.0, LENGTH),
+    ("km", 1e3, LENGTH),
+    ("cm", 1e-2, LENGTH),
+    ("mm", 1e-3, LENGTH),
+    ("um", 1e-6, LENGTH),
+    ("nm", 1e-9, LENGTH),
+    ("inch", 0.0254, LENGTH),
+    ("ft", 0.3048, LENGTH),
+    ("yd", 0.9144, LENGTH),
+    ("mi", 1609.344, LENGTH),
+    ("kg", 1.0, MASS),
+    ("g", 1e-3, MASS),
+    ("mg", 1e-6, MASS),
+    ("tonne", 1e3, MASS),
+    ("oz", 0.028349523125, MASS),
+    ("lb", 0.45359237, MASS),
+    ("s", 1.0, TIME),
+    ("ms", 1e-3, TIME),
+    ("us", 1e-6, TIME),
+    ("min", 60.0, TIME),
+    ("h", 3600.0, TIME),
+    ("day", 86400.0, TIME),
+    ("A", 1.0, CURRENT),
+    ("mA", 1e-3, CURRENT),
+    ("K", 1.0, TEMPERATURE),
+    ("mol", 1.0, AMOUNT),
+    ("cd", 1.0, LUMINOSITY),
+    ("ha", 1e4, AREA),
+    ("acre", 4046.8564224, AREA),
+    ("L", 1e-3, VOLUME),
+    ("mL", 1e-6, VOLUME),
+    ("gal", 3.785411784e-3, VOLUME),
+    ("mph", 0.44704, SPEED),
+    ("Hz", 1.0, FREQUENCY),
+    ("kHz", 1e3, FREQUENCY),
+    ("MHz", 1e6, FREQUENCY),
+    ("N", 1.0, FORCE),
+    ("kN", 1e3, FORCE),
+    ("lbf", 4.4482216152605, FORCE),
+    ("Pa", 1.0, PRESSURE),
+    ("kPa", 1e3, PRESSURE),
+    ("bar", 1e5, PRESSURE),
+    ("psi", 6894.757293168361, PRESSURE),
+    ("J", 1.0, ENERGY),
+    ("kJ", 1e3, ENERGY),
+    ("cal", 4.184, ENERGY),
+    ("kWh", 3.6e6, ENERGY),
+    ("W", 1.0, POWER),
+    ("kW", 1e3, POWER),
+    ("hp", 745.6998715822702, POWER),
+    ("C", 1.0, CHARGE),
+    ("V", 1.0, VOLTAGE),
+    ("ohm", 1.0, RESISTANCE),
+];
+
+/// A product of powers of the units of the table, like 'kg*m/s^2'.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Unit {
+    /// - terms. The names of the units with their non-null exponents, in order of appearance.
+    terms: Vec<(&'static str, i32)>,
+}
+
+impl Unit {
+    /// Parses a unit written like in the formulas, without spaces.
+    pub fn parse(text: &str) -> Option<Unit> {
+        let tokens = Lexer::get_tokens(text).ok()?;
+        Parser::new(&tokens).parse_whole_unit()
+    }
+
+    /// The unit of the table called 'name'.
+    pub fn named(name: &str) -> Option<Unit> {
+        UNITS
+            .iter()
+            .find(|(unit, ..)| *unit == name)
+            .map(|(unit, ..)| Unit {
+                terms: vec![(unit, 1)],
+            })
+    }
+
+    /// The unit of plain numbers.
+    pub fn none() -> Unit {
+        Unit { terms: vec![] }
+    }
+
+    /// Whether quantities in this unit are plain numbers, like for 'km/m'.
+    pub fn is_dimensionless(&self) -> bool {
+        self.dimension() == [0; 7]
+    }
+
+    /// The value of the unit in SI base units.
+    fn factor(&self) -> f64 {
+        self.terms
+            .iter()
+            .map(|&(name, exponent)| definition(name).1.powi(exponent))
+            .product()
+    }
+
+    /// The exponents of the base units, which fit an 'i32' as checked by 'from_terms'.
+    fn dimension(&self) -> Dimension {
+        let mut dimension = [0; 7];
+        for &(name, exponent) in &self.terms {
+            for (total, base) in dimension.iter_mut().zip(definition(name).2.iter()) {
+                *total += base * exponent;
+            }
+        }
+
+        dimension
+    }
+
+    /// The unit of 'terms' without the null exponents, or the first term whose exponent,
+    /// or one of the exponents of the dimension it adds to, doesn't fit an 'i32'.
+    fn from_terms(terms: Vec<(&'static str, i64)>) -> Result<Unit, (&'static str, i64)> {
+        let mut dimension: Dimension = [0; 7];
+        let mut checked = vec![];
+        for (name, exponent) in terms.into_iter().filter(|&(_, exponent)| exponent != 0) {
+            let fits = i32::try_from(exponent).ok().filter(|&exponent| {
+                dimension
+                    .iter_mut()
+                    .zip(definition(name).2.iter())
+                    .all(|(total, base)| {
+                        match base
+                            .checked_mul(exponent)
+                            .and_then(|n| n.checked_add(*total))
+                        {
+                            Some(sum) => {
+                                *total = sum;
+                                true
+                            }
+                            None => false,
+                        }
+                    })
+            });
+            match fits {
+                Some(exponent) => checked.push((name, exponent)),
+                None => return Err((name, exponent)),
+            }
+        }
+
+        Ok(Unit { terms: checked })
+    }
+
+    /// The product of the units, or their quotient when 'sign' is -1. Fails with the
+    /// term of the product whose exponent overflows, see 'from_terms'.
+    pub(crate) fn multiply(&self, other: &Unit, sign: i32) -> Result<Unit, (&'static str, i64)> {
+        let mut terms: Vec<_> = self
+            .terms
+            .iter()
+            .map(|&(name, exponent)| (name, i64::from(exponent)))
+            .collect();
+        for &(name, exponent) in &other.terms {
+            let exponent = i64::from(sign) * i64::from(exponent);
+            match terms.iter_mut().find(|(other, _)| *other == name) {
+                Some(term) => term.1 += exponent,
+                None => terms.push((name, exponent)),
+            }
+        }
+
+        Unit::from_terms(terms)
+    }
+
+    /// The unit raised to 'exponent', if its exponents stay integers like for 'sqrt(m^2)'
+    /// and fit an 'i32'.
+    pub(crate) fn power(&self, exponent: f64) -> Option<Unit> {
+        let terms = self
+            .terms
+            .iter()
+            .map(|&(name, power)| {
+                let power = power as f64 * exponent;
+                if power.fract() == 0.0 && power.abs() <= i32::MAX as f64 {
+                    Some((name, power as i64))
+                } else {
+                    None
+                }
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Unit::from_terms(terms).ok()
+    }
+}
+
+fn definition(name: &str) -> &'static (&'static str, f64, Dimension) {
+    UNITS
+        .iter()
+        .find(|(unit, ..)| *unit == name)
+        .unwrap_or_else(|| panic!("Unknown unit '{}'", name))
+}
+
+/// Writes the unit like 'kg*m/s^2', or '1' for plain numbers.
+impl Display for Unit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let write_term = |f: &mut Formatter<'_>, name, exponent: i32| match exponent.abs() {
+            1 => write!(f, "{}", name),
+            exponent => write!(f, "{}^{}", name, exponent),
+        };
+
+        let mut numerator = self.terms.iter().filter(|(_, exponent)| *exponent > 0);
+        match numerator.next() {
+            Some(&(name, exponent)) => write_term(f, name, exponent)?,
+            None => write!(f, "1")?,
+        }
+        for &(name, exponent) in numerator {
+            write!(f, "*")?;
+            write_term(f, name, exponent)?;
+        }
+        for &(name, exponent) in self.terms.iter().filter(|(_, exponent)| *exponent < 0) {
+            write!(f, "/")?;
+            write_term(f, name, exponent)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A number of some unit, evaluated from literals like '9.81 m/s^2'.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Quantity {
+    pub value: f64,
+    pub unit: Unit,
+}
+
+impl Quantity {
+    pub fn new(value: f64, unit: Unit) -> Self {
+        Quantity { value, unit }
+    }
+
+    /// The same quantity in 'unit', if it has the same dimension.
+    pub fn convert(&self, unit: &Unit) -> Option<Quantity> {
+        if self.unit.dimension() != unit.dimension() {
+            return None;
+        }
+
+        let value = self.value * (self.unit.factor() / unit.factor());
+        Some(Quantity::new(value, unit.clone()))
+    }
+
+    /// The other quantity in the unit of this one, for the operation at 'location'.
+    pub(crate) fn align(&self, other: &Quantity, location: Location) -> Result<f64, EvalError> {
+        other
+            .convert(&self.unit)
+            .map(|other| other.value)
+            .ok_or_else(|| mismatch(&self.unit, &other.unit, location))
+    }
+
+    pub(crate) fn add(
+        left: &Quantity,
+        right: &Quantity,
+        location: Location,
+    ) -> Result<Quantity, EvalError> {
+        let right = left.align(right, location)?;
+        Ok(Quantity::new(left.value + right, left.unit.clone()))
+    }
+
+    pub(crate) fn substract(
+        left: &Quantity,
+        right: &Quantity,
+        location: Location,
+    ) -> Result<Quantity, EvalError> {
+        Quantity::add(left, &right.negate(), location)
+    }
+
+    pub(crate) fn multiply(
+        left: &Quantity,
+        right: &Quantity,
+        location: Location,
+    ) -> Result<Quantity, EvalError> {
+        let unit = product(&left.unit, &right.unit, 1, location)?;
+        Ok(Quantity::new(left.value * right.value, unit))
+    }
+
+    pub(crate) fn divide(
+        left: &Quantity,
+        right: &Quantity,
+        location: Location,
+    ) -> Result<Quantity, EvalError> {
+        let unit = product(&left.unit, &right.unit, -1, location)?;
+        Ok(Quantity::new(left.value / right.value, unit))
+    }
+
+    /// Raises to a plain number, which keeps the exponents of the unit integers.
+    pub(crate) fn power(
+        base: &Quantity,
+        exponent: &Quantity,
+        location: Location,
+    ) -> Result<Quantity, EvalError> {
+        if !exponent.unit.is_dimensionless() {
+            return Err(mismatch(&Unit::none(), &exponent.unit, location));
+        }
+
+        let exponent = exponent.value * exponent.unit.factor();
+        let unit = base.unit.power(exponent).ok_or_else(|| {
+            EvalError::InvalidUnitPower(base.unit.to_string(), exponent, location)
+        })?;
+        Ok(Quantity::new(base.value.powf(exponent), unit))
+    }
+
+    pub(crate) fn negate(&self) -> Quantity {
+        Quantity::new(-self.value, self.unit.clone())
+    }
+}
+
+/// The unit of a product or quotient, an error when one of its exponents overflows.
+fn product(left: &Unit, right: &Unit, sign: i32, location: Location) -> Result<Unit, EvalError> {
+    left.multiply(right, sign).map_err(|(name, exponent)| {
+        EvalError::InvalidUnitPower(name.to_string(), exponent as f64, location)
+    })
+}
+
+/// The error of an operation between quantities of different dimensions.
+pub(crate) fn mismatch(left: &Unit, right: &Unit, location: Location) -> EvalError {
+    EvalError::IncompatibleUnits(left.to_string(), right.to_string(), location)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(text: &str) -> Unit {
+        Unit::parse(text).unwrap_or_else(|| panic!("'{}' should be a unit", text))
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("kg*m/s^2", unit("kg*m/s^2").to_string());
+        assert_eq!("m^2", unit("m*m").to_string());
+        assert_eq!("1/s", unit("Hz/Hz/s").to_string());
+        let speed = unit("m*s^-2").multiply(&unit("s"), 1);
+        assert_eq!("m/s", speed.unwrap().to_string());
+        assert_eq!(None, Unit::parse("m * s"));
+        assert_eq!(None, Unit::parse("parsec"));
+        assert_eq!(None, Unit::parse("m^0.5"));
+        assert_eq!(None, Unit::parse("m^99999999999"));
+    }
+
+    #[test]
+    fn test_dimensions() {
+        assert_eq!(unit("N").dimension(), unit("kg*m/s^2").dimension());
+        assert_eq!(unit("J").dimension(), unit("N*m").dimension());
+        // The exponents of 'N', but not those of its dimension, fit an 'i32'.
+        assert_eq!(None, unit("N").power(2e9));
+        assert_eq!(
+            Err(("m", 4_000_000_000)),
+            unit("m^2")
+                .power(1e9)
+                .unwrap()
+                .multiply(&unit("m^2").power(1e9).unwrap(), 1)
+        );
+        assert!(unit("km/m").is_dimensionless());
+        assert!(!unit("m/s").is_dimensionless());
+        assert_eq!(1000.0, unit("km/m").factor());
+    }
+
+    #[test]
+    fn test_conversions() {
+        let speed = Quantity::new(36.0, unit("km/h"));
+        assert_eq!(Some(10.0), speed.convert(&unit("m/s")).map(|q| q.value));
+        assert_eq!(None, speed.convert(&unit("m")));
+
+        let length = Quantity::new(1.0, unit("mi"));
+        let res = length.convert(&unit("ft")).unwrap();
+        assert!((res.value - 5280.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_operations() {
+        let location = Location(0, 0);
+        let km = Quantity::new(1.5, unit("km"));
+        let m = Quantity::new(500.0, unit("m"));
+        let s = Quantity::new(4.0, unit("s"));
+        assert_eq!(
+            Ok(Quantity::new(2.0, unit("km"))),
+            Quantity::add(&km, &m, location)
+        );
+        assert_eq!(
+            Ok(Quantity::new(1000.0, unit("m"))),
+            Quantity::substract(&m, &Quantity::new(-0.5, unit("km")), location)
+        );
+        assert_eq!(
+            Err(EvalError::IncompatibleUnits(
+                "km".to_string(),
+                "s".to_string(),
+                location
+            )),
+            Quantity::add(&km, &s, location)
+        );
+        assert_eq!(
+            Ok(Quantity::new(125.0, unit("m/s"))),
+            Quantity::divide(&m, &s, location)
+        );
+
+        let area = Quantity::multiply(&m, &m, location).unwrap();
+        let two = Quantity::new(2.0, Unit::none());
+        assert_eq!("m^2", area.unit.to_string());
+        assert_eq!(
+            Ok(Quantity::new(500.0, unit("m"))),
+            Quantity::power(&area, &Quantity::new(0.5, Unit::none()), location)
+        );
+        assert_eq!(
+            Err(EvalError::InvalidUnitPower("m".to_string(), 0.5, location)),
+            Quantity::power(&m, &Quantity::new(0.5, Unit::none()), location)
+        );
+        assert_eq!(
+            Err(EvalError::IncompatibleUnits(
+                "1".to_string(),
+                "s".to_string(),
+                location
+            )),
+            Quantity::power(&two, &s, location)
+        );
+    }
+}
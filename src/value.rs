@@ -0,0 +1,165 @@
+use crate::builtins::Builtin;
+use crate::complex::Complex;
+use crate::dual::Dual;
+use crate::interval::Interval;
+use crate::parser::ParseNode;
+use crate::units::{Quantity, Unit};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+/// A function defined with 'fn'.
+#[derive(PartialEq, Debug)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: ParseNode,
+}
+
+/// The result of an evaluation.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Number(f64),
+    /// Lists are immutable, so they are shared instead of copied.
+    List(Rc<Vec<Value>>),
+    /// Records are immutable as well, their fields are sorted by name.
+    Record(Rc<BTreeMap<String, Value>>),
+    Function(Rc<Function>),
+    Builtin(Builtin),
+    /// A number carrying its derivatives, only seen while evaluating a gradient.
+    Dual(Rc<Dual>),
+    /// The bounds of a number, see 'EvalContext::bind'.
+    Interval(Interval),
+    /// A number with a unit of measurement, never dimensionless.
+    Quantity(Rc<Quantity>),
+    /// A complex number, made by an imaginary literal like '2i'.
+    Complex(Complex),
+}
+
+impl Value {
+    pub fn list(values: Vec<Value>) -> Self {
+        Value::List(Rc::new(values))
+    }
+
+    /// Builds a record from its fields, like 'Value::record(vec![("qty", 3.0.into())])'.
+    pub fn record<S: Into<String>>(fields: impl IntoIterator<Item = (S, Value)>) -> Self {
+        Value::Record(Rc::new(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name.into(), value))
+                .collect(),
+        ))
+    }
+
+    /// Names the type of the value in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::List(_) => "list",
+            Value::Record(_) => "record",
+            Value::Function(_) | Value::Builtin(_) => "function",
+            Value::Dual(_) => "dual number",
+            Value::Interval(_) => "interval",
+            Value::Quantity(_) => "quantity",
+            Value::Complex(_) => "complex number",
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(num: f64) -> Self {
+        Value::Number(num)
+    }
+}
+
+/// A dual number without derivatives is a plain number.
+impl From<Dual> for Value {
+    fn from(dual: Dual) -> Self {
+        if dual.derivatives.is_empty() {
+            Value::Number(dual.value)
+        } else {
+            Value::Dual(Rc::new(dual))
+        }
+    }
+}
+
+impl From<Interval> for Value {
+    fn from(interval: Interval) -> Self {
+        Value::Interval(interval)
+    }
+}
+
+/// A quantity without dimension, like '3 km/m', is a plain number.
+impl From<Quantity> for Value {
+    fn from(quantity: Quantity) -> Self {
+        if quantity.unit.is_dimensionless() {
+            let unit = Unit::none();
+            Value::Number(quantity.convert(&unit).unwrap().value)
+        } else {
+            Value::Quantity(Rc::new(quantity))
+        }
+    }
+}
+
+/// A complex number stays one even when it is real, so that 'sqrt' of it is complex.
+impl From<Complex> for Value {
+    fn from(num: Complex) -> Self {
+        Value::Complex(num)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Number(num) => write!(f, "{}", num),
+            Value::List(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Value::Record(fields) => {
+                write!(f, "{{")?;
+                for (index, (name, value)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, " {}: {}", name, value)?;
+                }
+                write!(f, " }}")
+            }
+            Value::Function(function) => write!(f, "<fn {}>", function.name),
+            Value::Builtin(builtin) => write!(f, "<built-in {}>", builtin.name()),
+            Value::Dual(dual) => write!(f, "dual({}, {:?})", dual.value, dual.derivatives),
+            Value::Interval(interval) => write!(f, "interval({}, {})", interval.lo, interval.hi),
+            Value::Quantity(quantity) => write!(f, "{} {}", quantity.value, quantity.unit),
+            Value::Complex(num) => write!(f, "{}", num),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let nested = Value::list(vec![
+            Value::Number(1.0),
+            Value::list(vec![Value::Number(2.5), Value::Number(-3.0)]),
+            Value::list(vec![]),
+        ]);
+        assert_eq!("[1, [2.5, -3], []]", nested.to_string());
+        assert_eq!("<built-in avg>", Value::Builtin(Builtin::Avg).to_string());
+
+        let order = Value::record(vec![
+            ("qty", Value::Number(3.0)),
+            ("item", Value::record(vec![("price", Value::Number(2.5))])),
+        ]);
+        assert_eq!("{ item: { price: 2.5 }, qty: 3 }", order.to_string());
+    }
+}
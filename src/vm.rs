@@ -0,0 +1,294 @@
+use crate::compiler::{Instruction, Program};
+use crate::equality::EqualityMode;
+use crate::eval::{EvalContext, EvalError, NumericPolicy};
+use crate::parser::Location;
+use crate::value::Value;
+
+/// The settings of the stack machine, the ones of the 'EvalContext' it stands for.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Machine {
+    pub(crate) numeric_policy: NumericPolicy,
+    pub(crate) equality: EqualityMode,
+
+    /// - iteration_budget. The iterations allowed to the loops of a single run.
+    pub(crate) iteration_budget: usize,
+}
+
+/// Runs 'program' on a stack of numbers. Its globals are read from 'ctx' before the run,
+/// and the ones it sets are bound in 'ctx' afterwards, even if the run fails.
+pub(crate) fn run(ctx: &mut EvalContext, program: &Program) -> Result<Value, EvalError> {
+    let mut slots = vec![0.0; program.slots];
+    let mut defined = vec![false; program.slots];
+    for global in &program.globals {
+        match ctx.global(&global.name) {
+            Some(Value::Number(num)) => {
+                slots[global.slot] = *num;
+                defined[global.slot] = true;
+            }
+            Some(val) => {
+                return Err(EvalError::TypeMismatch(
+                    "number",
+                    val.type_name(),
+                    global.location,
+                ))
+            }
+            None => {}
+        }
+    }
+
+    let res = execute(ctx.machine(), program, &mut slots, &mut defined);
+    for global in &program.globals {
+        if global.assigned && defined[global.slot] {
+            ctx.bind(&global.name, slots[global.slot]);
+        }
+    }
+
+    res.map(Value::Number)
+}
+
+/// Runs 'program' from its first instruction, with the slots of the globals
+/// already 'defined' set to their values.
+pub(crate) fn execute(
+    machine: Machine,
+    program: &Program,
+    slots: &mut [f64],
+    defined: &mut [bool],
+) -> Result<f64, EvalError> {
+    use Instruction::*;
+    let mut stack: Vec<f64> = Vec::with_capacity(16);
+    let mut pc = 0;
+    let mut iterations = 0;
+    while let Some(instruction) = program.instructions.get(pc) {
+        let location = program.locations[pc];
+        pc += 1;
+        match *instruction {
+            Constant(num) => stack.push(machine.numeric_policy.check(num, location)?),
+            Load(slot) => stack.push(slots[slot]),
+            LoadGlobal(slot) if defined[slot] => {
+                stack.push(machine.numeric_policy.check(slots[slot], location)?)
+            }
+            LoadGlobal(slot) => {
+                let global = program
+                    .globals
+                    .iter()
+                    .find(|global| global.slot == slot)
+                    .unwrap();
+                return Err(EvalError::SymbolNotFound(global.name.clone(), location));
+            }
+            Store(slot) => {
+                slots[slot] = *stack.last().unwrap();
+                defined[slot] = true;
+            }
+            Pop => {
+                stack.pop();
+            }
+            Discard(count) => stack.truncate(stack.len() - count),
+            Negate => {
+                let val = stack.pop().unwrap();
+                stack.push(machine.numeric_policy.check(-val, location)?);
+            }
+            Jump(target) => pc = target,
+            JumpIfZero(target) => {
+                if stack.pop().unwrap() == 0.0 {
+                    pc = target;
+                }
+            }
+            Iterate => {
+                iterations += 1;
+                if iterations > machine.iteration_budget {
+                    return Err(EvalError::IterationBudgetExceeded(
+                        machine.iteration_budget,
+                        location,
+                    ));
+                }
+            }
+            Increment(slot) => slots[slot] += 1.0,
+            CheckRange(start, end) => EvalContext::check_range(slots[start], slots[end], location)?,
+            Choose(reference, global, local) => {
+                slots[reference] = if defined[global] { global } else { local } as f64
+            }
+            LoadReference(reference) => stack.push(slots[slots[reference] as usize]),
+            StoreReference(reference) => {
+                let slot = slots[reference] as usize;
+                slots[slot] = *stack.last().unwrap();
+                defined[slot] = true;
+            }
+            Fail(error) => return Err(program.errors[error].clone()),
+            _ => {
+                let right = stack.pop().unwrap();
+                let left = stack.pop().unwrap();
+                stack.push(binary(machine, *instruction, left, right, location)?);
+            }
+        }
+    }
+
+    Ok(stack.pop().unwrap())
+}
+
+fn binary(
+    machine: Machine,
+    instruction: Instruction,
+    left: f64,
+    right: f64,
+    location: Location,
+) -> Result<f64, EvalError> {
+    use Instruction::*;
+    let policy = machine.numeric_policy;
+    let truth = |res: bool| if res { 1.0 } else { 0.0 };
+    Ok(match instruction {
+        Add => policy.check(left + right, location)?,
+        Substract => policy.check(left - right, location)?,
+        Multiply => policy.check(left * right, location)?,
+        Divide => policy.divide(left, right, location)?,
+        Power => policy.check(left.powf(right), location)?,
+        GreaterThan => truth(left > right),
+        GreaterThanOrEqual => truth(left >= right),
+        LessThan => truth(left < right),
+        LessThanOrEqual => truth(left <= right),
+        Equal => truth(machine.equality.equals(left, right)),
+        NotEqual => truth(!machine.equality.equals(left, right)),
+        _ => panic!("{:?} is not a binary operation", instruction),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::NumericPolicy;
+    use crate::lexer::Lexer;
+    use crate::parser::{ParseNode, Parser};
+
+    fn parse(input: &str) -> ParseNode {
+        let tokens = Lexer::get_tokens(input).unwrap();
+        let (root, _) = Parser::new(&tokens).parse_partial();
+        root
+    }
+
+    /// Checks that the compiled program and the tree-walker agree on 'input',
+    /// and on the variables they leave behind.
+    fn assert_same(input: &str) {
+        assert_same_after_binding(input, &[]);
+    }
+
+    /// Like 'assert_same', binding the 'globals' once the program is compiled.
+    fn assert_same_after_binding(input: &str, globals: &[(&str, f64)]) {
+        let root = parse(input);
+        let mut walker = EvalContext::populated();
+        walker.bind("x", 3.0);
+        let mut machine = EvalContext::populated();
+        machine.bind("x", 3.0);
+
+        let program = machine.compile(&root).unwrap();
+        for &(name, num) in globals {
+            walker.bind(name, num);
+            machine.bind(name, num);
+        }
+        let expected = walker.eval(&root);
+        let res = machine.run(&program);
+        match (&expected, &res) {
+            (Ok(Value::Number(a)), Ok(Value::Number(b))) if a.is_nan() => {
+                assert!(b.is_nan(), "{}", input)
+            }
+            _ => assert_eq!(expected, res, "{}", input),
+        }
+        for name in &["x", "y", "total", "n"] {
+            assert_eq!(walker.global(name), machine.global(name), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_same_results() {
+        let inputs = [
+            "1 + 2 * 3 - 4 / 5",
+            "-2 ^ 2 + x ** 0.5",
+            "x > 2; x >= 3; x < 2; x <= 2; x == 3; x != 3",
+            "y = x * 2; y += 1",
+            "{ let x = 10; x * 2 } + x",
+            "{ y = 1 }\ny",
+            "total = 0\nfor i in 0..10 { total += i * x }\ntotal",
+            "n = 0\nwhile n < 100 { n += 7 }",
+            "n = 0\nfor i in 0..10 { n = n + 1; if_ = { break }; n = 100 }\nn",
+            "n = 0\nfor i in 0..10 { n += 1; 2 + { continue }; n = 100 }\nn",
+            "n = 0\nfor i in 0..3 { for j in 0..3 { n += 1; break } }\nn",
+            "n = 0; for i in 0..3 { while ({ break }) { 1 }; n = n + 1 }; n",
+            "while ({ break }) { n = 1 }",
+            "for i in 0..3 { i }\ni",
+            "for i in 0.5..3 { i }",
+            "for i in 0..1e300 { i }",
+            "while 0 { 1 }",
+            "1 / 0; 0 / 0",
+            "PI * x ^ 2",
+            "2 * {}",
+            "1\nbreak",
+            "undefined + 1",
+            "PI = 3",
+        ];
+        for input in &inputs {
+            assert_same(input);
+        }
+    }
+
+    #[test]
+    fn test_same_results_after_binding() {
+        let inputs = [
+            "{ y = 5 }; y",
+            "{ y = 5; y * 2 } + y",
+            "for i in 0..3 { n = i }; n",
+            "{ { y = 1 }; y }",
+        ];
+        for input in &inputs {
+            assert_same(input);
+            assert_same_after_binding(input, &[("y", 1.0), ("n", 7.0)]);
+        }
+    }
+
+    #[test]
+    fn test_policies_and_budget() {
+        let root = parse("x = 0\nfor i in 0..10 { x += 1 / i }");
+        let mut ctx = EvalContext::populated();
+        ctx.set_numeric_policy(NumericPolicy::RejectDivisionByZero);
+        let program = ctx.compile(&root).unwrap();
+        assert_eq!(
+            Err(EvalError::DivisionByZero(Location(1, 24))),
+            ctx.run(&program)
+        );
+
+        ctx.set_numeric_policy(NumericPolicy::Ieee);
+        ctx.set_iteration_budget(5);
+        assert_eq!(
+            Err(EvalError::IterationBudgetExceeded(5, Location(1, 0))),
+            ctx.run(&program)
+        );
+
+        let root = parse("n = 0\nwhile ({ n += 1; continue }) { 1 }");
+        let program = ctx.compile(&root).unwrap();
+        assert_eq!(
+            Err(EvalError::IterationBudgetExceeded(5, Location(1, 0))),
+            ctx.run(&program)
+        );
+        assert_eq!(Some(&Value::Number(6.0)), ctx.global("n"));
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let ctx = EvalContext::populated();
+        assert!(matches!(
+            ctx.compile(&parse("[1, 2][0]")),
+            Err(EvalError::Unimplemented(_))
+        ));
+        assert!(matches!(
+            ctx.compile(&parse("for i in [1] { i }")),
+            Err(EvalError::Unimplemented(_))
+        ));
+    }
+
+    #[test]
+    fn test_disassembly() {
+        let ctx = EvalContext::populated();
+        let program = ctx.compile(&parse("y = -x")).unwrap();
+        assert_eq!(
+            "global x = #0\nglobal y = #1\n   0 LoadGlobal(0)\n   1 Negate\n   2 Store(1)\n",
+            program.to_string()
+        );
+    }
+}